@@ -0,0 +1,573 @@
+/* Cellular Automata Derive
+
+Derive macros that remove the boilerplate needed for each new model state.
+
+`#[derive(CellState)]` implements `CellStateBase` for a struct and generates a
+python wrapper named `{Struct}Py` that implements `PyWrapperBase` and
+`CellStatePyBase`. The struct must have an `id: CellIndex` and a
+`position: Point<f64>` field. In python the id is an int and the position an
+`(x, y)` tuple.
+
+`#[derive(GlobalState)]` implements `GlobalStateBase` and generates the
+equivalent `GlobalStatePyBase` wrapper. The struct must also implement Default.
+
+Every other field gets a typed getter and setter so its type must convert to
+and from python. Use `#[cell_state(skip)]` or `#[global_state(skip)]` to hide a
+field; hidden fields are set to their default by the wrapper constructor.
+
+The wrappers support pickle and copy, compare equal when their states are
+equal and have a repr of their fields, as the hand written example wrappers
+do. The struct must derive `PartialEq`, `Serialize` and `Deserialize`.
+Like the hand written wrappers they hash every field that is not skipped with
+`HashField`, so the field types must implement it. Set `#[cell_state(hash)]`
+or `#[global_state(hash)]` to hash the state with its `Hash` implementation
+instead.
+
+Both derives also implement `Introspect` for the fields that are not skipped
+and have an int, float, bool or `String` type, named as written e.g. `u32` or
+`String`. Fields of other types are not described. `CellFields` is not derived
+so implement `new_at` by hand to create cells with the generic loaders.
+
+Pickle finds a class by its module so set the name of the python module the
+wrapper is added to with `#[cell_state(module = "my_model")]` or
+`#[global_state(module = "my_model")]` to pickle it.
+
+Cell states are not randomized unless a function is given with
+`#[cell_state(randomize = "path::to::fn")]` which takes `&Self` and returns `Self`.
+
+The generated code refers to the `cellular_automata`, `pyo3` and `geo` crates
+so they must be dependencies of the model crate.
+*/
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::format_ident;
+use quote::quote;
+use syn::parse_macro_input;
+use syn::spanned::Spanned;
+use syn::Attribute;
+use syn::Data;
+use syn::DeriveInput;
+use syn::Error;
+use syn::Fields;
+use syn::Ident;
+use syn::Lit;
+use syn::LitStr;
+use syn::Meta;
+use syn::NestedMeta;
+use syn::Path;
+use syn::Type;
+
+#[proc_macro_derive(CellState, attributes(cell_state))]
+pub fn derive_cell_state(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_cell_state(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(GlobalState, attributes(global_state))]
+pub fn derive_global_state(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_global_state(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[derive(Debug, PartialEq)]
+enum FieldKind {
+    Id,
+    Position,
+    Value,
+    Skip,
+}
+
+struct StateField {
+    ident: Ident,
+    ty: Type,
+    kind: FieldKind,
+}
+
+/// Options set in the struct or field attributes e.g. `#[cell_state(skip)]`
+#[derive(Default)]
+struct StateOptions {
+    skip: bool,
+    hash: bool,
+    module: Option<LitStr>,
+    randomize: Option<Path>,
+}
+
+fn parse_options(attrs: &[Attribute], attr_name: &str) -> Result<StateOptions, Error> {
+    let mut options = StateOptions::default();
+    for attr in attrs.iter().filter(|a| a.path.is_ident(attr_name)) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    format!("Expected #[{}(...)]", attr_name),
+                ))
+            }
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => {
+                    options.skip = true;
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("hash") => {
+                    options.hash = true;
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("module") => {
+                    match &nv.lit {
+                        Lit::Str(s) => options.module = Some(s.clone()),
+                        lit => return Err(Error::new(lit.span(), "Expected a module name")),
+                    }
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("randomize") => {
+                    match &nv.lit {
+                        Lit::Str(s) => options.randomize = Some(s.parse()?),
+                        lit => return Err(Error::new(lit.span(), "Expected a function path")),
+                    }
+                }
+                _ => return Err(Error::new(nested.span(), "Unknown state option")),
+            }
+        }
+    }
+    Ok(options)
+}
+
+fn parse_fields(
+    input: &DeriveInput,
+    attr_name: &str,
+    is_cell: bool,
+) -> Result<Vec<StateField>, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.ident.span(),
+                    "State derive requires named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "State derive only supports structs",
+            ))
+        }
+    };
+    fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.clone().expect("named field");
+            let options = parse_options(&field.attrs, attr_name)?;
+            let kind = match ident.to_string().as_str() {
+                "id" if is_cell => FieldKind::Id,
+                "position" if is_cell => FieldKind::Position,
+                _ if options.skip => FieldKind::Skip,
+                _ => FieldKind::Value,
+            };
+            Ok(StateField {
+                ident,
+                ty: field.ty.clone(),
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// Generate the wrapper constructor, accessors and python protocol methods
+///
+/// hash is the body of `__hash__`.
+fn expand_accessors(
+    name: &Ident,
+    wrapper: &Ident,
+    fields: &[StateField],
+    hash: TokenStream2,
+) -> TokenStream2 {
+    let krate = quote!(::cellular_automata);
+    let args = fields.iter().filter_map(|f| {
+        let ident = &f.ident;
+        let ty = &f.ty;
+        match f.kind {
+            FieldKind::Id => Some(quote!(#ident: u32)),
+            FieldKind::Position => Some(quote!(#ident: (f64, f64))),
+            FieldKind::Value => Some(quote!(#ident: #ty)),
+            FieldKind::Skip => None,
+        }
+    });
+    let arg_types = fields.iter().filter_map(|f| {
+        let ty = &f.ty;
+        match f.kind {
+            FieldKind::Id => Some(quote!(u32)),
+            FieldKind::Position => Some(quote!((f64, f64))),
+            FieldKind::Value => Some(quote!(#ty)),
+            FieldKind::Skip => None,
+        }
+    });
+    // The constructor arguments of the current state; the getters convert them
+    let arg_values = fields.iter().filter_map(|f| {
+        let ident = &f.ident;
+        match f.kind {
+            FieldKind::Skip => None,
+            _ => Some(quote!(self.#ident())),
+        }
+    });
+    let repr_fields: Vec<String> = fields
+        .iter()
+        .filter_map(|f| match f.kind {
+            FieldKind::Id => Some(format!("{}={{}}", f.ident)),
+            FieldKind::Position => Some(format!("{}=({{:?}}, {{:?}})", f.ident)),
+            FieldKind::Value => Some(format!("{}={{:?}}", f.ident)),
+            FieldKind::Skip => None,
+        })
+        .collect();
+    let repr_format = format!("{}({})", wrapper, repr_fields.join(", "));
+    let repr_values = fields.iter().filter_map(|f| {
+        let ident = &f.ident;
+        match f.kind {
+            FieldKind::Id => Some(quote!(self.inner.#ident.0)),
+            FieldKind::Position => Some(quote!(self.inner.#ident.x(), self.inner.#ident.y())),
+            FieldKind::Value => Some(quote!(self.inner.#ident)),
+            FieldKind::Skip => None,
+        }
+    });
+    let inits = fields.iter().map(|f| {
+        let ident = &f.ident;
+        match f.kind {
+            FieldKind::Id => {
+                quote!(#ident: #krate::process_runner::cells::state::CellIndex(#ident))
+            }
+            FieldKind::Position => quote!(#ident: ::geo::Point::new(#ident.0, #ident.1)),
+            FieldKind::Value => quote!(#ident),
+            FieldKind::Skip => quote!(#ident: ::std::default::Default::default()),
+        }
+    });
+    let accessors = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let ty = &f.ty;
+        let setter = format_ident!("set_{}", ident);
+        match f.kind {
+            FieldKind::Id => quote! {
+                #[getter]
+                pub fn #ident(&self) -> u32 {
+                    self.inner.#ident.0
+                }
+
+                #[setter]
+                pub fn #setter(&mut self, #ident: u32) {
+                    self.inner.#ident = #krate::process_runner::cells::state::CellIndex(#ident);
+                }
+            },
+            FieldKind::Position => quote! {
+                #[getter]
+                pub fn #ident(&self) -> (f64, f64) {
+                    (self.inner.#ident.x(), self.inner.#ident.y())
+                }
+
+                #[setter]
+                pub fn #setter(&mut self, #ident: (f64, f64)) {
+                    self.inner.#ident = ::geo::Point::new(#ident.0, #ident.1);
+                }
+            },
+            FieldKind::Value => quote! {
+                #[getter]
+                pub fn #ident(&self) -> #ty {
+                    ::std::clone::Clone::clone(&self.inner.#ident)
+                }
+
+                #[setter]
+                pub fn #setter(&mut self, #ident: #ty) {
+                    self.inner.#ident = #ident;
+                }
+            },
+            FieldKind::Skip => quote!(),
+        }
+    });
+    quote! {
+        #[::pyo3::prelude::pymethods]
+        impl #wrapper {
+            #[new]
+            pub fn new(#(#args),*) -> Self {
+                #wrapper {
+                    inner: #name {
+                        #(#inits),*
+                    },
+                }
+            }
+
+            #(#accessors)*
+
+            pub fn __getstate__(
+                &self,
+                py: ::pyo3::Python,
+            ) -> ::pyo3::PyResult<::pyo3::PyObject> {
+                #krate::py_interface::pickle::state_to_bytes(py, &self.inner)
+            }
+
+            pub fn __setstate__(&mut self, state: &::pyo3::types::PyBytes) -> ::pyo3::PyResult<()> {
+                self.inner = #krate::py_interface::pickle::state_from_bytes(state.as_bytes())?;
+                Ok(())
+            }
+
+            /// Constructor arguments used by pickle before the state is set
+            pub fn __getnewargs__(&self) -> (#(#arg_types,)*) {
+                (#(#arg_values,)*)
+            }
+
+            pub fn __copy__(&self) -> Self {
+                ::std::clone::Clone::clone(self)
+            }
+
+            pub fn __deepcopy__(&self, _memo: &::pyo3::PyAny) -> Self {
+                ::std::clone::Clone::clone(self)
+            }
+        }
+
+        #[::pyo3::prelude::pyproto]
+        impl ::pyo3::PyObjectProtocol for #wrapper {
+            fn __repr__(&self) -> ::pyo3::PyResult<String> {
+                Ok(format!(#repr_format, #(#repr_values),*))
+            }
+
+            /// Python only allows == and != on the state wrappers
+            fn __richcmp__(
+                &self,
+                other: #wrapper,
+                op: ::pyo3::basic::CompareOp,
+            ) -> ::pyo3::PyResult<bool> {
+                match op {
+                    ::pyo3::basic::CompareOp::Eq => Ok(self.inner == other.inner),
+                    ::pyo3::basic::CompareOp::Ne => Ok(self.inner != other.inner),
+                    _ => Err(::pyo3::exceptions::PyTypeError::new_err(
+                        "States can only be compared for equality",
+                    )),
+                }
+            }
+
+            fn __hash__(&self) -> ::pyo3::PyResult<isize> {
+                #hash
+            }
+        }
+
+        impl #krate::py_interface::PyWrapperBase<#name> for #wrapper {
+            fn get_inner(&self) -> #name {
+                ::std::clone::Clone::clone(&self.inner)
+            }
+
+            fn from_inner(inner: &#name) -> Self {
+                #wrapper {
+                    inner: ::std::clone::Clone::clone(inner),
+                }
+            }
+        }
+    }
+}
+
+/// The pyclass attribute of the wrapper with the module if one is set
+fn expand_pyclass(options: &StateOptions) -> TokenStream2 {
+    match &options.module {
+        Some(module) => quote!(#[::pyo3::prelude::pyclass(module = #module)]),
+        None => quote!(#[::pyo3::prelude::pyclass]),
+    }
+}
+
+/// The body of `__hash__` hashing each field that is not skipped
+fn hash_fields(fields: &[StateField]) -> TokenStream2 {
+    let krate = quote!(::cellular_automata);
+    let idents = fields
+        .iter()
+        .filter(|f| f.kind != FieldKind::Skip)
+        .map(|f| &f.ident);
+    quote! {
+        let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+        #(#krate::py_interface::hash::HashField::hash_field(&self.inner.#idents, &mut hasher);)*
+        Ok(::std::hash::Hasher::finish(&hasher) as isize)
+    }
+}
+
+/// The rust types of the fields described by the derived `Introspect`
+const FIELD_PRIMITIVES: [&str; 14] = [
+    "i8", "i16", "i32", "i64", "isize", "u8", "u16", "u32", "u64", "usize", "f32", "f64", "bool",
+    "String",
+];
+
+fn is_field_primitive(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last().is_some_and(|s| {
+            s.arguments.is_empty() && FIELD_PRIMITIVES.contains(&s.ident.to_string().as_str())
+        }),
+        _ => false,
+    }
+}
+
+/// Implement `Introspect` for the value fields with primitive types
+fn expand_introspect(name: &Ident, fields: &[StateField]) -> TokenStream2 {
+    let module = quote!(::cellular_automata::process_runner::fields);
+    let described: Vec<&StateField> = fields
+        .iter()
+        .filter(|f| f.kind == FieldKind::Value && is_field_primitive(&f.ty))
+        .collect();
+    let names: Vec<String> = described.iter().map(|f| f.ident.to_string()).collect();
+    let idents: Vec<&Ident> = described.iter().map(|f| &f.ident).collect();
+    let types: Vec<&Type> = described.iter().map(|f| &f.ty).collect();
+    quote! {
+        impl #module::Introspect for #name {
+            fn describe_fields(&self) -> ::std::vec::Vec<#module::FieldDescriptor> {
+                vec![#(#module::FieldDescriptor::new(
+                    #names,
+                    <#types as #module::FieldPrimitive>::field_kind(),
+                )),*]
+            }
+
+            fn field_value(&self, name: &str) -> ::std::option::Option<#module::FieldValue> {
+                match name {
+                    #(#names => Some(#module::FieldPrimitive::to_field_value(&self.#idents)),)*
+                    _ => None,
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn set_field_value(
+                &mut self,
+                name: &str,
+                value: #module::FieldValue,
+            ) -> ::std::result::Result<(), #module::FieldError> {
+                match name {
+                    #(#names => {
+                        self.#idents =
+                            <#types as #module::FieldPrimitive>::from_field_value(name, value)?
+                    })*
+                    _ => return Err(#module::FieldError::Unknown(name.to_owned())),
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The body of `__hash__` for states that implement `Hash`
+fn hash_state() -> TokenStream2 {
+    quote! {
+        let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+        ::std::hash::Hash::hash(&self.inner, &mut hasher);
+        Ok(::std::hash::Hasher::finish(&hasher) as isize)
+    }
+}
+
+fn expand_cell_state(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let krate = quote!(::cellular_automata);
+    let name = &input.ident;
+    let wrapper = format_ident!("{}Py", name);
+    let options = parse_options(&input.attrs, "cell_state")?;
+    let fields = parse_fields(input, "cell_state", true)?;
+    for (kind, field_name) in [(FieldKind::Id, "id"), (FieldKind::Position, "position")].iter() {
+        if !fields.iter().any(|f| &f.kind == kind) {
+            return Err(Error::new(
+                name.span(),
+                format!("CellState derive requires a `{}` field", field_name),
+            ));
+        }
+    }
+    let randomize = match &options.randomize {
+        Some(path) => quote!(#path(self)),
+        None => quote!(::std::clone::Clone::clone(self)),
+    };
+    let hash = match options.hash {
+        true => hash_state(),
+        false => hash_fields(&fields),
+    };
+    let pyclass = expand_pyclass(&options);
+    let accessors = expand_accessors(name, &wrapper, &fields, hash);
+    let introspect = expand_introspect(name, &fields);
+    Ok(quote! {
+        impl #krate::process_runner::cells::state::CellStateBase for #name {
+            fn id(&self) -> #krate::process_runner::cells::state::CellIndex {
+                self.id
+            }
+            fn position(&self) -> ::geo::Point<f64> {
+                self.position
+            }
+            fn randomize(&self) -> Self {
+                #randomize
+            }
+        }
+
+        #pyclass
+        #[derive(Clone)]
+        pub struct #wrapper {
+            pub inner: #name,
+        }
+
+        #accessors
+
+        #introspect
+
+        impl #krate::py_interface::cell_state::CellStatePyBase<#name> for #wrapper {}
+    })
+}
+
+fn expand_global_state(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let krate = quote!(::cellular_automata);
+    let name = &input.ident;
+    let wrapper = format_ident!("{}Py", name);
+    let options = parse_options(&input.attrs, "global_state")?;
+    let fields = parse_fields(input, "global_state", false)?;
+    let hash = match options.hash {
+        true => hash_state(),
+        false => hash_fields(&fields),
+    };
+    let pyclass = expand_pyclass(&options);
+    let accessors = expand_accessors(name, &wrapper, &fields, hash);
+    let introspect = expand_introspect(name, &fields);
+    Ok(quote! {
+        impl #krate::process_runner::global::state::GlobalStateBase for #name {}
+
+        #pyclass
+        #[derive(Clone, Default)]
+        pub struct #wrapper {
+            pub inner: #name,
+        }
+
+        #accessors
+
+        #introspect
+
+        impl #krate::py_interface::global_state::GlobalStatePyBase<#name> for #wrapper {}
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn should_require_id_and_position_for_cells() {
+        let input: DeriveInput = parse_quote! {
+            pub struct LandCell {
+                pub id: CellIndex,
+                pub population: u32,
+            }
+        };
+        let err = expand_cell_state(&input).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "CellState derive requires a `position` field"
+        );
+    }
+
+    #[test]
+    fn should_reject_unknown_options() {
+        let input: DeriveInput = parse_quote! {
+            pub struct Climate {
+                #[global_state(hidden)]
+                pub rainfall: f64,
+            }
+        };
+        assert!(expand_global_state(&input).is_err());
+    }
+}
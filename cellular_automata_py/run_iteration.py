@@ -1,4 +1,4 @@
-from cellular_automata import run, CellStatePy, GlobalStatePy
+from cellular_automata import run, CellStatePy, GlobalStatePy, SimulationPy
 
 
 def demo_run():
@@ -21,13 +21,14 @@ def demo_run():
     print("global_state.iterations", global_state.iterations)
 
     print('\n----Runing Multiple Iterations-----')
-    cell_data = initial_cell_data
+    # The simulation keeps the state in rust so cells are only converted when read
+    simulation = SimulationPy(initial_cell_data, GlobalStatePy(1))
 
     for i in range(100):
-        cell_data, global_state, network_map = run.run_iteration(
-            cell_data, global_state)
-        population_a = cell_data[0].population
+        simulation.step()
+    population_a = simulation.cell(0).population
+    global_state = simulation.global_state
     print("population_a", population_a)
     print("Iterations: ", global_state.iterations)
-    print("Global pop: ", global_state.population)
+    print("Global pop: ", simulation.global_field("population"))
     print('Complete')
@@ -1 +1 @@
-cargo test --no-default-features
\ No newline at end of file
+cargo test --no-default-features --features python
\ No newline at end of file
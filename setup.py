@@ -9,7 +9,8 @@ setuptools.setup(
     author="sam bland",
     author_email="sam.bland@sei.org",
     description="Cellular Automata Agent model",
-    setup_requires=["shapely", "geojson"],
+    setup_requires=["geojson"],
+    install_requires=["numpy"],
     tests_require=["pytest"],
     extras_require={"test": ["pytest"]},
     packages=setuptools.find_packages(),
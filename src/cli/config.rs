@@ -0,0 +1,328 @@
+/// Config Module
+///
+/// The JSON config file of a command line run. It names the model, the
+/// processes to run in order, parameter values, the seed, the number of
+/// iterations, the input data the cells are loaded from and the outputs to
+/// write.
+///
+/// Relative paths are relative to the directory of the config file.
+///
+/// ```json
+/// {
+///     "model": "example",
+///     "cell_processes": ["population_growth", "population_migration"],
+///     "global_processes": ["count_iterations"],
+///     "parameters": { "growth_rate": 5.0 },
+///     "seed": 42,
+///     "iterations": 100,
+///     "input": { "format": "csv", "path": "cells.csv", "fields": { "pop": "population" } },
+///     "output": { "csv": "out/cells.csv", "checkpoint": "out/final.ckpt" }
+/// }
+/// ```
+///
+/// The dynamic model also takes the specs of its cell fields as `cell_fields`.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::io::ascii_grid::AsciiGridError;
+use crate::io::csv::CsvError;
+use crate::io::geojson::GeoJsonError;
+use crate::process_runner::cells::dynamic::FieldSpec;
+use crate::process_runner::cells::dynamic::SchemaError;
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::parameters::ParameterError;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::registry::RegistryError;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(serde_json::Error),
+    UnknownModel(String),
+    /// The cell fields of the config are not a valid schema
+    Schema(SchemaError),
+    Process(RegistryError),
+    Parameter(ParameterError),
+    /// The JSON value is not a number or bool
+    InvalidParameterValue {
+        name: String,
+        value: String,
+    },
+    GeoJson(PathBuf, GeoJsonError),
+    Csv(PathBuf, CsvError),
+    AsciiGrid(PathBuf, AsciiGridError),
+    Checkpoint(PathBuf, Box<bincode::ErrorKind>),
+    NoCells,
+    /// Cell ids must match their position in the cell list
+    CellId {
+        index: usize,
+        id: CellIndex,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(e) => write!(f, "Invalid config: {}", e),
+            ConfigError::UnknownModel(name) => write!(f, "Unknown model: {}", name),
+            ConfigError::Schema(e) => write!(f, "Invalid cell fields: {}", e),
+            ConfigError::Process(e) => write!(f, "{}", e),
+            ConfigError::Parameter(e) => write!(f, "{}", e),
+            ConfigError::InvalidParameterValue { name, value } => write!(
+                f,
+                "Parameter {} must be a number or bool but got {}",
+                name, value
+            ),
+            ConfigError::GeoJson(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Csv(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::AsciiGrid(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Checkpoint(path, e) => {
+                write!(f, "{}: Invalid checkpoint: {}", path.display(), e)
+            }
+            ConfigError::NoCells => write!(f, "The input has no cells"),
+            ConfigError::CellId { index, id } => {
+                write!(f, "Cell {} has id {}; ids must match cell order", index, id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+impl From<SchemaError> for ConfigError {
+    fn from(e: SchemaError) -> Self {
+        ConfigError::Schema(e)
+    }
+}
+
+impl From<RegistryError> for ConfigError {
+    fn from(e: RegistryError) -> Self {
+        ConfigError::Process(e)
+    }
+}
+
+impl From<ParameterError> for ConfigError {
+    fn from(e: ParameterError) -> Self {
+        ConfigError::Parameter(e)
+    }
+}
+
+fn default_update_per_process() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    pub model: String,
+    /// Names of the registered cell processes in run order
+    #[serde(default)]
+    pub cell_processes: Vec<String>,
+    /// Names of the registered global processes in run order
+    #[serde(default)]
+    pub global_processes: Vec<String>,
+    /// Values for parameters that should not take their default
+    #[serde(default)]
+    pub parameters: BTreeMap<String, JsonValue>,
+    pub seed: Option<u64>,
+    pub iterations: u32,
+    #[serde(default = "default_update_per_process")]
+    pub update_per_process: bool,
+    /// Randomize the loaded cells before the first iteration
+    #[serde(default)]
+    pub randomize: bool,
+    /// The fields of the cells of the dynamic model
+    #[serde(default)]
+    pub cell_fields: Vec<FieldSpec>,
+    pub input: InputConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+}
+
+/// Where the initial cells are loaded from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case", deny_unknown_fields)]
+pub enum InputConfig {
+    /// A FeatureCollection; fields maps feature properties to cell fields
+    Geojson {
+        path: PathBuf,
+        #[serde(default)]
+        fields: BTreeMap<String, String>,
+    },
+    /// A table with id, x and y columns; fields maps columns to cell fields
+    Csv {
+        path: PathBuf,
+        #[serde(default)]
+        fields: BTreeMap<String, String>,
+    },
+    /// Grids with the same extent; grids maps cell fields to grid files
+    AsciiGrid { grids: BTreeMap<String, PathBuf> },
+    /// Continue from the state of a checkpoint written by a previous run
+    Checkpoint { path: PathBuf },
+}
+
+/// Files written by a run; outputs that are not set are not written
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// The cells of every iteration in long format
+    pub csv: Option<PathBuf>,
+    /// The final state as a FeatureCollection
+    pub geojson: Option<PathBuf>,
+    /// A history file of every iteration
+    pub history: Option<PathBuf>,
+    /// A snapshot of the final simulation that can be inspected or run on from
+    pub checkpoint: Option<PathBuf>,
+}
+
+fn resolve(base: &Path, path: &mut PathBuf) {
+    if path.is_relative() {
+        *path = base.join(path.as_path());
+    }
+}
+
+/// Convert a config parameter to a parameter value
+pub fn parameter_value_from_json(
+    name: &str,
+    value: &JsonValue,
+) -> Result<ParameterValue, ConfigError> {
+    ParameterValue::from_json(value).ok_or_else(|| ConfigError::InvalidParameterValue {
+        name: name.to_owned(),
+        value: value.to_string(),
+    })
+}
+
+impl RunConfig {
+    /// Read a config file and resolve its paths against its directory
+    pub fn read_file(path: impl AsRef<Path>) -> Result<RunConfig, ConfigError> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let mut config: RunConfig = data.parse()?;
+        config.resolve_paths(path.parent().unwrap_or_else(|| Path::new("")));
+        Ok(config)
+    }
+
+    /// Make relative input and output paths relative to the base directory
+    pub fn resolve_paths(&mut self, base: &Path) {
+        match &mut self.input {
+            InputConfig::Geojson { path, .. }
+            | InputConfig::Csv { path, .. }
+            | InputConfig::Checkpoint { path } => resolve(base, path),
+            InputConfig::AsciiGrid { grids } => {
+                grids.values_mut().for_each(|path| resolve(base, path))
+            }
+        }
+        let output = &mut self.output;
+        for path in vec![
+            &mut output.csv,
+            &mut output.geojson,
+            &mut output.history,
+            &mut output.checkpoint,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            resolve(base, path);
+        }
+    }
+
+    /// The parameter values of the config in name order
+    pub fn parameter_values(&self) -> Vec<Result<(String, ParameterValue), ConfigError>> {
+        self.parameters
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), parameter_value_from_json(name, value)?)))
+            .collect()
+    }
+}
+
+impl std::str::FromStr for RunConfig {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"{
+        "model": "example",
+        "cell_processes": ["population_growth"],
+        "parameters": { "growth_rate": 5, "migration": true },
+        "iterations": 3,
+        "input": { "format": "ascii_grid", "grids": { "population": "pop.asc" } },
+        "output": { "csv": "/tmp/cells.csv", "checkpoint": "out/final.ckpt" }
+    }"#;
+
+    #[test]
+    fn should_read_a_config_with_defaults() {
+        let config: RunConfig = CONFIG.parse().unwrap();
+        assert_eq!(config.model, "example");
+        assert_eq!(config.seed, None);
+        assert!(config.update_per_process);
+        assert!(config.global_processes.is_empty());
+        assert_eq!(config.output.history, None);
+        assert_eq!(
+            config
+                .parameter_values()
+                .into_iter()
+                .map(|v| v.unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                ("growth_rate".to_owned(), ParameterValue::Int(5)),
+                ("migration".to_owned(), ParameterValue::Bool(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_resolve_relative_paths_against_the_base() {
+        let mut config: RunConfig = CONFIG.parse().unwrap();
+        config.resolve_paths(Path::new("/data/run"));
+        assert_eq!(
+            config.input,
+            InputConfig::AsciiGrid {
+                grids: vec![("population".to_owned(), PathBuf::from("/data/run/pop.asc"))]
+                    .into_iter()
+                    .collect()
+            }
+        );
+        assert_eq!(config.output.csv, Some(PathBuf::from("/tmp/cells.csv")));
+        assert_eq!(
+            config.output.checkpoint,
+            Some(PathBuf::from("/data/run/out/final.ckpt"))
+        );
+    }
+
+    #[test]
+    fn should_reject_unknown_keys_and_invalid_parameters() {
+        let config = CONFIG.replace("\"iterations\"", "\"iteration_count\"");
+        assert!(matches!(
+            config.parse::<RunConfig>(),
+            Err(ConfigError::Parse(_))
+        ));
+        let config: RunConfig = CONFIG
+            .replace("\"migration\": true", "\"migration\": \"yes\"")
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            config.parameter_values()[1],
+            Err(ConfigError::InvalidParameterValue { .. })
+        ));
+    }
+}
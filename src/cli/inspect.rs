@@ -0,0 +1,178 @@
+/// Inspect Module
+///
+/// Summaries of the cells, network and fields of a checkpoint.
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::FieldDescriptor;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::network::CellNetwork;
+use crate::process_runner::simulation::SimulationSnapshot;
+
+/// Degree statistics of a network
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkSummary {
+    /// The number of neighbour entries; a symmetric link counts twice
+    pub links: usize,
+    pub min_degree: usize,
+    pub max_degree: usize,
+    pub mean_degree: f64,
+    /// Cells without neighbours
+    pub isolated: usize,
+}
+
+impl NetworkSummary {
+    pub fn new(network: &CellNetwork) -> NetworkSummary {
+        let degrees = network.iter().map(|n| n.len());
+        let links = degrees.clone().sum();
+        NetworkSummary {
+            links,
+            min_degree: degrees.clone().min().unwrap_or(0),
+            max_degree: degrees.clone().max().unwrap_or(0),
+            mean_degree: if network.is_empty() {
+                0.0
+            } else {
+                links as f64 / network.len() as f64
+            },
+            isolated: degrees.filter(|d| *d == 0).count(),
+        }
+    }
+}
+
+impl fmt::Display for NetworkSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} links, degree min {} mean {:.2} max {}, {} isolated cells",
+            self.links, self.min_degree, self.mean_degree, self.max_degree, self.isolated
+        )
+    }
+}
+
+/// Statistics of a numeric field over cells
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSummary {
+    pub field: FieldDescriptor,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl fmt::Display for FieldSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.field.name)?;
+        if let Some(unit) = &self.field.unit {
+            write!(f, " ({})", unit)?;
+        }
+        write!(
+            f,
+            ": min {} mean {:.2} max {}",
+            self.min, self.mean, self.max
+        )
+    }
+}
+
+/// Summaries of the numeric fields described by the first cell
+///
+/// Cells where a field is missing are left out of its summary.
+pub fn summarise_fields<C: Introspect>(cells: &[C]) -> Vec<FieldSummary> {
+    let fields = match cells.first() {
+        Some(cell) => cell.describe_fields(),
+        None => return vec![],
+    };
+    fields
+        .into_iter()
+        .filter(|field| field.kind.is_numeric())
+        .filter_map(|field| {
+            let values: Vec<f64> = cells
+                .iter()
+                .filter_map(|cell| cell.numeric_field_value(&field.name))
+                .collect();
+            if values.is_empty() {
+                return None;
+            }
+            Some(FieldSummary {
+                min: values.iter().cloned().fold(f64::INFINITY, f64::min),
+                max: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                mean: values.iter().sum::<f64>() / values.len() as f64,
+                field,
+            })
+        })
+        .collect()
+}
+
+/// Write a summary of the checkpoint at the path
+pub fn write_inspection<C, G>(
+    out: &mut dyn Write,
+    path: &Path,
+    snapshot: &SimulationSnapshot<C, G>,
+) -> std::io::Result<()>
+where
+    C: CellStateBase + Introspect,
+    G: GlobalStateBase + Introspect,
+{
+    let state = &snapshot.state;
+    writeln!(out, "Checkpoint: {}", path.display())?;
+    writeln!(out, "Iteration: {}", snapshot.iteration)?;
+    writeln!(out, "Cells: {}", state.cells.len())?;
+    writeln!(out, "Network: {}", NetworkSummary::new(&state.network))?;
+    writeln!(out, "Global state:")?;
+    for (name, value) in state.global_state.field_values() {
+        writeln!(out, "  {}: {}", name, value)?;
+    }
+    writeln!(out, "Parameters:")?;
+    for (name, value) in snapshot.parameters.values() {
+        writeln!(out, "  {}: {}", name, value)?;
+    }
+    writeln!(out, "Cell fields:")?;
+    for summary in summarise_fields(&state.cells) {
+        writeln!(out, "  {}", summary)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::cells::state::CellIndex;
+    use crate::process_runner::examples::example_state::CellState;
+    use geo::point;
+
+    #[test]
+    fn should_summarise_a_network() {
+        let network = vec![
+            vec![CellIndex(1), CellIndex(2)],
+            vec![CellIndex(0)],
+            vec![CellIndex(0)],
+            vec![],
+        ];
+        assert_eq!(
+            NetworkSummary::new(&network),
+            NetworkSummary {
+                links: 4,
+                min_degree: 0,
+                max_degree: 2,
+                mean_degree: 1.0,
+                isolated: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn should_summarise_numeric_fields() {
+        let cells = vec![
+            CellState::new(0, point!(x: 0.0, y: 0.0), 10),
+            CellState::new(1, point!(x: 0.0, y: 0.1), 30),
+        ];
+        let summaries = summarise_fields(&cells);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].field.name, "population");
+        assert_eq!(
+            summaries[0].to_string(),
+            "population (people): min 10 mean 20.00 max 30"
+        );
+    }
+}
@@ -0,0 +1,403 @@
+/// CLI Module
+///
+/// The command line interface of the `cellular_automata` binary so that batch
+/// jobs can run models without Python.
+///
+/// - `run <config>` runs the model named in a config file and writes its outputs
+/// - `validate <config>` checks the config and its input without running
+/// - `inspect <checkpoint>` prints the cells, network and fields of a checkpoint
+/// - `serve` hosts a simulation for the web GUI when built with the `server` feature
+pub mod config;
+pub mod inspect;
+pub mod model;
+
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::io::csv::CsvError;
+use crate::io::history::HistoryError;
+use crate::process_runner::cells::dynamic::with_schema;
+use crate::process_runner::cells::dynamic::CellSchema;
+use crate::process_runner::cells::dynamic::DynamicCellState;
+use crate::process_runner::examples::example_dynamic::dynamic_registry;
+use crate::process_runner::examples::example_processes::default_registry;
+use crate::process_runner::examples::example_state::CellState;
+use crate::process_runner::examples::example_state::GlobalState;
+use config::ConfigError;
+use config::RunConfig;
+use inspect::write_inspection;
+use model::read_checkpoint;
+use model::DYNAMIC_MODEL;
+use model::EXAMPLE_MODEL;
+use model::MODELS;
+
+pub const USAGE: &str = "Usage:
+    cellular_automata run <config> [--iterations <n>] [--seed <n>]
+    cellular_automata validate <config>
+    cellular_automata inspect <checkpoint> [--model <name>]
+    cellular_automata serve [--address <host:port>] [--interval <ms>] [--model <name>] [--origin <origin>]...
+    cellular_automata help";
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Config(ConfigError),
+    /// Validation found problems with the config
+    Invalid(Vec<ConfigError>),
+    Io(PathBuf, std::io::Error),
+    Csv(CsvError),
+    History(HistoryError),
+    GeoJson(serde_json::Error),
+    Encoding(Box<bincode::ErrorKind>),
+    Server(std::io::Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(msg) => write!(f, "{}\n\n{}", msg, USAGE),
+            CliError::Config(e) => write!(f, "{}", e),
+            CliError::Invalid(problems) => {
+                write!(f, "Invalid config:")?;
+                for problem in problems.iter() {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+            CliError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            CliError::Csv(e) => write!(f, "{}", e),
+            CliError::History(e) => write!(f, "{}", e),
+            CliError::GeoJson(e) => write!(f, "{}", e),
+            CliError::Encoding(e) => write!(f, "{}", e),
+            CliError::Server(e) => write!(f, "Server error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<ConfigError> for CliError {
+    fn from(e: ConfigError) -> Self {
+        CliError::Config(e)
+    }
+}
+
+impl From<CsvError> for CliError {
+    fn from(e: CsvError) -> Self {
+        CliError::Csv(e)
+    }
+}
+
+impl From<HistoryError> for CliError {
+    fn from(e: HistoryError) -> Self {
+        CliError::History(e)
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(e: serde_json::Error) -> Self {
+        CliError::GeoJson(e)
+    }
+}
+
+impl From<Box<bincode::ErrorKind>> for CliError {
+    fn from(e: Box<bincode::ErrorKind>) -> Self {
+        CliError::Encoding(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run {
+        config: PathBuf,
+        /// Overrides the iterations of the config
+        iterations: Option<u32>,
+        /// Overrides the seed of the config
+        seed: Option<u64>,
+    },
+    Validate {
+        config: PathBuf,
+    },
+    Inspect {
+        checkpoint: PathBuf,
+        model: String,
+    },
+    /// Host a simulation for the web GUI; needs the server feature
+    Serve {
+        address: Option<String>,
+        /// Time between iterations while running
+        step_interval_ms: Option<u64>,
+        model: String,
+        /// Other origins allowed to use the server
+        origins: Vec<String>,
+    },
+    Help,
+}
+
+fn parse_option<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, CliError> {
+    let value = value.ok_or_else(|| CliError::Usage(format!("{} needs a value", option)))?;
+    value
+        .parse()
+        .map_err(|_| CliError::Usage(format!("Invalid value for {}: {}", option, value)))
+}
+
+/// Parse the arguments after the program name
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Err(CliError::Usage("No command given".to_owned())),
+    };
+    if command == "help" || command == "--help" || command == "-h" {
+        return Ok(Command::Help);
+    }
+    // Every command but serve takes a file
+    let path = match command.as_str() {
+        "serve" => None,
+        _ => Some(
+            args.next()
+                .map(PathBuf::from)
+                .ok_or_else(|| CliError::Usage(format!("{} needs a file", command)))?,
+        ),
+    };
+    let mut iterations = None;
+    let mut seed = None;
+    let mut model = EXAMPLE_MODEL.to_owned();
+    let mut address = None;
+    let mut step_interval_ms = None;
+    let mut origins = vec![];
+    while let Some(option) = args.next() {
+        match (command.as_str(), option.as_str()) {
+            ("run", "--iterations") => iterations = Some(parse_option(&option, args.next())?),
+            ("run", "--seed") => seed = Some(parse_option(&option, args.next())?),
+            ("inspect", "--model") | ("serve", "--model") => {
+                model = parse_option(&option, args.next())?
+            }
+            ("serve", "--address") => address = Some(parse_option(&option, args.next())?),
+            ("serve", "--interval") => step_interval_ms = Some(parse_option(&option, args.next())?),
+            ("serve", "--origin") => origins.push(parse_option(&option, args.next())?),
+            _ => return Err(CliError::Usage(format!("Unexpected argument: {}", option))),
+        }
+    }
+    match (command.as_str(), path) {
+        ("run", Some(config)) => Ok(Command::Run {
+            config,
+            iterations,
+            seed,
+        }),
+        ("validate", Some(config)) => Ok(Command::Validate { config }),
+        ("inspect", Some(checkpoint)) => Ok(Command::Inspect { checkpoint, model }),
+        ("serve", None) => Ok(Command::Serve {
+            address,
+            step_interval_ms,
+            model,
+            origins,
+        }),
+        _ => Err(CliError::Usage(format!("Unknown command: {}", command))),
+    }
+}
+
+fn unknown_model(name: &str) -> CliError {
+    CliError::Config(ConfigError::UnknownModel(format!(
+        "{} (available models: {})",
+        name,
+        MODELS.join(", ")
+    )))
+}
+
+/// The schema of the cells of a dynamic model config
+fn config_schema(config: &RunConfig) -> Result<Arc<CellSchema>, ConfigError> {
+    Ok(Arc::new(CellSchema::from_specs(
+        config.cell_fields.clone(),
+    )?))
+}
+
+fn write_error(e: std::io::Error) -> CliError {
+    CliError::Io(PathBuf::from("<output>"), e)
+}
+
+/// Host the model for the web GUI
+///
+/// Errors on single connections do not stop the server so they are reported
+/// on stderr.
+#[cfg(feature = "server")]
+fn serve(
+    address: Option<String>,
+    step_interval_ms: Option<u64>,
+    model: &str,
+    origins: &[String],
+    out: &mut dyn Write,
+) -> Result<(), CliError> {
+    use crate::process_runner::examples::example_gui::ExampleGuiModel;
+    use crate::server::Server;
+    use crate::server::DEFAULT_ADDRESS;
+    use crate::server::DEFAULT_STEP_INTERVAL;
+    use std::time::Duration;
+
+    let address = address.unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+    let step_interval = step_interval_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_STEP_INTERVAL);
+    let server = match model {
+        EXAMPLE_MODEL => Server::bind(ExampleGuiModel, address.as_str())
+            .map_err(CliError::Server)?
+            .with_step_interval(step_interval),
+        name => return Err(unknown_model(name)),
+    };
+    let server = origins
+        .iter()
+        .fold(server, |server, origin| server.with_allowed_origin(origin));
+    let local_addr = server.local_addr().map_err(CliError::Server)?;
+    writeln!(out, "Serving {} on http://{}", model, local_addr).map_err(write_error)?;
+    out.flush().map_err(write_error)?;
+    server
+        .serve(|e| eprintln!("Connection error: {}", e))
+        .map_err(CliError::Server)
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(
+    _address: Option<String>,
+    _step_interval_ms: Option<u64>,
+    _model: &str,
+    _origins: &[String],
+    _out: &mut dyn Write,
+) -> Result<(), CliError> {
+    Err(CliError::Usage(
+        "serve needs the binary to be built with the server feature".to_owned(),
+    ))
+}
+
+/// Run a command writing its report to out
+pub fn execute(command: Command, out: &mut dyn Write) -> Result<(), CliError> {
+    match command {
+        Command::Help => writeln!(out, "{}", USAGE).map_err(write_error),
+        Command::Run {
+            config,
+            iterations,
+            seed,
+        } => {
+            let mut config = RunConfig::read_file(&config)?;
+            if let Some(iterations) = iterations {
+                config.iterations = iterations;
+            }
+            if seed.is_some() {
+                config.seed = seed;
+            }
+            let summary = match config.model.as_str() {
+                EXAMPLE_MODEL => model::run(&config, &default_registry())?,
+                DYNAMIC_MODEL => {
+                    let schema = config_schema(&config)?;
+                    with_schema(&schema, || model::run(&config, &dynamic_registry(&schema)))?
+                }
+                name => return Err(unknown_model(name)),
+            };
+            writeln!(
+                out,
+                "Ran {} iterations of {} cells to iteration {}",
+                summary.iterations, summary.cells, summary.final_iteration
+            )
+            .map_err(write_error)?;
+            for path in summary.outputs.iter() {
+                writeln!(out, "Wrote {}", path.display()).map_err(write_error)?;
+            }
+            Ok(())
+        }
+        Command::Validate { config: path } => {
+            let config = RunConfig::read_file(&path)?;
+            let problems = match config.model.as_str() {
+                EXAMPLE_MODEL => model::validate(&config, &default_registry()),
+                DYNAMIC_MODEL => {
+                    let schema = config_schema(&config)?;
+                    with_schema(&schema, || {
+                        model::validate(&config, &dynamic_registry(&schema))
+                    })
+                }
+                name => return Err(unknown_model(name)),
+            };
+            if !problems.is_empty() {
+                return Err(CliError::Invalid(problems));
+            }
+            writeln!(out, "{} is valid", path.display()).map_err(write_error)
+        }
+        Command::Serve {
+            address,
+            step_interval_ms,
+            model,
+            origins,
+        } => serve(address, step_interval_ms, &model, &origins, out),
+        Command::Inspect { checkpoint, model } => match model.as_str() {
+            EXAMPLE_MODEL => {
+                let snapshot = read_checkpoint::<CellState, GlobalState>(&checkpoint)?;
+                write_inspection(out, &checkpoint, &snapshot).map_err(write_error)
+            }
+            // Dynamic cells are saved with their fields so no config is needed
+            DYNAMIC_MODEL => {
+                let snapshot = read_checkpoint::<DynamicCellState, GlobalState>(&checkpoint)?;
+                write_inspection(out, &checkpoint, &snapshot).map_err(write_error)
+            }
+            name => Err(unknown_model(name)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn should_parse_commands_and_options() {
+        assert_eq!(
+            parse_args(args(&["run", "config.json", "--seed", "7"])).unwrap(),
+            Command::Run {
+                config: PathBuf::from("config.json"),
+                iterations: None,
+                seed: Some(7),
+            }
+        );
+        assert_eq!(
+            parse_args(args(&["inspect", "final.ckpt"])).unwrap(),
+            Command::Inspect {
+                checkpoint: PathBuf::from("final.ckpt"),
+                model: EXAMPLE_MODEL.to_owned(),
+            }
+        );
+        assert_eq!(
+            parse_args(args(&[
+                "serve",
+                "--interval",
+                "50",
+                "--origin",
+                "http://localhost:3000"
+            ]))
+            .unwrap(),
+            Command::Serve {
+                address: None,
+                step_interval_ms: Some(50),
+                model: EXAMPLE_MODEL.to_owned(),
+                origins: vec!["http://localhost:3000".to_owned()],
+            }
+        );
+        assert_eq!(parse_args(args(&["help"])).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn should_reject_invalid_arguments() {
+        for invalid in [
+            args(&[]),
+            args(&["run"]),
+            args(&["simulate", "config.json"]),
+            args(&["validate", "config.json", "--seed", "7"]),
+            args(&["serve", "config.json"]),
+            args(&["run", "config.json", "--iterations", "many"]),
+        ] {
+            assert!(matches!(parse_args(invalid), Err(CliError::Usage(_))));
+        }
+    }
+}
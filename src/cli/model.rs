@@ -0,0 +1,501 @@
+/// Model Module
+///
+/// Load, validate and run a config for any model. A model is given by the
+/// process registry its processes are chosen from; the binary picks the
+/// registry from the model named in the config.
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::config::ConfigError;
+use super::config::InputConfig;
+use super::config::RunConfig;
+use super::CliError;
+use crate::io::ascii_grid::AsciiGrid;
+use crate::io::ascii_grid::AsciiGridLoader;
+use crate::io::csv::CsvCellReader;
+use crate::io::csv::CsvSeries;
+use crate::io::geojson::state_to_geojson;
+use crate::io::geojson::GeoJsonLoader;
+use crate::io::history::HistoryWriter;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::random;
+use crate::process_runner::registry::ProcessRegistry;
+use crate::process_runner::run::setup_initial_state;
+use crate::process_runner::simulation::Simulation;
+use crate::process_runner::simulation::SimulationSnapshot;
+use geo::Geometry;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The name of the example model in configs
+pub const EXAMPLE_MODEL: &str = "example";
+
+/// The name of the model whose cell fields are set by the `cell_fields` of the config
+pub const DYNAMIC_MODEL: &str = "dynamic";
+
+/// Names of the models the binary can run
+pub const MODELS: [&str; 2] = [EXAMPLE_MODEL, DYNAMIC_MODEL];
+
+/// The cells loaded from the input of a config
+#[derive(Debug, Clone)]
+pub struct LoadedInput<C: CellStateBase, G: GlobalStateBase> {
+    pub cells: Vec<C>,
+    /// The geometry of each cell when loaded from GeoJSON
+    pub geometries: Option<Vec<Geometry<f64>>>,
+    /// The simulation when continuing from a checkpoint
+    pub snapshot: Option<SimulationSnapshot<C, G>>,
+}
+
+/// What a run did and the files it wrote
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub cells: usize,
+    pub iterations: u32,
+    /// The iteration count of the simulation at the end of the run
+    pub final_iteration: u32,
+    pub outputs: Vec<PathBuf>,
+}
+
+pub fn read_checkpoint<C, G>(path: &Path) -> Result<SimulationSnapshot<C, G>, ConfigError>
+where
+    C: CellStateBase + DeserializeOwned,
+    G: GlobalStateBase + DeserializeOwned,
+{
+    let bytes = fs::read(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+    bincode::deserialize(&bytes).map_err(|e| ConfigError::Checkpoint(path.to_owned(), e))
+}
+
+pub fn write_checkpoint<C, G>(
+    path: &Path,
+    snapshot: &SimulationSnapshot<C, G>,
+) -> Result<(), CliError>
+where
+    C: CellStateBase + Serialize,
+    G: GlobalStateBase + Serialize,
+{
+    let file = File::create(path).map_err(|e| CliError::Io(path.to_owned(), e))?;
+    bincode::serialize_into(BufWriter::new(file), snapshot)?;
+    Ok(())
+}
+
+/// Cells must not be empty and their ids must match their order
+fn check_cells<C: CellStateBase>(cells: &[C]) -> Result<(), ConfigError> {
+    if cells.is_empty() {
+        return Err(ConfigError::NoCells);
+    }
+    match cells
+        .iter()
+        .enumerate()
+        .find(|(index, cell)| u32::from(cell.id()) as usize != *index)
+    {
+        Some((index, cell)) => Err(ConfigError::CellId {
+            index,
+            id: cell.id(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Load the cells of the config input
+pub fn load_input<C, G>(input: &InputConfig) -> Result<LoadedInput<C, G>, ConfigError>
+where
+    C: CellFields + DeserializeOwned,
+    G: GlobalStateBase + DeserializeOwned,
+{
+    let loaded = match input {
+        InputConfig::Geojson { path, fields } => {
+            let loader = fields
+                .iter()
+                .fold(GeoJsonLoader::new(), |loader, (property, field)| {
+                    loader.with_field(property, field)
+                });
+            let loaded = loader
+                .load_file::<C>(path)
+                .map_err(|e| ConfigError::GeoJson(path.clone(), e))?;
+            LoadedInput {
+                cells: loaded.cells,
+                geometries: Some(loaded.geometries),
+                snapshot: None,
+            }
+        }
+        InputConfig::Csv { path, fields } => {
+            let reader = fields
+                .iter()
+                .fold(CsvCellReader::new(), |reader, (column, field)| {
+                    reader.with_field(column, field)
+                });
+            LoadedInput {
+                cells: reader
+                    .read_file(path)
+                    .map_err(|e| ConfigError::Csv(path.clone(), e))?,
+                geometries: None,
+                snapshot: None,
+            }
+        }
+        InputConfig::AsciiGrid { grids } => {
+            let mut loader = AsciiGridLoader::new();
+            for (field, path) in grids.iter() {
+                let grid = AsciiGrid::read_file(path)
+                    .map_err(|e| ConfigError::AsciiGrid(path.clone(), e))?;
+                loader = loader.with_field(grid, field);
+            }
+            // Grid files have been read so errors are from the field mapping
+            let path = grids.values().next().cloned().unwrap_or_default();
+            LoadedInput {
+                cells: loader
+                    .load()
+                    .map_err(|e| ConfigError::AsciiGrid(path, e))?
+                    .cells,
+                geometries: None,
+                snapshot: None,
+            }
+        }
+        InputConfig::Checkpoint { path } => {
+            let snapshot = read_checkpoint::<C, G>(path)?;
+            LoadedInput {
+                cells: snapshot.state.cells.clone(),
+                geometries: None,
+                snapshot: Some(snapshot),
+            }
+        }
+    };
+    check_cells(&loaded.cells)?;
+    Ok(loaded)
+}
+
+/// Set the config parameters on the base parameters
+///
+/// Returns the parameters with every valid value set and the invalid values.
+fn apply_parameters(
+    mut parameters: Parameters,
+    config: &RunConfig,
+) -> (Parameters, Vec<ConfigError>) {
+    let mut errors = vec![];
+    for value in config.parameter_values() {
+        let result = value.and_then(|(name, value)| Ok(parameters.set(&name, value)?));
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+    (parameters, errors)
+}
+
+/// The parameters the config values are set on: those of the checkpoint or the registry
+fn base_parameters<C: CellStateBase, G: GlobalStateBase>(
+    registry: &ProcessRegistry<C, G>,
+    input: Option<&LoadedInput<C, G>>,
+) -> Parameters {
+    match input.and_then(|input| input.snapshot.as_ref()) {
+        Some(snapshot) => snapshot.parameters.clone(),
+        None => registry.parameters(),
+    }
+}
+
+/// Every problem that would stop the config from running
+///
+/// The input is loaded to check that it can be read and that its fields exist.
+pub fn validate<C, G>(config: &RunConfig, registry: &ProcessRegistry<C, G>) -> Vec<ConfigError>
+where
+    C: CellFields + DeserializeOwned,
+    G: GlobalStateBase + DeserializeOwned,
+{
+    let mut problems: Vec<ConfigError> = vec![];
+    for name in config.cell_processes.iter() {
+        if let Err(e) = registry.cell_process(name) {
+            problems.push(e.into());
+        }
+    }
+    for name in config.global_processes.iter() {
+        if let Err(e) = registry.global_process(name) {
+            problems.push(e.into());
+        }
+    }
+    let input = match load_input::<C, G>(&config.input) {
+        Ok(input) => Some(input),
+        Err(e) => {
+            problems.push(e);
+            None
+        }
+    };
+    let (_, errors) = apply_parameters(base_parameters(registry, input.as_ref()), config);
+    problems.extend(errors);
+    problems
+}
+
+/// Create the simulation for the config from the loaded input
+///
+/// When continuing from a checkpoint the simulation keeps its iteration count.
+pub fn build_simulation<C, G>(
+    config: &RunConfig,
+    registry: &ProcessRegistry<C, G>,
+    input: LoadedInput<C, G>,
+) -> Result<Simulation<C, G>, ConfigError>
+where
+    C: CellStateBase,
+    G: GlobalStateBase,
+{
+    let cell_names: Vec<&str> = config.cell_processes.iter().map(|s| s.as_str()).collect();
+    let global_names: Vec<&str> = config.global_processes.iter().map(|s| s.as_str()).collect();
+    let cell_processes = registry.cell_processes(&cell_names)?;
+    let global_processes = registry.global_processes(&global_names)?;
+    let (parameters, mut errors) =
+        apply_parameters(base_parameters(registry, Some(&input)), config);
+    if !errors.is_empty() {
+        return Err(errors.remove(0));
+    }
+
+    let (state, iteration) = match input.snapshot {
+        Some(snapshot) => (snapshot.state, snapshot.iteration),
+        None => (
+            setup_initial_state(
+                None,
+                None,
+                &parameters,
+                input.cells,
+                G::default(),
+                config.randomize,
+            ),
+            0,
+        ),
+    };
+    let mut simulation = Simulation::new(state, cell_processes, global_processes, parameters)
+        .with_update_per_process(config.update_per_process);
+    if iteration > 0 {
+        let snapshot = SimulationSnapshot {
+            iteration,
+            ..simulation.snapshot()
+        };
+        simulation.restore(snapshot);
+    }
+    Ok(simulation)
+}
+
+fn create_parent(path: &Path) -> Result<&Path, CliError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| CliError::Io(parent.to_owned(), e))?;
+    }
+    Ok(path)
+}
+
+/// Run the config and write its outputs
+///
+/// The random generator of the current thread is seeded before the cells are
+/// loaded. The CSV and history outputs include the state before the first
+/// iteration.
+pub fn run<C, G>(
+    config: &RunConfig,
+    registry: &ProcessRegistry<C, G>,
+) -> Result<RunSummary, CliError>
+where
+    C: CellFields + PartialEq + Serialize + DeserializeOwned,
+    G: GlobalStateBase + Serialize + DeserializeOwned,
+{
+    if let Some(seed) = config.seed {
+        random::seed(seed);
+    }
+    let input = load_input::<C, G>(&config.input)?;
+    let geometries = input.geometries.clone();
+    let mut simulation = build_simulation(config, registry, input)?;
+    let output = &config.output;
+
+    let mut csv = match &output.csv {
+        Some(path) => Some(CsvSeries::create(create_parent(path)?)?),
+        None => None,
+    };
+    let mut history = match &output.history {
+        Some(path) => Some(HistoryWriter::create(create_parent(path)?)?),
+        None => None,
+    };
+    if let Some(csv) = csv.as_mut() {
+        csv.record(simulation.iteration(), &simulation.state)?;
+    }
+    if let Some(history) = history.as_mut() {
+        history.record(simulation.iteration(), &simulation.state)?;
+    }
+    {
+        let mut observers: Vec<&mut dyn Observer<C, G>> = vec![];
+        if let Some(csv) = csv.as_mut() {
+            observers.push(csv);
+        }
+        if let Some(history) = history.as_mut() {
+            observers.push(history);
+        }
+        simulation.run(config.iterations, &mut observers);
+    }
+    if let Some(csv) = csv {
+        csv.finish()?;
+    }
+    if let Some(history) = history {
+        history.finish()?;
+    }
+    if let Some(path) = &output.geojson {
+        let geojson = state_to_geojson(&simulation.state, geometries.as_deref())?;
+        fs::write(create_parent(path)?, geojson).map_err(|e| CliError::Io(path.clone(), e))?;
+    }
+    if let Some(path) = &output.checkpoint {
+        write_checkpoint(create_parent(path)?, &simulation.snapshot())?;
+    }
+
+    Ok(RunSummary {
+        cells: simulation.state.cells.len(),
+        iterations: config.iterations,
+        final_iteration: simulation.iteration(),
+        outputs: vec![
+            &output.csv,
+            &output.geojson,
+            &output.history,
+            &output.checkpoint,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::history::HistoryReader;
+    use crate::process_runner::cells::dynamic::with_schema;
+    use crate::process_runner::cells::dynamic::CellSchema;
+    use crate::process_runner::cells::dynamic::DynamicCellState;
+    use crate::process_runner::examples::example_dynamic::dynamic_registry;
+    use crate::process_runner::examples::example_processes::default_registry;
+    use crate::process_runner::examples::example_state::CellState;
+    use crate::process_runner::examples::example_state::GlobalState;
+    use crate::process_runner::fields::FieldValue;
+    use crate::process_runner::fields::Introspect;
+    use std::env;
+    use std::sync::Arc;
+
+    const GRID: &str = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 0.1\n\
+                        NODATA_value -9999\n10 20 -9999\n30 40 50\n";
+
+    fn demo_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("cellular_automata_cli_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("population.asc"), GRID).unwrap();
+        dir
+    }
+
+    fn demo_config(dir: &Path) -> RunConfig {
+        let mut config: RunConfig = r#"{
+            "model": "example",
+            "cell_processes": ["set_population_to_100", "population_growth"],
+            "global_processes": ["count_iterations"],
+            "parameters": { "growth_rate": 50 },
+            "seed": 1,
+            "iterations": 2,
+            "input": { "format": "ascii_grid", "grids": { "population": "population.asc" } },
+            "output": {
+                "csv": "out/cells.csv",
+                "history": "out/run.cahist",
+                "checkpoint": "out/final.ckpt"
+            }
+        }"#
+        .parse()
+        .unwrap();
+        config.resolve_paths(dir);
+        config
+    }
+
+    #[test]
+    fn should_run_a_config_and_write_the_outputs() {
+        let dir = demo_dir("run");
+        let config = demo_config(&dir);
+        let summary = run(&config, &default_registry()).unwrap();
+        assert_eq!(summary.cells, 5);
+        assert_eq!(summary.final_iteration, 2);
+        assert_eq!(summary.outputs.len(), 3);
+
+        let snapshot: SimulationSnapshot<CellState, GlobalState> =
+            read_checkpoint(&dir.join("out/final.ckpt")).unwrap();
+        assert_eq!(snapshot.iteration, 2);
+        assert_eq!(snapshot.state.global_state.iterations, 2);
+        assert_eq!(snapshot.state.cells[0].population, 150);
+        assert_eq!(snapshot.parameters.get_f64("growth_rate"), Ok(50.0));
+
+        let mut history: HistoryReader<CellState, GlobalState, _> =
+            HistoryReader::open(dir.join("out/run.cahist")).unwrap();
+        assert_eq!(history.iterations(), vec![0, 1, 2]);
+        assert_eq!(history.read_cells(0).unwrap()[3].population, 40);
+        let csv = fs::read_to_string(dir.join("out/cells.csv")).unwrap();
+        assert_eq!(csv.lines().count(), 1 + 3 * 5);
+    }
+
+    #[test]
+    fn should_continue_from_a_checkpoint() {
+        let dir = demo_dir("continue");
+        let config = demo_config(&dir);
+        run(&config, &default_registry()).unwrap();
+
+        let mut config = RunConfig {
+            input: InputConfig::Checkpoint {
+                path: dir.join("out/final.ckpt"),
+            },
+            ..config
+        };
+        config.output.checkpoint = Some(dir.join("out/continued.ckpt"));
+        let summary = run(&config, &default_registry()).unwrap();
+        assert_eq!(summary.final_iteration, 4);
+        let snapshot: SimulationSnapshot<CellState, GlobalState> =
+            read_checkpoint(&dir.join("out/continued.ckpt")).unwrap();
+        assert_eq!(snapshot.state.global_state.iterations, 4);
+    }
+
+    #[test]
+    fn should_run_a_config_with_dynamic_cell_fields() {
+        let dir = demo_dir("dynamic");
+        let mut config = demo_config(&dir);
+        config.model = DYNAMIC_MODEL.to_owned();
+        config.cell_processes = vec!["population_growth".to_owned()];
+        config.cell_fields = serde_json::from_str(
+            r#"[{"name": "population", "type": "int"}, {"name": "flooded", "type": "bool"}]"#,
+        )
+        .unwrap();
+        let schema = Arc::new(CellSchema::from_specs(config.cell_fields.clone()).unwrap());
+        let summary = with_schema(&schema, || run(&config, &dynamic_registry(&schema))).unwrap();
+        assert_eq!(summary.final_iteration, 2);
+
+        let snapshot: SimulationSnapshot<DynamicCellState, GlobalState> =
+            read_checkpoint(&dir.join("out/final.ckpt")).unwrap();
+        let cell = &snapshot.state.cells[0];
+        assert_eq!(cell.schema().field_names(), vec!["population", "flooded"]);
+        assert_eq!(cell.field_value("population"), Some(FieldValue::Int(23)));
+        assert_eq!(snapshot.state.global_state.iterations, 2);
+    }
+
+    #[test]
+    fn should_report_every_problem_with_a_config() {
+        let dir = demo_dir("validate");
+        let config = demo_config(&dir);
+        assert!(validate(&config, &default_registry()).is_empty());
+
+        let mut config = config;
+        config.cell_processes.push("grow_faster".to_owned());
+        config.global_processes.push("population_growth".to_owned());
+        config
+            .parameters
+            .insert("growth_rate".to_owned(), serde_json::json!(500.0));
+        config.input = InputConfig::AsciiGrid {
+            grids: vec![("height".to_owned(), dir.join("population.asc"))]
+                .into_iter()
+                .collect(),
+        };
+        let problems = validate(&config, &default_registry());
+        assert_eq!(problems.len(), 4);
+        assert!(matches!(problems[0], ConfigError::Process(_)));
+        assert!(matches!(problems[1], ConfigError::Process(_)));
+        assert!(matches!(problems[2], ConfigError::AsciiGrid(..)));
+        assert!(matches!(problems[3], ConfigError::Parameter(_)));
+    }
+}
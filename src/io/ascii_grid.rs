@@ -0,0 +1,591 @@
+/// ESRI ASCII Grid Module
+///
+/// Read lattice cells from ESRI ASCII grid (`.asc`) rasters and write numeric
+/// cell fields back out with the same georeferencing.
+///
+/// Each raster sets one cell field. All rasters of a model must share the same
+/// extent and cell size. A cell is created at the centre of each grid cell that
+/// has a value in every raster; grid cells with the `NODATA_value` in any
+/// raster are masked and have no cell. Cells are numbered in row order from
+/// the top left grid cell, skipping masked grid cells.
+///
+/// On export cells are placed in the grid cell containing their position.
+/// Grid cells without a cell are written as `NODATA_value`.
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::fields::FieldError;
+use crate::process_runner::fields::FieldValue;
+use crate::process_runner::fields::Introspect;
+use geo::Point;
+
+/// The no data value written when the grid header does not have one
+pub const DEFAULT_NODATA_VALUE: f64 = -9999.0;
+
+#[derive(Debug)]
+pub enum AsciiGridError {
+    Io(std::io::Error),
+    MissingHeader(&'static str),
+    /// The header value on the line could not be read
+    InvalidHeader {
+        line: usize,
+        key: String,
+        value: String,
+    },
+    /// The grid value on the line could not be read as a number
+    InvalidValue {
+        line: usize,
+        value: String,
+    },
+    WrongValueCount {
+        expected: usize,
+        found: usize,
+    },
+    /// The number of grid cells does not fit in memory
+    TooLarge {
+        ncols: usize,
+        nrows: usize,
+    },
+    /// The x and y origin keys disagree, e.g. xllcenter with yllcorner
+    MixedOrigin,
+    /// The raster for the field does not have the extent of the first raster
+    MismatchedGrid(String),
+    NoGrids,
+    UnknownField(String),
+    /// The field value of the cell is not numeric
+    NotNumeric {
+        cell: CellIndex,
+        field: String,
+    },
+    Field {
+        row: usize,
+        col: usize,
+        error: FieldError,
+    },
+}
+
+impl fmt::Display for AsciiGridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsciiGridError::Io(e) => write!(f, "{}", e),
+            AsciiGridError::MissingHeader(key) => write!(f, "Missing header: {}", key),
+            AsciiGridError::InvalidHeader { line, key, value } => {
+                write!(f, "Line {}: invalid {} header value {:?}", line, key, value)
+            }
+            AsciiGridError::InvalidValue { line, value } => {
+                write!(f, "Line {}: expected a number but got {:?}", line, value)
+            }
+            AsciiGridError::WrongValueCount { expected, found } => {
+                write!(f, "Expected {} grid values but got {}", expected, found)
+            }
+            AsciiGridError::TooLarge { ncols, nrows } => {
+                write!(f, "A grid of {} by {} cells is too large", ncols, nrows)
+            }
+            AsciiGridError::MixedOrigin => write!(
+                f,
+                "The x and y origins must both be corners or both be centres"
+            ),
+            AsciiGridError::MismatchedGrid(field) => write!(
+                f,
+                "The grid for field {} does not match the extent of the other grids",
+                field
+            ),
+            AsciiGridError::NoGrids => write!(f, "At least one grid is required"),
+            AsciiGridError::UnknownField(field) => write!(f, "Unknown field: {}", field),
+            AsciiGridError::NotNumeric { cell, field } => {
+                write!(f, "Cell {} field {} is not numeric", cell, field)
+            }
+            AsciiGridError::Field { row, col, error } => {
+                write!(f, "Row {} column {}: {}", row, col, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsciiGridError {}
+
+impl From<std::io::Error> for AsciiGridError {
+    fn from(e: std::io::Error) -> Self {
+        AsciiGridError::Io(e)
+    }
+}
+
+/// Whether the grid origin is the lower left corner or the centre of the lower left cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridOrigin {
+    Corner,
+    Center,
+}
+
+/// The extent and georeferencing of a grid
+///
+/// The origin is kept as written so that grids are written back with the
+/// header keys they were read with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsciiGridHeader {
+    pub ncols: usize,
+    pub nrows: usize,
+    pub xll: f64,
+    pub yll: f64,
+    pub origin: GridOrigin,
+    pub cellsize: f64,
+    pub nodata_value: Option<f64>,
+}
+
+impl AsciiGridHeader {
+    /// The number of grid cells
+    pub fn cell_count(&self) -> Result<usize, AsciiGridError> {
+        self.ncols
+            .checked_mul(self.nrows)
+            .ok_or(AsciiGridError::TooLarge {
+                ncols: self.ncols,
+                nrows: self.nrows,
+            })
+    }
+
+    /// The x and y of the lower left corner of the grid
+    pub fn lower_left_corner(&self) -> (f64, f64) {
+        match self.origin {
+            GridOrigin::Corner => (self.xll, self.yll),
+            GridOrigin::Center => (
+                self.xll - self.cellsize / 2.0,
+                self.yll - self.cellsize / 2.0,
+            ),
+        }
+    }
+
+    /// The position of the centre of the grid cell
+    ///
+    /// Row 0 is the top row of the grid.
+    pub fn cell_centre(&self, row: usize, col: usize) -> Point<f64> {
+        let (x, y) = self.lower_left_corner();
+        Point::new(
+            x + (col as f64 + 0.5) * self.cellsize,
+            y + ((self.nrows - row) as f64 - 0.5) * self.cellsize,
+        )
+    }
+
+    /// The row and column of the grid cell containing the position
+    pub fn locate(&self, position: Point<f64>) -> Option<(usize, usize)> {
+        let (x, y) = self.lower_left_corner();
+        let col = ((position.x() - x) / self.cellsize).floor();
+        let row_from_bottom = ((position.y() - y) / self.cellsize).floor();
+        if col < 0.0
+            || row_from_bottom < 0.0
+            || col >= self.ncols as f64
+            || row_from_bottom >= self.nrows as f64
+        {
+            return None;
+        }
+        Some((self.nrows - 1 - row_from_bottom as usize, col as usize))
+    }
+
+    /// Grids match if they have the same extent and cell size
+    ///
+    /// The no data value does not need to match.
+    pub fn matches(&self, other: &AsciiGridHeader) -> bool {
+        self.ncols == other.ncols
+            && self.nrows == other.nrows
+            && self.cellsize == other.cellsize
+            && self.lower_left_corner() == other.lower_left_corner()
+    }
+}
+
+/// A raster with its values in row order from the top left
+///
+/// Cells with the no data value are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsciiGrid {
+    pub header: AsciiGridHeader,
+    pub values: Vec<Option<f64>>,
+}
+
+impl AsciiGrid {
+    /// Create a grid with every cell set to no data
+    pub fn empty(header: AsciiGridHeader) -> Result<AsciiGrid, AsciiGridError> {
+        Ok(AsciiGrid {
+            values: vec![None; header.cell_count()?],
+            header,
+        })
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> Result<AsciiGrid, AsciiGridError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), AsciiGridError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<f64> {
+        self.values
+            .get(row * self.header.ncols + col)
+            .copied()
+            .flatten()
+    }
+}
+
+fn parse_header_value<T: FromStr>(
+    line: usize,
+    key: &str,
+    value: &str,
+) -> Result<T, AsciiGridError> {
+    value.parse().map_err(|_| AsciiGridError::InvalidHeader {
+        line,
+        key: key.to_owned(),
+        value: value.to_owned(),
+    })
+}
+
+fn origin_of_key(key: &str) -> GridOrigin {
+    if key.ends_with("center") {
+        GridOrigin::Center
+    } else {
+        GridOrigin::Corner
+    }
+}
+
+impl FromStr for AsciiGrid {
+    type Err = AsciiGridError;
+
+    /// Header keys are case insensitive and values may span any number of lines
+    fn from_str(data: &str) -> Result<AsciiGrid, AsciiGridError> {
+        let mut ncols = None;
+        let mut nrows = None;
+        let mut xll = None;
+        let mut yll = None;
+        let mut x_origin = None;
+        let mut y_origin = None;
+        let mut cellsize = None;
+        let mut nodata_value = None;
+
+        let mut lines = data.lines().enumerate().peekable();
+        while let Some((i, line)) = lines.peek() {
+            let starts_with_key = line
+                .trim_start()
+                .starts_with(|c: char| c.is_ascii_alphabetic());
+            if !starts_with_key {
+                break;
+            }
+            let line_number = i + 1;
+            let mut parts = line.split_whitespace();
+            let key = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let value = parts.next().unwrap_or_default();
+            match key.as_str() {
+                "ncols" => ncols = Some(parse_header_value(line_number, &key, value)?),
+                "nrows" => nrows = Some(parse_header_value(line_number, &key, value)?),
+                "xllcorner" | "xllcenter" => {
+                    x_origin = Some(origin_of_key(&key));
+                    xll = Some(parse_header_value(line_number, &key, value)?)
+                }
+                "yllcorner" | "yllcenter" => {
+                    y_origin = Some(origin_of_key(&key));
+                    yll = Some(parse_header_value(line_number, &key, value)?)
+                }
+                "cellsize" => cellsize = Some(parse_header_value(line_number, &key, value)?),
+                "nodata_value" => {
+                    nodata_value = Some(parse_header_value(line_number, &key, value)?)
+                }
+                _ => {
+                    return Err(AsciiGridError::InvalidHeader {
+                        line: line_number,
+                        key,
+                        value: value.to_owned(),
+                    })
+                }
+            }
+            lines.next();
+        }
+        let header = AsciiGridHeader {
+            ncols: ncols.ok_or(AsciiGridError::MissingHeader("ncols"))?,
+            nrows: nrows.ok_or(AsciiGridError::MissingHeader("nrows"))?,
+            xll: xll.ok_or(AsciiGridError::MissingHeader("xllcorner"))?,
+            yll: yll.ok_or(AsciiGridError::MissingHeader("yllcorner"))?,
+            origin: x_origin.unwrap_or(GridOrigin::Corner),
+            cellsize: cellsize.ok_or(AsciiGridError::MissingHeader("cellsize"))?,
+            nodata_value,
+        };
+
+        if x_origin != y_origin {
+            return Err(AsciiGridError::MixedOrigin);
+        }
+
+        // There are fewer values than characters so the header cannot reserve more
+        let expected = header.cell_count()?;
+        let mut values = Vec::with_capacity(expected.min(data.len()));
+        for (i, line) in lines {
+            for value in line.split_whitespace() {
+                let v = value
+                    .parse::<f64>()
+                    .map_err(|_| AsciiGridError::InvalidValue {
+                        line: i + 1,
+                        value: value.to_owned(),
+                    })?;
+                values.push(match header.nodata_value {
+                    Some(nodata) if v == nodata => None,
+                    _ => Some(v),
+                });
+            }
+        }
+        if values.len() != expected {
+            return Err(AsciiGridError::WrongValueCount {
+                expected,
+                found: values.len(),
+            });
+        }
+        Ok(AsciiGrid { header, values })
+    }
+}
+
+impl fmt::Display for AsciiGrid {
+    /// Grids with masked cells are written with the default no data value if
+    /// the header does not have one
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.header;
+        let (x_key, y_key) = match header.origin {
+            GridOrigin::Corner => ("xllcorner", "yllcorner"),
+            GridOrigin::Center => ("xllcenter", "yllcenter"),
+        };
+        let nodata_value = match header.nodata_value {
+            Some(v) => Some(v),
+            None if self.values.iter().any(Option::is_none) => Some(DEFAULT_NODATA_VALUE),
+            None => None,
+        };
+        writeln!(f, "ncols {}", header.ncols)?;
+        writeln!(f, "nrows {}", header.nrows)?;
+        writeln!(f, "{} {}", x_key, header.xll)?;
+        writeln!(f, "{} {}", y_key, header.yll)?;
+        writeln!(f, "cellsize {}", header.cellsize)?;
+        if let Some(nodata) = nodata_value {
+            writeln!(f, "NODATA_value {}", nodata)?;
+        }
+        for row in self.values.chunks(header.ncols.max(1)) {
+            let row: Vec<String> = row
+                .iter()
+                .map(|v| v.or(nodata_value).unwrap_or_default().to_string())
+                .collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Convert a grid value to a field value
+///
+/// Whole numbers are ints so they can set integer fields.
+fn field_value_from_grid(value: f64) -> FieldValue {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        FieldValue::Int(value as i64)
+    } else {
+        FieldValue::Float(value)
+    }
+}
+
+/// Cells loaded from grids with the grid row and column of each cell in cell order
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridCells<C: CellFields> {
+    pub cells: Vec<C>,
+    pub header: AsciiGridHeader,
+    pub grid_positions: Vec<(usize, usize)>,
+}
+
+/// Loads lattice cells from a grid per field
+#[derive(Debug, Clone, Default)]
+pub struct AsciiGridLoader {
+    field_grids: Vec<(AsciiGrid, String)>,
+}
+
+impl AsciiGridLoader {
+    pub fn new() -> AsciiGridLoader {
+        AsciiGridLoader::default()
+    }
+
+    /// Set the cell field from the grid
+    pub fn with_field(mut self, grid: AsciiGrid, field: &str) -> Self {
+        self.field_grids.push((grid, field.to_owned()));
+        self
+    }
+
+    /// Read the grid file and set the cell field from it
+    pub fn with_field_file(
+        self,
+        path: impl AsRef<Path>,
+        field: &str,
+    ) -> Result<Self, AsciiGridError> {
+        Ok(self.with_field(AsciiGrid::read_file(path)?, field))
+    }
+
+    pub fn load<C: CellFields>(&self) -> Result<GridCells<C>, AsciiGridError> {
+        let header = match self.field_grids.first() {
+            Some((grid, _)) => grid.header.clone(),
+            None => return Err(AsciiGridError::NoGrids),
+        };
+        for (grid, field) in self.field_grids.iter() {
+            if !grid.header.matches(&header) {
+                return Err(AsciiGridError::MismatchedGrid(field.clone()));
+            }
+        }
+        let mut loaded = GridCells {
+            cells: vec![],
+            header: header.clone(),
+            grid_positions: vec![],
+        };
+        for row in 0..header.nrows {
+            for col in 0..header.ncols {
+                let values: Option<Vec<f64>> = self
+                    .field_grids
+                    .iter()
+                    .map(|(grid, _)| grid.get(row, col))
+                    .collect();
+                let values = match values {
+                    Some(values) => values,
+                    None => continue,
+                };
+                let id = CellIndex(loaded.cells.len() as u32);
+                let mut cell = C::new_at(id, header.cell_centre(row, col));
+                for ((_, field), value) in self.field_grids.iter().zip(values) {
+                    cell.set_field_value(field, field_value_from_grid(value))
+                        .map_err(|error| AsciiGridError::Field { row, col, error })?;
+                }
+                loaded.cells.push(cell);
+                loaded.grid_positions.push((row, col));
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+/// Write a numeric cell field to a grid with the header
+///
+/// Each cell is placed in the grid cell containing its position. Cells outside
+/// the grid are ignored and grid cells without a cell have no data.
+pub fn cells_to_ascii_grid<C: CellStateBase + Introspect>(
+    cells: &[C],
+    header: &AsciiGridHeader,
+    field: &str,
+) -> Result<AsciiGrid, AsciiGridError> {
+    let mut grid = AsciiGrid::empty(header.clone())?;
+    for cell in cells.iter() {
+        let value = cell
+            .field_value(field)
+            .ok_or_else(|| AsciiGridError::UnknownField(field.to_owned()))?
+            .as_f64()
+            .ok_or_else(|| AsciiGridError::NotNumeric {
+                cell: cell.id(),
+                field: field.to_owned(),
+            })?;
+        if let Some((row, col)) = header.locate(cell.position()) {
+            grid.values[row * header.ncols + col] = Some(value);
+        }
+    }
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_state::CellState;
+    use geo::point;
+
+    const DEMO_GRID: &str = "ncols 3\n\
+                             nrows 2\n\
+                             xllcorner 100.0\n\
+                             yllcorner 200.0\n\
+                             cellsize 10\n\
+                             NODATA_value -9999\n\
+                             1 2 -9999\n\
+                             4 5.5 6\n";
+
+    #[test]
+    fn should_read_grid_headers_and_values() {
+        let grid: AsciiGrid = DEMO_GRID.parse().unwrap();
+        assert_eq!(grid.header.ncols, 3);
+        assert_eq!(grid.header.nrows, 2);
+        assert_eq!(grid.header.nodata_value, Some(-9999.0));
+        assert_eq!(grid.get(0, 2), None);
+        assert_eq!(grid.get(1, 1), Some(5.5));
+        assert_eq!(grid.header.cell_centre(0, 0), point!(x: 105.0, y: 215.0));
+        assert_eq!(grid.header.locate(point!(x: 125.0, y: 201.0)), Some((1, 2)));
+        assert_eq!(grid.header.locate(point!(x: 99.0, y: 201.0)), None);
+
+        let err = "ncols 2\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\n1\n"
+            .parse::<AsciiGrid>()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Expected 2 grid values but got 1");
+
+        let err = "ncols 2\nnrows 1\nxllcenter 0\nyllcorner 0\ncellsize 1\n1 2\n"
+            .parse::<AsciiGrid>()
+            .err()
+            .unwrap();
+        assert!(matches!(err, AsciiGridError::MixedOrigin));
+        let err = format!(
+            "ncols {}\nnrows 3\nxllcorner 0\nyllcorner 0\ncellsize 1\n1\n",
+            usize::MAX
+        )
+        .parse::<AsciiGrid>()
+        .err()
+        .unwrap();
+        assert!(matches!(err, AsciiGridError::TooLarge { nrows: 3, .. }));
+        let err = "ncols 1\nnrows 1\nxllcorner 0\ncellsize 1\n1\n"
+            .parse::<AsciiGrid>()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Missing header: yllcorner");
+    }
+
+    #[test]
+    fn should_load_cells_skipping_masked_grid_cells() {
+        let loaded: GridCells<CellState> = AsciiGridLoader::new()
+            .with_field(DEMO_GRID.parse().unwrap(), "population")
+            .load()
+            .unwrap();
+        assert_eq!(loaded.cells.len(), 5);
+        assert_eq!(loaded.cells[2].id, CellIndex(2));
+        assert_eq!(loaded.cells[2].position, point!(x: 105.0, y: 205.0));
+        assert_eq!(loaded.cells[2].population, 4);
+        assert_eq!(loaded.cells[3].population, 6);
+        assert_eq!(loaded.grid_positions[2], (1, 0));
+
+        let other = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 10\n0 0 0\n0 0 0\n";
+        let err = AsciiGridLoader::new()
+            .with_field(DEMO_GRID.parse().unwrap(), "population")
+            .with_field(other.parse().unwrap(), "population")
+            .load::<CellState>()
+            .err()
+            .unwrap();
+        assert!(matches!(err, AsciiGridError::MismatchedGrid(_)));
+    }
+
+    #[test]
+    fn should_write_fields_with_the_original_georeferencing() {
+        let grid: AsciiGrid = DEMO_GRID.parse().unwrap();
+        let loaded: GridCells<CellState> = AsciiGridLoader::new()
+            .with_field(grid.clone(), "population")
+            .load()
+            .unwrap();
+        let out = cells_to_ascii_grid(&loaded.cells, &loaded.header, "population").unwrap();
+        assert_eq!(
+            out.to_string(),
+            "ncols 3\nnrows 2\nxllcorner 100\nyllcorner 200\ncellsize 10\n\
+             NODATA_value -9999\n1 2 -9999\n4 6 6\n"
+        );
+        let reread: AsciiGrid = out.to_string().parse().unwrap();
+        assert_eq!(reread.header, grid.header);
+
+        let centred = AsciiGridHeader {
+            origin: GridOrigin::Center,
+            nodata_value: None,
+            ..grid.header
+        };
+        let out = AsciiGrid::empty(centred).unwrap().to_string();
+        assert!(out.contains("xllcenter 100\nyllcenter 200\n"));
+        assert!(out.contains("NODATA_value -9999\n"));
+    }
+}
@@ -0,0 +1,459 @@
+/// CSV Module
+///
+/// Read cells from CSV tables and write cells and networks to CSV.
+///
+/// Input tables have a row per cell with id, x and y columns. Other columns are
+/// mapped to cell fields by name. Values that cannot be read are reported with
+/// their line and column.
+///
+/// Cells are written with a row per cell, or with a row per cell per iteration
+/// in long format with a leading iteration column. The field columns are the
+/// fields described by the first cell. Networks are written as an
+/// edge list of source and target cell ids.
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::fields::FieldError;
+use crate::process_runner::fields::FieldValue;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::state::IterationState;
+use geo::Point;
+
+#[derive(Debug)]
+pub enum CsvError {
+    Io(std::io::Error),
+    Csv(Box<::csv::Error>),
+    MissingColumn(String),
+    /// The value in the column could not be read as the column type
+    Parse {
+        line: u64,
+        column: String,
+        value: String,
+        expected: &'static str,
+    },
+    Field {
+        line: u64,
+        column: String,
+        error: FieldError,
+    },
+    /// Cell ids must match the row order, starting at 0
+    CellId {
+        line: u64,
+        id: u32,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Io(e) => write!(f, "{}", e),
+            CsvError::Csv(e) => write!(f, "Invalid CSV: {}", e),
+            CsvError::MissingColumn(column) => write!(f, "Missing column: {}", column),
+            CsvError::Parse {
+                line,
+                column,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Line {} column {}: expected {} but got {:?}",
+                line, column, expected, value
+            ),
+            CsvError::Field {
+                line,
+                column,
+                error,
+            } => write!(f, "Line {} column {}: {}", line, column, error),
+            CsvError::CellId { line, id, expected } => write!(
+                f,
+                "Line {}: cell id {} does not match its row, expected {}",
+                line, id, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+impl From<std::io::Error> for CsvError {
+    fn from(e: std::io::Error) -> Self {
+        CsvError::Io(e)
+    }
+}
+
+impl From<::csv::Error> for CsvError {
+    fn from(e: ::csv::Error) -> Self {
+        CsvError::Csv(Box::new(e))
+    }
+}
+
+/// Read a CSV value as the most specific field value
+///
+/// Values are read as an int, float or bool before falling back to text.
+pub fn field_value_from_str(value: &str) -> FieldValue {
+    if let Ok(v) = value.parse::<i64>() {
+        FieldValue::Int(v)
+    } else if let Ok(v) = value.parse::<f64>() {
+        FieldValue::Float(v)
+    } else if let Ok(v) = value.parse::<bool>() {
+        FieldValue::Bool(v)
+    } else {
+        FieldValue::Text(value.to_owned())
+    }
+}
+
+/// Reads cells from a CSV table with a header row
+#[derive(Debug, Clone)]
+pub struct CsvCellReader {
+    id_column: String,
+    x_column: String,
+    y_column: String,
+    field_columns: Vec<(String, String)>,
+}
+
+impl Default for CsvCellReader {
+    fn default() -> Self {
+        CsvCellReader {
+            id_column: "id".to_owned(),
+            x_column: "x".to_owned(),
+            y_column: "y".to_owned(),
+            field_columns: vec![],
+        }
+    }
+}
+
+impl CsvCellReader {
+    pub fn new() -> CsvCellReader {
+        CsvCellReader::default()
+    }
+
+    pub fn with_id_column(mut self, column: &str) -> Self {
+        self.id_column = column.to_owned();
+        self
+    }
+
+    pub fn with_position_columns(mut self, x: &str, y: &str) -> Self {
+        self.x_column = x.to_owned();
+        self.y_column = y.to_owned();
+        self
+    }
+
+    /// Set the cell field from the column
+    ///
+    /// Empty values keep the default field value.
+    pub fn with_field(mut self, column: &str, field: &str) -> Self {
+        self.field_columns
+            .push((column.to_owned(), field.to_owned()));
+        self
+    }
+
+    pub fn read_file<C: CellFields>(&self, path: impl AsRef<Path>) -> Result<Vec<C>, CsvError> {
+        self.read(File::open(path)?)
+    }
+
+    pub fn read_str<C: CellFields>(&self, data: &str) -> Result<Vec<C>, CsvError> {
+        self.read(data.as_bytes())
+    }
+
+    pub fn read<C: CellFields>(&self, reader: impl std::io::Read) -> Result<Vec<C>, CsvError> {
+        let mut reader = ::csv::ReaderBuilder::new()
+            .trim(::csv::Trim::All)
+            .from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let column_index = |column: &str| {
+            headers
+                .iter()
+                .position(|h| h == column)
+                .ok_or_else(|| CsvError::MissingColumn(column.to_owned()))
+        };
+        let id_index = column_index(&self.id_column)?;
+        let x_index = column_index(&self.x_column)?;
+        let y_index = column_index(&self.y_column)?;
+        let field_indexes = self
+            .field_columns
+            .iter()
+            .map(|(column, field)| Ok((column_index(column)?, column, field)))
+            .collect::<Result<Vec<_>, CsvError>>()?;
+
+        let mut cells = vec![];
+        for record in reader.records() {
+            let record = record?;
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            let value = |index: usize| record.get(index).unwrap_or_default();
+            let parse_error = |index: usize, expected| CsvError::Parse {
+                line,
+                column: headers[index].to_owned(),
+                value: value(index).to_owned(),
+                expected,
+            };
+            let id = value(id_index)
+                .parse::<u32>()
+                .map_err(|_| parse_error(id_index, "an id"))?;
+            if id as usize != cells.len() {
+                return Err(CsvError::CellId {
+                    line,
+                    id,
+                    expected: cells.len(),
+                });
+            }
+            let x = value(x_index)
+                .parse::<f64>()
+                .map_err(|_| parse_error(x_index, "a number"))?;
+            let y = value(y_index)
+                .parse::<f64>()
+                .map_err(|_| parse_error(y_index, "a number"))?;
+            let mut cell = C::new_at(CellIndex(id), Point::new(x, y));
+            for (index, column, field) in field_indexes.iter() {
+                if value(*index).is_empty() {
+                    continue;
+                }
+                cell.set_field_value(field, field_value_from_str(value(*index)))
+                    .map_err(|error| CsvError::Field {
+                        line,
+                        column: column.to_string(),
+                        error,
+                    })?;
+            }
+            cells.push(cell);
+        }
+        Ok(cells)
+    }
+}
+
+/// The id, x, y and field columns of the cells, with fields described by the first cell
+fn cell_header<C: Introspect>(first: Option<&C>) -> Vec<String> {
+    let mut header = vec!["id".to_owned(), "x".to_owned(), "y".to_owned()];
+    if let Some(cell) = first {
+        header.extend(cell.describe_fields().into_iter().map(|field| field.name));
+    }
+    header
+}
+
+fn cell_row<C: CellStateBase + Introspect>(cell: &C, header: &[String]) -> Vec<String> {
+    let mut row = vec![
+        cell.id().to_string(),
+        cell.position().x().to_string(),
+        cell.position().y().to_string(),
+    ];
+    row.extend(header.iter().skip(3).map(|name| {
+        cell.field_value(name)
+            .map(|v| v.to_string())
+            .unwrap_or_default()
+    }));
+    row
+}
+
+/// Write the cells as CSV with id, x, y and field columns
+///
+/// The output can be read back with a `CsvCellReader` with a field for each column.
+pub fn cells_to_csv<C: CellStateBase + Introspect>(cells: &[C]) -> Result<String, CsvError> {
+    let mut writer = ::csv::Writer::from_writer(vec![]);
+    let header = cell_header(cells.first());
+    writer.write_record(&header)?;
+    for cell in cells.iter() {
+        writer.write_record(cell_row(cell, &header))?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| CsvError::Io(e.into_error()))?;
+    Ok(String::from_utf8(bytes).expect("CSV output is utf8"))
+}
+
+/// Write the network as an edge list with a source and target column
+///
+/// The network is in cell order as in `IterationState`.
+pub fn network_to_csv<C: CellStateBase>(
+    cells: &[C],
+    network: &[Vec<CellIndex>],
+) -> Result<String, CsvError> {
+    let mut writer = ::csv::Writer::from_writer(vec![]);
+    writer.write_record(["source", "target"])?;
+    for (cell, neighbours) in cells.iter().zip(network.iter()) {
+        for neighbour in neighbours.iter() {
+            writer.write_record(&[cell.id().to_string(), neighbour.to_string()])?;
+        }
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| CsvError::Io(e.into_error()))?;
+    Ok(String::from_utf8(bytes).expect("CSV output is utf8"))
+}
+
+/// An observer that writes the cells of every iteration in long format
+///
+/// Each row is an iteration number followed by the cell columns.
+#[derive(Debug)]
+pub struct CsvSeries<W: Write> {
+    writer: ::csv::Writer<W>,
+    header: Option<Vec<String>>,
+}
+
+impl CsvSeries<File> {
+    pub fn create(path: impl AsRef<Path>) -> Result<CsvSeries<File>, CsvError> {
+        Ok(CsvSeries::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> CsvSeries<W> {
+    pub fn new(writer: W) -> CsvSeries<W> {
+        CsvSeries {
+            writer: ::csv::Writer::from_writer(writer),
+            header: None,
+        }
+    }
+
+    /// The columns are set by the first recorded iteration
+    pub fn record<C: CellStateBase + Introspect, G: GlobalStateBase>(
+        &mut self,
+        iteration: u32,
+        state: &IterationState<C, G>,
+    ) -> Result<(), CsvError> {
+        if self.header.is_none() {
+            let header = cell_header(state.cells.first());
+            let mut record = vec!["iteration".to_owned()];
+            record.extend(header.iter().cloned());
+            self.writer.write_record(record)?;
+            self.header = Some(header);
+        }
+        let header = self.header.as_ref().expect("Header is written");
+        for cell in state.cells.iter() {
+            let mut row = vec![iteration.to_string()];
+            row.extend(cell_row(cell, header));
+            self.writer.write_record(row)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the rows and return the inner writer
+    pub fn finish(self) -> Result<W, CsvError> {
+        self.writer
+            .into_inner()
+            .map_err(|e| CsvError::Io(e.into_error()))
+    }
+}
+
+impl<C, G, W> Observer<C, G> for CsvSeries<W>
+where
+    C: CellStateBase + Introspect,
+    G: GlobalStateBase,
+    W: Write,
+{
+    /// Panics if the rows cannot be written
+    fn observe(&mut self, iteration: u32, state: &IterationState<C, G>) {
+        if let Err(e) = self.record(iteration, state) {
+            panic!("Failed to write CSV series: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_state::CellState;
+    use crate::process_runner::examples::example_state::GlobalState;
+    use geo::point;
+
+    const DEMO_TABLE: &str = "id,x,y,pop,name\n\
+                              0,1.5,-2.0,120,North\n\
+                              1, 3.0 ,0.5,,South\n";
+
+    #[test]
+    fn should_read_cells_with_mapped_columns() {
+        let cells: Vec<CellState> = CsvCellReader::new()
+            .with_field("pop", "population")
+            .read_str(DEMO_TABLE)
+            .unwrap();
+        assert_eq!(cells.len(), 2);
+        assert_eq!(cells[0].id, CellIndex(0));
+        assert_eq!(cells[0].position, point!(x: 1.5, y: -2.0));
+        assert_eq!(cells[0].population, 120);
+        assert_eq!(cells[1].position, point!(x: 3.0, y: 0.5));
+        assert_eq!(cells[1].population, 0);
+    }
+
+    #[test]
+    fn should_report_the_line_and_column_of_invalid_values() {
+        let err = CsvCellReader::new()
+            .read_str::<CellState>("id,x,y\n0,1.0,2.0\n1,east,2.0\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Line 3 column x: expected a number but got \"east\""
+        );
+        let err = CsvCellReader::new()
+            .with_field("name", "population")
+            .read_str::<CellState>(DEMO_TABLE)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Line 2 column name: Field population expects a int value but got text"
+        );
+        let err = CsvCellReader::new()
+            .with_field("jobs", "population")
+            .read_str::<CellState>(DEMO_TABLE)
+            .err()
+            .unwrap();
+        assert!(matches!(err, CsvError::MissingColumn(c) if c == "jobs"));
+    }
+
+    #[test]
+    fn should_reject_ids_that_do_not_match_the_row_order() {
+        let err = CsvCellReader::new()
+            .read_str::<CellState>("id,x,y\n0,1.0,2.0\n2,1.0,3.0\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Line 3: cell id 2 does not match its row, expected 1"
+        );
+    }
+
+    #[test]
+    fn should_write_cells_that_can_be_read_back() {
+        let cells = vec![
+            CellState::new(0, point!(x: 0.5, y: 1.0), 10),
+            CellState::new(1, point!(x: -3.0, y: 2.25), 20),
+        ];
+        let csv = cells_to_csv(&cells).unwrap();
+        assert_eq!(csv, "id,x,y,population\n0,0.5,1,10\n1,-3,2.25,20\n");
+        let read: Vec<CellState> = CsvCellReader::new()
+            .with_field("population", "population")
+            .read_str(&csv)
+            .unwrap();
+        assert_eq!(read, cells);
+    }
+
+    #[test]
+    fn should_write_long_format_series_and_edge_lists() {
+        let state = IterationState {
+            global_state: GlobalState::default(),
+            cells: vec![
+                CellState::new(0, point!(x: 0.0, y: 0.0), 1),
+                CellState::new(1, point!(x: 1.0, y: 0.0), 2),
+            ],
+            network: vec![vec![CellIndex(1)], vec![CellIndex(0)]],
+        };
+        let mut series = CsvSeries::new(vec![]);
+        series.observe(1, &state);
+        series.observe(2, &state);
+        let out = String::from_utf8(series.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "iteration,id,x,y,population");
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[4], "2,1,1,0,2");
+        assert_eq!(
+            network_to_csv(&state.cells, &state.network).unwrap(),
+            "source,target\n0,1\n1,0\n"
+        );
+    }
+}
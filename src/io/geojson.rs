@@ -0,0 +1,508 @@
+/// GeoJSON Module
+///
+/// Build cells from the features of a GeoJSON FeatureCollection and write
+/// model states back out as FeatureCollections.
+///
+/// Each feature becomes a cell positioned at the centroid of its geometry with
+/// the feature index as its id. The geometry and an optional label property are
+/// kept alongside the cells, and chosen feature properties are set on cell
+/// fields.
+///
+/// On export each cell becomes a feature with its fields as properties. The
+/// global state is written to the `global_state` foreign member of the
+/// collection.
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::fields::FieldError;
+use crate::process_runner::fields::FieldValue;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::state::IterationState;
+use ::geojson::feature::Id;
+use ::geojson::Feature;
+use ::geojson::FeatureCollection;
+use ::geojson::GeoJson;
+use geo::algorithm::centroid::Centroid;
+use geo::Geometry;
+use geo::MultiPoint;
+use geo::Point;
+use serde::Serialize;
+use serde_json::Map as JsonMap;
+use serde_json::Value as JsonValue;
+
+#[derive(Debug)]
+pub enum GeoJsonError {
+    Io(std::io::Error),
+    Parse(Box<::geojson::Error>),
+    NotAFeatureCollection,
+    /// The feature at the index has no geometry
+    MissingGeometry(usize),
+    /// The geometry of the feature at the index has no centroid
+    EmptyGeometry(usize),
+    /// The property is not a number, bool or string
+    InvalidProperty {
+        feature: usize,
+        property: String,
+    },
+    Field {
+        feature: usize,
+        error: FieldError,
+    },
+}
+
+impl fmt::Display for GeoJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoJsonError::Io(e) => write!(f, "{}", e),
+            GeoJsonError::Parse(e) => write!(f, "Invalid GeoJSON: {}", e),
+            GeoJsonError::NotAFeatureCollection => write!(f, "Expected a FeatureCollection"),
+            GeoJsonError::MissingGeometry(i) => write!(f, "Feature {} has no geometry", i),
+            GeoJsonError::EmptyGeometry(i) => write!(f, "Feature {} has an empty geometry", i),
+            GeoJsonError::InvalidProperty { feature, property } => write!(
+                f,
+                "Feature {} property {} is not a number, bool or string",
+                feature, property
+            ),
+            GeoJsonError::Field { feature, error } => write!(f, "Feature {}: {}", feature, error),
+        }
+    }
+}
+
+impl std::error::Error for GeoJsonError {}
+
+impl From<std::io::Error> for GeoJsonError {
+    fn from(e: std::io::Error) -> Self {
+        GeoJsonError::Io(e)
+    }
+}
+
+impl From<::geojson::Error> for GeoJsonError {
+    fn from(e: ::geojson::Error) -> Self {
+        GeoJsonError::Parse(Box::new(e))
+    }
+}
+
+/// The centroid of a geometry or None if it is empty
+///
+/// The centroid of a collection is the mean of the centroids of its members.
+fn geometry_centroid(geometry: &Geometry<f64>) -> Option<Point<f64>> {
+    match geometry {
+        Geometry::Point(point) => Some(point.centroid()),
+        Geometry::Line(line) => Some(line.centroid()),
+        Geometry::LineString(line_string) => line_string.centroid(),
+        Geometry::Polygon(polygon) => polygon.centroid(),
+        Geometry::MultiPoint(multi_point) => multi_point.centroid(),
+        Geometry::MultiLineString(multi_line_string) => multi_line_string.centroid(),
+        Geometry::MultiPolygon(multi_polygon) => multi_polygon.centroid(),
+        Geometry::GeometryCollection(collection) => {
+            let centroids: MultiPoint<f64> =
+                collection.iter().filter_map(geometry_centroid).collect();
+            centroids.centroid()
+        }
+        Geometry::Rect(rect) => Some(rect.centroid()),
+        Geometry::Triangle(triangle) => triangle.to_polygon().centroid(),
+    }
+}
+
+/// Cells loaded from GeoJSON with the geometry and label of each cell in cell order
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoJsonCells<C: CellFields> {
+    pub cells: Vec<C>,
+    pub geometries: Vec<Geometry<f64>>,
+    pub labels: Vec<Option<String>>,
+}
+
+/// Convert a JSON property to a field value
+///
+/// Null is treated as a missing property.
+fn field_value_from_json(value: &JsonValue) -> Option<Result<FieldValue, ()>> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::Bool(v) => Some(Ok(FieldValue::Bool(*v))),
+        JsonValue::Number(v) => Some(Ok(match v.as_i64() {
+            Some(v) => FieldValue::Int(v),
+            None => FieldValue::Float(v.as_f64().unwrap_or(f64::NAN)),
+        })),
+        JsonValue::String(v) => Some(Ok(FieldValue::Text(v.clone()))),
+        _ => Some(Err(())),
+    }
+}
+
+/// Loads cells from a GeoJSON FeatureCollection
+#[derive(Debug, Clone, Default)]
+pub struct GeoJsonLoader {
+    label_property: Option<String>,
+    field_properties: Vec<(String, String)>,
+}
+
+impl GeoJsonLoader {
+    pub fn new() -> GeoJsonLoader {
+        GeoJsonLoader::default()
+    }
+
+    /// Keep the property as the label of each cell
+    pub fn with_label_property(mut self, property: &str) -> Self {
+        self.label_property = Some(property.to_owned());
+        self
+    }
+
+    /// Set the cell field from the feature property
+    ///
+    /// Features without the property keep the default field value.
+    pub fn with_field(mut self, property: &str, field: &str) -> Self {
+        self.field_properties
+            .push((property.to_owned(), field.to_owned()));
+        self
+    }
+
+    pub fn load_file<C: CellFields>(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<GeoJsonCells<C>, GeoJsonError> {
+        self.load_str(&fs::read_to_string(path)?)
+    }
+
+    pub fn load_str<C: CellFields>(&self, data: &str) -> Result<GeoJsonCells<C>, GeoJsonError> {
+        let collection = match data.parse::<GeoJson>()? {
+            GeoJson::FeatureCollection(collection) => collection,
+            _ => return Err(GeoJsonError::NotAFeatureCollection),
+        };
+        let mut loaded = GeoJsonCells {
+            cells: Vec::with_capacity(collection.features.len()),
+            geometries: Vec::with_capacity(collection.features.len()),
+            labels: Vec::with_capacity(collection.features.len()),
+        };
+        for (i, feature) in collection.features.into_iter().enumerate() {
+            let geometry = feature.geometry.ok_or(GeoJsonError::MissingGeometry(i))?;
+            let geometry: Geometry<f64> = geometry.value.try_into()?;
+            let centroid = geometry_centroid(&geometry).ok_or(GeoJsonError::EmptyGeometry(i))?;
+            let properties = feature.properties.unwrap_or_default();
+            let mut cell = C::new_at(CellIndex(i as u32), centroid);
+            for (property, field) in self.field_properties.iter() {
+                let value = match properties.get(property).and_then(field_value_from_json) {
+                    Some(Ok(value)) => value,
+                    Some(Err(())) => {
+                        return Err(GeoJsonError::InvalidProperty {
+                            feature: i,
+                            property: property.clone(),
+                        })
+                    }
+                    None => continue,
+                };
+                cell.set_field_value(field, value)
+                    .map_err(|error| GeoJsonError::Field { feature: i, error })?;
+            }
+            let label = self
+                .label_property
+                .as_ref()
+                .and_then(|property| properties.get(property))
+                .and_then(|value| match value {
+                    JsonValue::String(s) => Some(s.clone()),
+                    JsonValue::Null => None,
+                    value => Some(value.to_string()),
+                });
+            loaded.cells.push(cell);
+            loaded.geometries.push(geometry);
+            loaded.labels.push(label);
+        }
+        Ok(loaded)
+    }
+}
+
+/// Convert a field value to a JSON property
+///
+/// Non finite floats are written as null.
+pub fn field_value_to_json(value: FieldValue) -> JsonValue {
+    match value {
+        FieldValue::Int(v) => JsonValue::from(v),
+        FieldValue::Float(v) => JsonValue::from(v),
+        FieldValue::Bool(v) => JsonValue::from(v),
+        FieldValue::Text(v) => JsonValue::from(v),
+    }
+}
+
+/// Convert a cell to a feature with its id and fields as properties
+///
+/// The geometry is a point at the cell position unless one is given.
+pub fn cell_to_feature<C: CellStateBase + Introspect>(
+    cell: &C,
+    geometry: Option<&Geometry<f64>>,
+) -> Feature {
+    let id = u32::from(cell.id());
+    let geometry = match geometry {
+        Some(geometry) => geometry.clone(),
+        None => Geometry::Point(cell.position()),
+    };
+    let mut properties = JsonMap::new();
+    properties.insert("id".to_owned(), JsonValue::from(id));
+    for (name, value) in cell.field_values() {
+        properties.insert(name, field_value_to_json(value));
+    }
+    Feature {
+        bbox: None,
+        geometry: Some(::geojson::Geometry::new(::geojson::Value::from(&geometry))),
+        id: Some(Id::Number(id.into())),
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+/// Convert a model state to a FeatureCollection
+///
+/// Geometries are looked up by cell id, as assigned by the loader, so cells
+/// are exported with the polygons they were loaded from. Cells without a
+/// geometry are exported as points.
+pub fn state_to_feature_collection<C, G>(
+    state: &IterationState<C, G>,
+    geometries: Option<&[Geometry<f64>]>,
+) -> Result<FeatureCollection, serde_json::Error>
+where
+    C: CellStateBase + Introspect,
+    G: GlobalStateBase + Serialize,
+{
+    let features = state
+        .cells
+        .iter()
+        .map(|cell| {
+            let geometry = geometries.and_then(|g| g.get(u32::from(cell.id()) as usize));
+            cell_to_feature(cell, geometry)
+        })
+        .collect();
+    let mut foreign_members = JsonMap::new();
+    foreign_members.insert(
+        "global_state".to_owned(),
+        serde_json::to_value(&state.global_state)?,
+    );
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: Some(foreign_members),
+    })
+}
+
+/// Write a model state to a GeoJSON string
+pub fn state_to_geojson<C, G>(
+    state: &IterationState<C, G>,
+    geometries: Option<&[Geometry<f64>]>,
+) -> Result<String, serde_json::Error>
+where
+    C: CellStateBase + Introspect,
+    G: GlobalStateBase + Serialize,
+{
+    Ok(GeoJson::from(state_to_feature_collection(state, geometries)?).to_string())
+}
+
+/// An observer that keeps a FeatureCollection of each iteration
+///
+/// Each collection has the iteration number in its `iteration` foreign member.
+#[derive(Debug, Clone, Default)]
+pub struct GeoJsonSeries {
+    geometries: Option<Vec<Geometry<f64>>>,
+    collections: Vec<FeatureCollection>,
+}
+
+impl GeoJsonSeries {
+    pub fn new() -> GeoJsonSeries {
+        GeoJsonSeries::default()
+    }
+
+    /// Export cells with these geometries, e.g. from `GeoJsonCells`
+    pub fn with_geometries(mut self, geometries: Vec<Geometry<f64>>) -> Self {
+        self.geometries = Some(geometries);
+        self
+    }
+
+    pub fn record<C, G>(
+        &mut self,
+        iteration: u32,
+        state: &IterationState<C, G>,
+    ) -> Result<(), serde_json::Error>
+    where
+        C: CellStateBase + Introspect,
+        G: GlobalStateBase + Serialize,
+    {
+        let mut collection = state_to_feature_collection(state, self.geometries.as_deref())?;
+        collection
+            .foreign_members
+            .get_or_insert_with(JsonMap::new)
+            .insert("iteration".to_owned(), JsonValue::from(iteration));
+        self.collections.push(collection);
+        Ok(())
+    }
+
+    pub fn collections(&self) -> &[FeatureCollection] {
+        &self.collections
+    }
+
+    pub fn len(&self) -> usize {
+        self.collections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collections.is_empty()
+    }
+
+    /// Export the series as a JSON array of FeatureCollections in iteration order
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self.collections)
+    }
+
+    /// Write each collection to `{prefix}_{iteration}.geojson` in the directory
+    pub fn write_dir(&self, dir: impl AsRef<Path>, prefix: &str) -> Result<(), GeoJsonError> {
+        fs::create_dir_all(&dir)?;
+        for collection in self.collections.iter() {
+            let iteration = collection
+                .foreign_members
+                .as_ref()
+                .and_then(|m| m.get("iteration"))
+                .and_then(|i| i.as_u64())
+                .unwrap_or_default();
+            let path = dir
+                .as_ref()
+                .join(format!("{}_{}.geojson", prefix, iteration));
+            fs::write(path, GeoJson::from(collection.clone()).to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl<C, G> Observer<C, G> for GeoJsonSeries
+where
+    C: CellStateBase + Introspect,
+    G: GlobalStateBase + Serialize,
+{
+    /// Panics if the global state cannot be serialized
+    fn observe(&mut self, iteration: u32, state: &IterationState<C, G>) {
+        self.record(iteration, state)
+            .expect("Global state can be serialized");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_state::CellState;
+    use crate::process_runner::examples::example_state::GlobalState;
+    use geo::point;
+
+    const DEMO_COLLECTION: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0], [0.0, 0.0]]]
+                },
+                "properties": {"NAME": "North", "pop": 120}
+            },
+            {
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [5.0, -1.0]},
+                "properties": {"NAME": "South", "pop": null}
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn should_load_cells_at_feature_centroids() {
+        let loaded: GeoJsonCells<CellState> = GeoJsonLoader::new()
+            .with_label_property("NAME")
+            .with_field("pop", "population")
+            .load_str(DEMO_COLLECTION)
+            .unwrap();
+        assert_eq!(loaded.cells.len(), 2);
+        assert_eq!(loaded.cells[0].id, CellIndex(0));
+        assert_eq!(loaded.cells[0].position, point!(x: 1.0, y: 1.0));
+        assert_eq!(loaded.cells[0].population, 120);
+        assert_eq!(loaded.cells[1].position, point!(x: 5.0, y: -1.0));
+        assert_eq!(loaded.cells[1].population, 0);
+        assert!(matches!(loaded.geometries[0], Geometry::Polygon(_)));
+        assert_eq!(
+            loaded.labels,
+            vec![Some("North".to_owned()), Some("South".to_owned())]
+        );
+    }
+
+    #[test]
+    fn should_report_invalid_fields_with_the_feature() {
+        let err = GeoJsonLoader::new()
+            .with_field("NAME", "population")
+            .load_str::<CellState>(DEMO_COLLECTION)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Feature 0: Field population expects a int value but got text"
+        );
+    }
+
+    #[test]
+    fn should_export_cells_with_loaded_geometries() {
+        let loaded: GeoJsonCells<CellState> = GeoJsonLoader::new()
+            .with_field("pop", "population")
+            .load_str(DEMO_COLLECTION)
+            .unwrap();
+        let mut cells = loaded.cells.clone();
+        cells.push(CellState::new(2, point!(x: 9.0, y: 9.0), 3));
+        let state = IterationState {
+            global_state: GlobalState::new(5),
+            cells,
+            network: vec![vec![]],
+        };
+        let collection = state_to_feature_collection(&state, Some(&loaded.geometries)).unwrap();
+        assert_eq!(collection.features.len(), 3);
+        let properties = collection.features[0].properties.as_ref().unwrap();
+        assert_eq!(properties["id"], JsonValue::from(0));
+        assert_eq!(properties["population"], JsonValue::from(120));
+        let geometry_type = |i: usize| {
+            let geometry = collection.features[i].geometry.clone().unwrap();
+            let geometry: Geometry<f64> = geometry.value.try_into().unwrap();
+            geometry
+        };
+        assert!(matches!(geometry_type(0), Geometry::Polygon(_)));
+        assert_eq!(geometry_type(2), Geometry::Point(point!(x: 9.0, y: 9.0)));
+        assert_eq!(
+            collection.foreign_members.unwrap()["global_state"]["population"],
+            JsonValue::from(5)
+        );
+
+        let reloaded: GeoJsonCells<CellState> = GeoJsonLoader::new()
+            .with_field("population", "population")
+            .load_str(&state_to_geojson(&state, None).unwrap())
+            .unwrap();
+        assert_eq!(reloaded.cells, state.cells);
+    }
+
+    #[test]
+    fn should_record_a_collection_per_iteration() {
+        let mut series = GeoJsonSeries::new();
+        let state = IterationState {
+            global_state: GlobalState::default(),
+            cells: vec![CellState::new(0, point!(x: 0.0, y: 0.0), 1)],
+            network: vec![vec![]],
+        };
+        series.observe(1, &state);
+        series.observe(2, &state);
+        assert_eq!(series.len(), 2);
+        let json: JsonValue = serde_json::from_str(&series.to_json().unwrap()).unwrap();
+        assert_eq!(json[1]["iteration"], JsonValue::from(2));
+        assert_eq!(json[1]["type"], JsonValue::from("FeatureCollection"));
+    }
+
+    #[test]
+    fn should_reject_other_geojson_objects() {
+        let err = GeoJsonLoader::new()
+            .load_str::<CellState>(r#"{"type": "Point", "coordinates": [0.0, 0.0]}"#)
+            .err()
+            .unwrap();
+        assert!(matches!(err, GeoJsonError::NotAFeatureCollection));
+    }
+}
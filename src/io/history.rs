@@ -0,0 +1,735 @@
+/// History Module
+///
+/// An append-only binary log of every iteration of a run that can be read back
+/// at any iteration without keeping the run in memory.
+///
+/// The file starts with a magic header followed by a frame per iteration. A
+/// frame is a kind byte, the iteration, the payload length and the bincode
+/// payload. Keyframes hold the full state. Delta frames hold the global state,
+/// the cell count, the cells that changed since the previous iteration and the
+/// network if it changed. A keyframe is written every `keyframe_interval`
+/// iterations so reading an iteration decodes at most that many frames.
+///
+/// When the writer is finished an index frame listing the offset of every frame
+/// is appended, followed by the offset of the index and a magic trailer. Files
+/// without an index, e.g. from a run that did not finish, are indexed by
+/// scanning the frame headers.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::network::CellNetwork;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::state::IterationState;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+
+const MAGIC: &[u8; 8] = b"CAHIST01";
+const INDEX_MAGIC: &[u8; 8] = b"CAHIDX01";
+const FRAME_HEADER_LEN: u64 = 13;
+const TRAILER_LEN: u64 = 16;
+
+pub const DEFAULT_KEYFRAME_INTERVAL: u32 = 100;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(std::io::Error),
+    Encoding(Box<bincode::ErrorKind>),
+    NotAHistory,
+    /// The frame at the offset has an unknown kind or runs past the end of the file
+    Corrupt(u64),
+    MissingIteration(u32),
+    UnknownField(String),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::Io(e) => write!(f, "{}", e),
+            HistoryError::Encoding(e) => write!(f, "Invalid history frame: {}", e),
+            HistoryError::NotAHistory => write!(f, "Not a history file"),
+            HistoryError::Corrupt(offset) => write!(f, "Corrupt history frame at byte {}", offset),
+            HistoryError::MissingIteration(i) => write!(f, "Iteration {} is not in the history", i),
+            HistoryError::UnknownField(field) => write!(f, "Unknown field: {}", field),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<std::io::Error> for HistoryError {
+    fn from(e: std::io::Error) -> Self {
+        HistoryError::Io(e)
+    }
+}
+
+impl From<Box<bincode::ErrorKind>> for HistoryError {
+    fn from(e: Box<bincode::ErrorKind>) -> Self {
+        HistoryError::Encoding(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameKind {
+    Keyframe,
+    Delta,
+    Index,
+}
+
+impl FrameKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            FrameKind::Keyframe => 0,
+            FrameKind::Delta => 1,
+            FrameKind::Index => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<FrameKind> {
+        match byte {
+            0 => Some(FrameKind::Keyframe),
+            1 => Some(FrameKind::Delta),
+            2 => Some(FrameKind::Index),
+            _ => None,
+        }
+    }
+}
+
+/// The position of an iteration frame in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub iteration: u32,
+    pub kind: FrameKind,
+    /// The offset of the frame header
+    pub offset: u64,
+}
+
+/// Written with references to the current state and read back owned
+#[derive(Serialize, Deserialize)]
+struct Delta<C, G, N> {
+    global_state: G,
+    cell_count: u32,
+    changed: Vec<(u32, C)>,
+    network: Option<N>,
+}
+
+/// The leading global state of keyframe and delta payloads
+///
+/// Both payloads start with the global state so it can be read without
+/// decoding the cells.
+#[derive(Deserialize)]
+struct GlobalHead<G> {
+    global_state: G,
+}
+
+/// Writes an iteration frame for each observed iteration
+///
+/// Call `finish` at the end of the run to write the index.
+/// As an observer the first error writing a frame is kept, no further frames
+/// are written and the error is returned by `flush` and `finish`.
+#[derive(Debug)]
+pub struct HistoryWriter<C, G, W: Write>
+where
+    C: CellStateBase,
+    G: GlobalStateBase,
+{
+    writer: W,
+    offset: u64,
+    keyframe_interval: u32,
+    frames_since_keyframe: u32,
+    previous: Option<IterationState<C, G>>,
+    index: Vec<IndexEntry>,
+    error: Option<HistoryError>,
+}
+
+impl<C, G> HistoryWriter<C, G, BufWriter<File>>
+where
+    C: CellStateBase + PartialEq + Serialize,
+    G: GlobalStateBase + Serialize,
+{
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        HistoryWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<C, G, W> HistoryWriter<C, G, W>
+where
+    C: CellStateBase + PartialEq + Serialize,
+    G: GlobalStateBase + Serialize,
+    W: Write,
+{
+    pub fn new(mut writer: W) -> Result<Self, HistoryError> {
+        writer.write_all(MAGIC)?;
+        Ok(HistoryWriter {
+            writer,
+            offset: MAGIC.len() as u64,
+            keyframe_interval: DEFAULT_KEYFRAME_INTERVAL,
+            frames_since_keyframe: 0,
+            previous: None,
+            index: vec![],
+            error: None,
+        })
+    }
+
+    /// Write a full state every interval iterations
+    ///
+    /// Shorter intervals make random reads faster and the file larger.
+    pub fn with_keyframe_interval(mut self, interval: u32) -> Self {
+        self.keyframe_interval = interval.max(1);
+        self
+    }
+
+    fn write_frame(
+        &mut self,
+        kind: FrameKind,
+        iteration: u32,
+        payload: &[u8],
+    ) -> Result<(), HistoryError> {
+        self.writer.write_all(&[kind.to_byte()])?;
+        self.writer.write_all(&iteration.to_le_bytes())?;
+        self.writer
+            .write_all(&(payload.len() as u64).to_le_bytes())?;
+        self.writer.write_all(payload)?;
+        if kind != FrameKind::Index {
+            self.index.push(IndexEntry {
+                iteration,
+                kind,
+                offset: self.offset,
+            });
+        }
+        self.offset += FRAME_HEADER_LEN + payload.len() as u64;
+        Ok(())
+    }
+
+    pub fn record(
+        &mut self,
+        iteration: u32,
+        state: &IterationState<C, G>,
+    ) -> Result<(), HistoryError> {
+        let keyframe = match self.previous.as_ref() {
+            Some(_) => self.frames_since_keyframe + 1 >= self.keyframe_interval,
+            None => true,
+        };
+        if keyframe {
+            let payload = bincode::serialize(state)?;
+            self.write_frame(FrameKind::Keyframe, iteration, &payload)?;
+            self.frames_since_keyframe = 0;
+        } else {
+            let previous = self.previous.as_ref().expect("Previous state is kept");
+            let changed = state
+                .cells
+                .iter()
+                .enumerate()
+                .filter(|(i, cell)| previous.cells.get(*i) != Some(*cell))
+                .map(|(i, cell)| (i as u32, cell))
+                .collect::<Vec<_>>();
+            let delta = Delta {
+                global_state: &state.global_state,
+                cell_count: state.cells.len() as u32,
+                changed,
+                network: if previous.network == state.network {
+                    None
+                } else {
+                    Some(&state.network)
+                },
+            };
+            let payload = bincode::serialize(&delta)?;
+            self.write_frame(FrameKind::Delta, iteration, &payload)?;
+            self.frames_since_keyframe += 1;
+        }
+        self.previous = Some(state.clone());
+        Ok(())
+    }
+
+    /// Flush the written frames, returning the error kept while observing
+    pub fn flush(&mut self) -> Result<(), HistoryError> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the index and return the inner writer
+    pub fn finish(mut self) -> Result<W, HistoryError> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let index_offset = self.offset;
+        let payload = bincode::serialize(&self.index)?;
+        self.write_frame(FrameKind::Index, 0, &payload)?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.write_all(INDEX_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<C, G, W> Observer<C, G> for HistoryWriter<C, G, W>
+where
+    C: CellStateBase + PartialEq + Serialize,
+    G: GlobalStateBase + Serialize,
+    W: Write,
+{
+    /// Keeps the error if the frame cannot be written
+    fn observe(&mut self, iteration: u32, state: &IterationState<C, G>) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.record(iteration, state) {
+            self.error = Some(e);
+        }
+    }
+}
+
+/// Reads iterations from a history file
+///
+/// Only the index is kept in memory; frames are read when an iteration is
+/// requested.
+#[derive(Debug)]
+pub struct HistoryReader<C, G, R> {
+    reader: R,
+    /// The length of the file, which bounds the length of frames
+    len: u64,
+    index: Vec<IndexEntry>,
+    positions: HashMap<u32, usize>,
+    phantom: PhantomData<(C, G)>,
+}
+
+impl<C, G> HistoryReader<C, G, BufReader<File>>
+where
+    C: CellStateBase + DeserializeOwned,
+    G: GlobalStateBase + DeserializeOwned,
+{
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        HistoryReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<C, G, R> HistoryReader<C, G, R>
+where
+    C: CellStateBase + DeserializeOwned,
+    G: GlobalStateBase + DeserializeOwned,
+    R: Read + Seek,
+{
+    pub fn new(mut reader: R) -> Result<Self, HistoryError> {
+        let mut magic = [0; 8];
+        reader.seek(SeekFrom::Start(0))?;
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| HistoryError::NotAHistory)?;
+        if &magic != MAGIC {
+            return Err(HistoryError::NotAHistory);
+        }
+        let len = reader.seek(SeekFrom::End(0))?;
+        let index = match read_index(&mut reader)? {
+            Some(index) => index,
+            None => scan_frames(&mut reader)?,
+        };
+        let positions = index
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.iteration, i))
+            .collect();
+        Ok(HistoryReader {
+            reader,
+            len,
+            index,
+            positions,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The recorded iterations in the order they were written
+    pub fn iterations(&self) -> Vec<u32> {
+        self.index.iter().map(|entry| entry.iteration).collect()
+    }
+
+    pub fn index(&self) -> &[IndexEntry] {
+        &self.index
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    fn read_payload(&mut self, entry: &IndexEntry) -> Result<Vec<u8>, HistoryError> {
+        self.reader
+            .seek(SeekFrom::Start(entry.offset + FRAME_HEADER_LEN - 8))?;
+        let mut length = [0; 8];
+        self.reader.read_exact(&mut length)?;
+        let remaining = self.len.saturating_sub(entry.offset + FRAME_HEADER_LEN);
+        read_frame_payload(
+            &mut self.reader,
+            u64::from_le_bytes(length),
+            remaining,
+            entry.offset,
+        )
+    }
+
+    fn position(&self, iteration: u32) -> Result<usize, HistoryError> {
+        self.positions
+            .get(&iteration)
+            .copied()
+            .ok_or(HistoryError::MissingIteration(iteration))
+    }
+
+    /// The position of the keyframe that the frame at the position is decoded from
+    fn keyframe_position(&self, position: usize) -> usize {
+        (0..=position)
+            .rev()
+            .find(|i| self.index[*i].kind == FrameKind::Keyframe)
+            .unwrap_or(0)
+    }
+
+    /// Apply the frame at the position to the state of the previous frame
+    fn apply_frame(
+        &mut self,
+        position: usize,
+        state: Option<IterationState<C, G>>,
+    ) -> Result<IterationState<C, G>, HistoryError> {
+        let entry = self.index[position];
+        let payload = self.read_payload(&entry)?;
+        match (entry.kind, state) {
+            (FrameKind::Keyframe, _) => Ok(bincode::deserialize(&payload)?),
+            (FrameKind::Delta, Some(mut state)) => {
+                let delta: Delta<C, G, CellNetwork> = bincode::deserialize(&payload)?;
+                state.global_state = delta.global_state;
+                state.cells.truncate(delta.cell_count as usize);
+                for (i, cell) in delta.changed.into_iter() {
+                    match state.cells.get_mut(i as usize) {
+                        Some(existing) => *existing = cell,
+                        None => state.cells.push(cell),
+                    }
+                }
+                if let Some(network) = delta.network {
+                    state.network = network;
+                }
+                Ok(state)
+            }
+            _ => Err(HistoryError::Corrupt(entry.offset)),
+        }
+    }
+
+    /// Read the full state of the iteration
+    pub fn read_state(&mut self, iteration: u32) -> Result<IterationState<C, G>, HistoryError> {
+        let position = self.position(iteration)?;
+        let mut state = None;
+        for i in self.keyframe_position(position)..=position {
+            state = Some(self.apply_frame(i, state)?);
+        }
+        state.ok_or(HistoryError::MissingIteration(iteration))
+    }
+
+    /// Read the global state of the iteration without decoding its cells
+    pub fn read_global_state(&mut self, iteration: u32) -> Result<G, HistoryError> {
+        let position = self.position(iteration)?;
+        let entry = self.index[position];
+        let payload = self.read_payload(&entry)?;
+        let head: GlobalHead<G> = bincode::deserialize(&payload)?;
+        Ok(head.global_state)
+    }
+
+    pub fn read_cells(&mut self, iteration: u32) -> Result<Vec<C>, HistoryError> {
+        Ok(self.read_state(iteration)?.cells)
+    }
+
+    /// The global state of every iteration
+    pub fn global_series(&mut self) -> Result<Vec<(u32, G)>, HistoryError> {
+        self.iterations()
+            .into_iter()
+            .map(|iteration| Ok((iteration, self.read_global_state(iteration)?)))
+            .collect()
+    }
+
+    /// The value of a numeric field of one cell at every iteration
+    ///
+    /// Frames are decoded in order keeping only the current state. The value
+    /// is `None` at iterations where the cell does not exist or the field is
+    /// not numeric.
+    pub fn cell_field_series(
+        &mut self,
+        cell: CellIndex,
+        field: &str,
+    ) -> Result<Vec<(u32, Option<f64>)>, HistoryError>
+    where
+        C: Introspect,
+    {
+        let index: usize = cell.into();
+        let mut series = Vec::with_capacity(self.index.len());
+        let mut state = None;
+        let mut checked = false;
+        for position in 0..self.index.len() {
+            let current = self.apply_frame(position, state)?;
+            if let (false, Some(first)) = (checked, current.cells.first()) {
+                if !first.describe_fields().iter().any(|f| f.name == field) {
+                    return Err(HistoryError::UnknownField(field.to_owned()));
+                }
+                checked = true;
+            }
+            let value = current
+                .cells
+                .get(index)
+                .and_then(|c| c.numeric_field_value(field));
+            series.push((self.index[position].iteration, value));
+            state = Some(current);
+        }
+        Ok(series)
+    }
+}
+
+fn read_frame_header<R: Read>(reader: &mut R) -> Option<(u8, u32, u64)> {
+    let mut header = [0; FRAME_HEADER_LEN as usize];
+    reader.read_exact(&mut header).ok()?;
+    let mut iteration = [0; 4];
+    iteration.copy_from_slice(&header[1..5]);
+    let mut length = [0; 8];
+    length.copy_from_slice(&header[5..]);
+    Some((
+        header[0],
+        u32::from_le_bytes(iteration),
+        u64::from_le_bytes(length),
+    ))
+}
+
+/// Read a frame payload of the length after checking that the rest of the file holds it
+///
+/// A corrupt length would otherwise allocate up to the length before the read fails.
+fn read_frame_payload<R: Read>(
+    reader: &mut R,
+    length: u64,
+    remaining: u64,
+    offset: u64,
+) -> Result<Vec<u8>, HistoryError> {
+    if length > remaining {
+        return Err(HistoryError::Corrupt(offset));
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Read the index written when the history was finished
+fn read_index<R: Read + Seek>(reader: &mut R) -> Result<Option<Vec<IndexEntry>>, HistoryError> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    if end < MAGIC.len() as u64 + TRAILER_LEN {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(end - TRAILER_LEN))?;
+    let mut trailer = [0; TRAILER_LEN as usize];
+    reader.read_exact(&mut trailer)?;
+    if &trailer[8..] != INDEX_MAGIC {
+        return Ok(None);
+    }
+    let mut offset = [0; 8];
+    offset.copy_from_slice(&trailer[..8]);
+    let offset = u64::from_le_bytes(offset);
+    reader.seek(SeekFrom::Start(offset))?;
+    match read_frame_header(reader) {
+        Some((kind, _, length)) if FrameKind::from_byte(kind) == Some(FrameKind::Index) => {
+            let remaining = (end - TRAILER_LEN).saturating_sub(offset + FRAME_HEADER_LEN);
+            let payload = read_frame_payload(reader, length, remaining, offset)?;
+            Ok(Some(bincode::deserialize(&payload)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Index the frames by reading their headers
+///
+/// A frame cut short at the end of the file is ignored.
+fn scan_frames<R: Read + Seek>(reader: &mut R) -> Result<Vec<IndexEntry>, HistoryError> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let mut offset = reader.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+    let mut index = vec![];
+    while let Some((kind, iteration, length)) = read_frame_header(reader) {
+        let kind = FrameKind::from_byte(kind).ok_or(HistoryError::Corrupt(offset))?;
+        let next = (offset + FRAME_HEADER_LEN).saturating_add(length);
+        if kind == FrameKind::Index || next > end {
+            break;
+        }
+        index.push(IndexEntry {
+            iteration,
+            kind,
+            offset,
+        });
+        offset = reader.seek(SeekFrom::Start(next))?;
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_state::CellState;
+    use crate::process_runner::examples::example_state::GlobalState;
+    use geo::point;
+    use std::io::Cursor;
+
+    fn demo_states() -> Vec<IterationState<CellState, GlobalState>> {
+        let mut state = IterationState {
+            global_state: GlobalState::default(),
+            cells: (0..4)
+                .map(|i| CellState::new(i, point!(x: i as f64, y: 0.0), 10))
+                .collect(),
+            network: vec![vec![]; 4],
+        };
+        let mut states = vec![];
+        for i in 0..7 {
+            state.global_state.iterations = i;
+            state.cells[(i % 4) as usize].population += i;
+            if i == 4 {
+                state
+                    .cells
+                    .push(CellState::new(4, point!(x: 4.0, y: 0.0), 1));
+                state.network.push(vec![CellIndex(0)]);
+            }
+            states.push(state.clone());
+        }
+        states
+    }
+
+    fn write_history(states: &[IterationState<CellState, GlobalState>]) -> Vec<u8> {
+        let mut writer = HistoryWriter::new(vec![])
+            .unwrap()
+            .with_keyframe_interval(3);
+        for (i, state) in states.iter().enumerate() {
+            writer.observe(i as u32, state);
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn should_read_back_any_iteration() {
+        let states = demo_states();
+        let bytes = write_history(&states);
+        let mut reader: HistoryReader<CellState, GlobalState, _> =
+            HistoryReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.iterations(), (0..7).collect::<Vec<u32>>());
+        let kinds: Vec<FrameKind> = reader.index().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds[..4],
+            [
+                FrameKind::Keyframe,
+                FrameKind::Delta,
+                FrameKind::Delta,
+                FrameKind::Keyframe
+            ]
+        );
+        for i in [5, 1, 6, 0].iter() {
+            assert_eq!(reader.read_state(*i).unwrap(), states[*i as usize]);
+        }
+        assert_eq!(reader.read_global_state(2).unwrap().iterations, 2);
+        assert!(matches!(
+            reader.read_state(9),
+            Err(HistoryError::MissingIteration(9))
+        ));
+    }
+
+    #[test]
+    fn should_extract_the_series_of_a_cell_field() {
+        let states = demo_states();
+        let mut reader: HistoryReader<CellState, GlobalState, _> =
+            HistoryReader::new(Cursor::new(write_history(&states))).unwrap();
+        let series = reader
+            .cell_field_series(CellIndex(1), "population")
+            .unwrap();
+        let expected: Vec<(u32, Option<f64>)> = states
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (i as u32, Some(s.cells[1].population as f64)))
+            .collect();
+        assert_eq!(series, expected);
+        let series = reader
+            .cell_field_series(CellIndex(4), "population")
+            .unwrap();
+        assert_eq!(series[3], (3, None));
+        assert_eq!(series[4], (4, Some(1.0)));
+        assert_eq!(reader.global_series().unwrap().len(), 7);
+        assert!(matches!(
+            reader.cell_field_series(CellIndex(0), "jobs"),
+            Err(HistoryError::UnknownField(_))
+        ));
+    }
+
+    #[test]
+    fn should_scan_histories_without_an_index() {
+        let states = demo_states();
+        let mut bytes = write_history(&states);
+        let mut offset = [0; 8];
+        offset.copy_from_slice(&bytes[bytes.len() - 16..bytes.len() - 8]);
+        // Drop the index and cut the last frame short as if the run stopped
+        bytes.truncate(u64::from_le_bytes(offset) as usize - 3);
+        let mut reader: HistoryReader<CellState, GlobalState, _> =
+            HistoryReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.len(), 6);
+        assert_eq!(reader.read_state(5).unwrap(), states[5]);
+        assert!(matches!(
+            HistoryReader::<CellState, GlobalState, _>::new(Cursor::new(b"not a history".to_vec())),
+            Err(HistoryError::NotAHistory)
+        ));
+    }
+
+    #[test]
+    fn should_reject_frames_longer_than_the_file() {
+        let states = demo_states();
+        let mut bytes = write_history(&states);
+        let first_frame = MAGIC.len() + 5;
+        bytes[first_frame..first_frame + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut reader: HistoryReader<CellState, GlobalState, _> =
+            HistoryReader::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(
+            reader.read_state(0),
+            Err(HistoryError::Corrupt(8))
+        ));
+    }
+
+    /// A writer that fails after accepting a number of bytes
+    struct FailingWriter(usize);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.len() > self.0 {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_keep_the_first_error_when_observing() {
+        let states = demo_states();
+        let mut writer = HistoryWriter::new(FailingWriter(64)).unwrap();
+        for (i, state) in states.iter().enumerate() {
+            writer.observe(i as u32, state);
+        }
+        assert!(matches!(writer.flush(), Err(HistoryError::Io(_))));
+        assert!(writer.flush().is_ok());
+
+        let mut writer = HistoryWriter::new(FailingWriter(64)).unwrap();
+        writer.observe(0, &states[0]);
+        assert!(matches!(writer.finish(), Err(HistoryError::Io(_))));
+    }
+}
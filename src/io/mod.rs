@@ -0,0 +1,8 @@
+/// IO Module
+///
+/// Read model states from and write them to common file formats.
+pub mod ascii_grid;
+pub mod csv;
+pub mod geojson;
+pub mod history;
+pub mod render;
@@ -0,0 +1,750 @@
+/// Render Module
+///
+/// Draw a numeric cell field as an image by mapping its values through a
+/// colour map.
+///
+/// Lattice cells are drawn as raster images (PNG or PPM) using the grid of an
+/// ESRI ASCII grid header, with each grid cell drawn as a square of pixels.
+/// Point and polygon cells are drawn as SVG, with the geometries they were
+/// loaded from and optionally the edges of their network.
+///
+/// A `LatticeAnimation` observer keeps the field of each iteration and writes
+/// them as an animated GIF or a numbered sequence of PNG frames. All frames
+/// share one value range so colours are comparable between iterations.
+use std::fmt;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use crate::io::ascii_grid::cells_to_ascii_grid;
+use crate::io::ascii_grid::AsciiGrid;
+use crate::io::ascii_grid::AsciiGridError;
+use crate::io::ascii_grid::AsciiGridHeader;
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::state::IterationState;
+use geo::Geometry;
+use geo::LineString;
+
+pub type Rgb = [u8; 3];
+
+const VIRIDIS: [Rgb; 10] = [
+    [68, 1, 84],
+    [72, 40, 120],
+    [62, 73, 137],
+    [49, 104, 142],
+    [38, 130, 142],
+    [31, 158, 137],
+    [53, 183, 121],
+    [110, 206, 88],
+    [181, 222, 43],
+    [253, 231, 37],
+];
+
+#[derive(Debug)]
+pub enum RenderError {
+    Io(std::io::Error),
+    Png(png::EncodingError),
+    Gif(gif::EncodingError),
+    Grid(AsciiGridError),
+    UnknownField(String),
+    /// The field value of the cell is not numeric
+    NotNumeric {
+        cell: CellIndex,
+        field: String,
+    },
+    /// GIF frames are limited to 65535 pixels in each direction
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+    NoFrames,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Io(e) => write!(f, "{}", e),
+            RenderError::Png(e) => write!(f, "Failed to encode PNG: {}", e),
+            RenderError::Gif(e) => write!(f, "Failed to encode GIF: {}", e),
+            RenderError::Grid(e) => write!(f, "{}", e),
+            RenderError::UnknownField(field) => write!(f, "Unknown field: {}", field),
+            RenderError::NotNumeric { cell, field } => {
+                write!(f, "Cell {} field {} is not numeric", cell, field)
+            }
+            RenderError::TooLarge { width, height } => {
+                write!(f, "Image of {}x{} pixels is too large", width, height)
+            }
+            RenderError::NoFrames => write!(f, "No frames have been recorded"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<std::io::Error> for RenderError {
+    fn from(e: std::io::Error) -> Self {
+        RenderError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for RenderError {
+    fn from(e: png::EncodingError) -> Self {
+        RenderError::Png(e)
+    }
+}
+
+impl From<gif::EncodingError> for RenderError {
+    fn from(e: gif::EncodingError) -> Self {
+        RenderError::Gif(e)
+    }
+}
+
+impl From<AsciiGridError> for RenderError {
+    fn from(e: AsciiGridError) -> Self {
+        RenderError::Grid(e)
+    }
+}
+
+/// Colours between evenly spaced stops are linearly interpolated
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ColourMap {
+    Greys,
+    #[default]
+    Viridis,
+    Stops(Vec<Rgb>),
+}
+
+impl ColourMap {
+    /// The colour at t between 0 and 1
+    ///
+    /// t is clamped to the range.
+    pub fn colour(&self, t: f64) -> Rgb {
+        let stops: &[Rgb] = match self {
+            ColourMap::Greys => &[[0, 0, 0], [255, 255, 255]],
+            ColourMap::Viridis => &VIRIDIS,
+            ColourMap::Stops(stops) => stops,
+        };
+        match stops.len() {
+            0 => [0, 0, 0],
+            1 => stops[0],
+            n => {
+                let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+                let position = t * (n - 1) as f64;
+                let i = (position.floor() as usize).min(n - 2);
+                let f = position - i as f64;
+                let mut colour = [0; 3];
+                for (c, (a, b)) in colour
+                    .iter_mut()
+                    .zip(stops[i].iter().zip(stops[i + 1].iter()))
+                {
+                    *c = (*a as f64 + (*b as f64 - *a as f64) * f).round() as u8;
+                }
+                colour
+            }
+        }
+    }
+}
+
+/// Maps field values to colours
+///
+/// Without a fixed range the range is the minimum and maximum of the values
+/// being drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColourScale {
+    pub colour_map: ColourMap,
+    pub range: Option<(f64, f64)>,
+    pub nodata_colour: Rgb,
+}
+
+impl Default for ColourScale {
+    fn default() -> Self {
+        ColourScale {
+            colour_map: ColourMap::default(),
+            range: None,
+            nodata_colour: [255, 255, 255],
+        }
+    }
+}
+
+impl ColourScale {
+    pub fn new(colour_map: ColourMap) -> ColourScale {
+        ColourScale {
+            colour_map,
+            ..ColourScale::default()
+        }
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    pub fn with_nodata_colour(mut self, colour: Rgb) -> Self {
+        self.nodata_colour = colour;
+        self
+    }
+
+    /// The fixed range or the range of the values
+    pub fn resolve_range(&self, values: impl Iterator<Item = f64>) -> (f64, f64) {
+        if let Some(range) = self.range {
+            return range;
+        }
+        values
+            .filter(|v| v.is_finite())
+            .fold(None, |range, v| match range {
+                None => Some((v, v)),
+                Some((min, max)) => Some((f64::min(min, v), f64::max(max, v))),
+            })
+            .unwrap_or((0.0, 1.0))
+    }
+
+    /// The colour of the value in the range
+    ///
+    /// All values are drawn with the lowest colour if the range is empty.
+    pub fn colour(&self, value: Option<f64>, range: (f64, f64)) -> Rgb {
+        let (min, max) = range;
+        match value {
+            None => self.nodata_colour,
+            Some(_) if max <= min => self.colour_map.colour(0.0),
+            Some(v) => self.colour_map.colour((v - min) / (max - min)),
+        }
+    }
+}
+
+/// An RGB raster image with pixels in row order from the top left
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RasterImage {
+    /// Draw each grid cell as a square of pixel_size pixels
+    pub fn from_grid(
+        grid: &AsciiGrid,
+        scale: &ColourScale,
+        range: (f64, f64),
+        pixel_size: u32,
+    ) -> RasterImage {
+        let pixel_size = pixel_size.max(1) as usize;
+        let ncols = grid.header.ncols;
+        let width = ncols * pixel_size;
+        let height = grid.header.nrows * pixel_size;
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let value = grid.get(y / pixel_size, x / pixel_size);
+                pixels.extend_from_slice(&scale.colour(value, range));
+            }
+        }
+        RasterImage {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        }
+    }
+
+    /// Encode as a binary PPM (P6)
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.pixels);
+        out
+    }
+
+    pub fn write_ppm(&self, path: impl AsRef<Path>) -> Result<(), RenderError> {
+        fs::write(path, self.to_ppm())?;
+        Ok(())
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), RenderError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), RenderError> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Draw a numeric field of lattice cells
+///
+/// Cells are placed in the grid cell containing their position, as for
+/// `cells_to_ascii_grid`. Grid cells without a cell use the no data colour.
+pub fn render_lattice<C: CellStateBase + Introspect>(
+    cells: &[C],
+    header: &AsciiGridHeader,
+    field: &str,
+    scale: &ColourScale,
+    pixel_size: u32,
+) -> Result<RasterImage, RenderError> {
+    let grid = cells_to_ascii_grid(cells, header, field)?;
+    let range = scale.resolve_range(grid.values.iter().flatten().copied());
+    Ok(RasterImage::from_grid(&grid, scale, range, pixel_size))
+}
+
+fn hex_colour(colour: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour[0], colour[1], colour[2])
+}
+
+fn cell_values<C: CellStateBase + Introspect>(
+    cells: &[C],
+    field: &str,
+) -> Result<Vec<f64>, RenderError> {
+    cells
+        .iter()
+        .map(|cell| {
+            cell.field_value(field)
+                .ok_or_else(|| RenderError::UnknownField(field.to_owned()))?
+                .as_f64()
+                .ok_or_else(|| RenderError::NotNumeric {
+                    cell: cell.id(),
+                    field: field.to_owned(),
+                })
+        })
+        .collect()
+}
+
+/// The rings of the polygons in the geometry
+fn polygon_rings(geometry: &Geometry<f64>) -> Vec<&LineString<f64>> {
+    match geometry {
+        Geometry::Polygon(polygon) => std::iter::once(polygon.exterior())
+            .chain(polygon.interiors().iter())
+            .collect(),
+        Geometry::MultiPolygon(polygons) => polygons
+            .0
+            .iter()
+            .flat_map(|polygon| {
+                std::iter::once(polygon.exterior()).chain(polygon.interiors().iter())
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Draws point and polygon cells as SVG
+///
+/// Geometries are looked up by cell id, as for GeoJSON export. Cells without a
+/// polygon geometry are drawn as circles at their position.
+#[derive(Debug, Clone)]
+pub struct SvgRenderer<'a> {
+    scale: ColourScale,
+    width: f64,
+    point_radius: f64,
+    geometries: Option<&'a [Geometry<f64>]>,
+    network: Option<&'a [Vec<CellIndex>]>,
+    edge_colour: Rgb,
+}
+
+impl<'a> Default for SvgRenderer<'a> {
+    fn default() -> Self {
+        SvgRenderer {
+            scale: ColourScale::default(),
+            width: 800.0,
+            point_radius: 4.0,
+            geometries: None,
+            network: None,
+            edge_colour: [128, 128, 128],
+        }
+    }
+}
+
+impl<'a> SvgRenderer<'a> {
+    pub fn new() -> SvgRenderer<'a> {
+        SvgRenderer::default()
+    }
+
+    pub fn with_colour_scale(mut self, scale: ColourScale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// The image width in pixels; the height follows the extent of the cells
+    pub fn with_width(mut self, width: f64) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn with_point_radius(mut self, radius: f64) -> Self {
+        self.point_radius = radius;
+        self
+    }
+
+    /// Draw cells with these geometries, e.g. from `GeoJsonCells`
+    pub fn with_geometries(mut self, geometries: &'a [Geometry<f64>]) -> Self {
+        self.geometries = Some(geometries);
+        self
+    }
+
+    /// Draw a line between each cell and its neighbours
+    ///
+    /// The network is in cell order as in `IterationState`.
+    pub fn with_network(mut self, network: &'a [Vec<CellIndex>], colour: Rgb) -> Self {
+        self.network = Some(network);
+        self.edge_colour = colour;
+        self
+    }
+
+    pub fn render<C: CellStateBase + Introspect>(
+        &self,
+        cells: &[C],
+        field: &str,
+    ) -> Result<String, RenderError> {
+        let values = cell_values(cells, field)?;
+        let range = self.scale.resolve_range(values.iter().copied());
+        let rings: Vec<Vec<&LineString<f64>>> = cells
+            .iter()
+            .map(|cell| {
+                self.geometries
+                    .and_then(|g| g.get(u32::from(cell.id()) as usize))
+                    .map(polygon_rings)
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let coords = cells
+            .iter()
+            .map(|cell| (cell.position().x(), cell.position().y()))
+            .chain(
+                rings
+                    .iter()
+                    .flatten()
+                    .flat_map(|ring| ring.0.iter().map(|c| (c.x, c.y))),
+            );
+        let (min_x, min_y, max_x, max_y) = coords.fold(
+            (
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(min_x, min_y, max_x, max_y), (x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        );
+        let extent_x = (max_x - min_x).max(f64::EPSILON);
+        let extent_y = (max_y - min_y).max(f64::EPSILON);
+        let margin = self.point_radius;
+        let ratio = if cells.is_empty() {
+            1.0
+        } else {
+            (self.width - 2.0 * margin).max(1.0) / extent_x
+        };
+        let height = if cells.is_empty() {
+            self.width
+        } else {
+            extent_y * ratio + 2.0 * margin
+        };
+        // SVG y increases downwards so the model y axis is flipped
+        let project = |x: f64, y: f64| (margin + (x - min_x) * ratio, margin + (max_y - y) * ratio);
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = self.width,
+            h = height
+        );
+        if let Some(network) = self.network {
+            let _ = writeln!(
+                svg,
+                r#"<g stroke="{}" stroke-width="1">"#,
+                hex_colour(self.edge_colour)
+            );
+            for (cell, neighbours) in cells.iter().zip(network.iter()) {
+                let (x1, y1) = project(cell.position().x(), cell.position().y());
+                for CellIndex(id) in neighbours.iter() {
+                    // Draw each undirected edge once
+                    if *id <= u32::from(cell.id()) {
+                        continue;
+                    }
+                    if let Some(neighbour) = cells.get(*id as usize) {
+                        let (x2, y2) = project(neighbour.position().x(), neighbour.position().y());
+                        let _ = writeln!(
+                            svg,
+                            r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                            x1, y1, x2, y2
+                        );
+                    }
+                }
+            }
+            let _ = writeln!(svg, "</g>");
+        }
+        for ((cell, value), cell_rings) in cells.iter().zip(values.iter()).zip(rings.iter()) {
+            let fill = hex_colour(self.scale.colour(Some(*value), range));
+            if cell_rings.is_empty() {
+                let (x, y) = project(cell.position().x(), cell.position().y());
+                let _ = writeln!(
+                    svg,
+                    r#"<circle id="cell-{}" cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+                    cell.id(),
+                    x,
+                    y,
+                    self.point_radius,
+                    fill
+                );
+                continue;
+            }
+            let mut path = String::new();
+            for ring in cell_rings.iter() {
+                for (i, c) in ring.0.iter().enumerate() {
+                    let (x, y) = project(c.x, c.y);
+                    let _ = write!(path, "{}{} {} ", if i == 0 { "M" } else { "L" }, x, y);
+                }
+                path.push('Z');
+            }
+            let _ = writeln!(
+                svg,
+                r#"<path id="cell-{}" d="{}" fill="{}" fill-rule="evenodd" stroke="black" stroke-width="0.5"/>"#,
+                cell.id(),
+                path,
+                fill
+            );
+        }
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
+
+/// An observer that keeps a numeric field of lattice cells at every iteration
+///
+/// Frames are drawn when written so that they share the range of all recorded
+/// values unless the colour scale has a fixed range.
+#[derive(Debug, Clone)]
+pub struct LatticeAnimation {
+    header: AsciiGridHeader,
+    field: String,
+    scale: ColourScale,
+    pixel_size: u32,
+    frames: Vec<(u32, AsciiGrid)>,
+}
+
+impl LatticeAnimation {
+    pub fn new(header: AsciiGridHeader, field: &str) -> LatticeAnimation {
+        LatticeAnimation {
+            header,
+            field: field.to_owned(),
+            scale: ColourScale::default(),
+            pixel_size: 1,
+            frames: vec![],
+        }
+    }
+
+    pub fn with_colour_scale(mut self, scale: ColourScale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Draw each grid cell as a square of pixel_size pixels
+    pub fn with_pixel_size(mut self, pixel_size: u32) -> Self {
+        self.pixel_size = pixel_size;
+        self
+    }
+
+    pub fn record<C: CellStateBase + Introspect, G: GlobalStateBase>(
+        &mut self,
+        iteration: u32,
+        state: &IterationState<C, G>,
+    ) -> Result<(), RenderError> {
+        let grid = cells_to_ascii_grid(&state.cells, &self.header, &self.field)?;
+        self.frames.push((iteration, grid));
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Draw the recorded frames with their iteration
+    pub fn images(&self) -> Vec<(u32, RasterImage)> {
+        let range = self.scale.resolve_range(
+            self.frames
+                .iter()
+                .flat_map(|(_, grid)| grid.values.iter().flatten().copied()),
+        );
+        self.frames
+            .iter()
+            .map(|(iteration, grid)| {
+                (
+                    *iteration,
+                    RasterImage::from_grid(grid, &self.scale, range, self.pixel_size),
+                )
+            })
+            .collect()
+    }
+
+    /// Write the frames as a looping animated GIF
+    ///
+    /// The delay is rounded to the nearest 10 milliseconds.
+    pub fn write_gif<W: Write>(&self, writer: W, frame_delay_ms: u32) -> Result<(), RenderError> {
+        let images = self.images();
+        let (width, height) = match images.first() {
+            Some((_, image)) => (image.width, image.height),
+            None => return Err(RenderError::NoFrames),
+        };
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(RenderError::TooLarge { width, height });
+        }
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        let delay = (frame_delay_ms.saturating_add(5) / 10).min(u16::MAX as u32) as u16;
+        for (_, image) in images.iter() {
+            let mut frame = gif::Frame::from_rgb(width as u16, height as u16, &image.pixels);
+            frame.delay = delay;
+            encoder.write_frame(&frame)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_gif(&self, path: impl AsRef<Path>, frame_delay_ms: u32) -> Result<(), RenderError> {
+        self.write_gif(BufWriter::new(File::create(path)?), frame_delay_ms)
+    }
+
+    /// Write each frame to `{prefix}_{iteration}.png` in the directory
+    ///
+    /// Iterations are zero padded so the files sort in iteration order.
+    pub fn write_frames(&self, dir: impl AsRef<Path>, prefix: &str) -> Result<(), RenderError> {
+        fs::create_dir_all(&dir)?;
+        for (iteration, image) in self.images() {
+            let path = dir
+                .as_ref()
+                .join(format!("{}_{:06}.png", prefix, iteration));
+            image.save_png(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: CellStateBase + Introspect, G: GlobalStateBase> Observer<C, G> for LatticeAnimation {
+    /// Panics if the field is not a numeric cell field
+    fn observe(&mut self, iteration: u32, state: &IterationState<C, G>) {
+        if let Err(e) = self.record(iteration, state) {
+            panic!("Failed to record animation frame: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::ascii_grid::GridOrigin;
+    use crate::process_runner::examples::example_state::CellState;
+    use crate::process_runner::examples::example_state::GlobalState;
+    use geo::point;
+    use geo::polygon;
+
+    fn demo_header() -> AsciiGridHeader {
+        AsciiGridHeader {
+            ncols: 2,
+            nrows: 1,
+            xll: 0.0,
+            yll: 0.0,
+            origin: GridOrigin::Corner,
+            cellsize: 1.0,
+            nodata_value: None,
+        }
+    }
+
+    #[test]
+    fn should_interpolate_colour_maps() {
+        assert_eq!(ColourMap::Greys.colour(0.0), [0, 0, 0]);
+        assert_eq!(ColourMap::Greys.colour(0.5), [128, 128, 128]);
+        assert_eq!(ColourMap::Greys.colour(2.0), [255, 255, 255]);
+        assert_eq!(ColourMap::Viridis.colour(1.0), [253, 231, 37]);
+        let scale = ColourScale::new(ColourMap::Greys);
+        assert_eq!(
+            scale.resolve_range(vec![3.0, -1.0, f64::NAN].into_iter()),
+            (-1.0, 3.0)
+        );
+        assert_eq!(scale.colour(Some(1.0), (1.0, 1.0)), [0, 0, 0]);
+        assert_eq!(scale.colour(None, (0.0, 1.0)), [255, 255, 255]);
+    }
+
+    #[test]
+    fn should_render_lattice_cells_as_ppm() {
+        let cells = vec![
+            CellState::new(0, point!(x: 0.5, y: 0.5), 0),
+            CellState::new(1, point!(x: 1.5, y: 0.5), 10),
+        ];
+        let scale = ColourScale::new(ColourMap::Greys);
+        let image = render_lattice(&cells, &demo_header(), "population", &scale, 2).unwrap();
+        assert_eq!((image.width, image.height), (4, 2));
+        let ppm = image.to_ppm();
+        assert!(ppm.starts_with(b"P6\n4 2\n255\n"));
+        assert_eq!(&ppm[ppm.len() - 6..], &[255, 255, 255, 255, 255, 255]);
+        assert_eq!(&image.pixels[..6], &[0, 0, 0, 0, 0, 0]);
+        let mut png = vec![];
+        image.write_png(&mut png).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn should_render_polygons_points_and_edges_as_svg() {
+        let cells = vec![
+            CellState::new(0, point!(x: 1.0, y: 1.0), 0),
+            CellState::new(1, point!(x: 5.0, y: 1.0), 10),
+        ];
+        let geometries = vec![Geometry::Polygon(polygon![
+            (x: 0.0, y: 0.0),
+            (x: 2.0, y: 0.0),
+            (x: 2.0, y: 2.0),
+            (x: 0.0, y: 2.0),
+        ])];
+        let network = vec![vec![CellIndex(1)], vec![CellIndex(0)]];
+        let svg = SvgRenderer::new()
+            .with_colour_scale(ColourScale::new(ColourMap::Greys))
+            .with_geometries(&geometries)
+            .with_network(&network, [255, 0, 0])
+            .render(&cells, "population")
+            .unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"<path id="cell-0""#));
+        assert!(svg.contains(r#"<circle id="cell-1" cx="796""#));
+        assert!(svg.contains(r##"r="4" fill="#ffffff"/>"##));
+        assert_eq!(svg.matches("<line ").count(), 1);
+        assert!(matches!(
+            SvgRenderer::new().render(&cells, "jobs"),
+            Err(RenderError::UnknownField(_))
+        ));
+    }
+
+    #[test]
+    fn should_write_animated_gifs_with_a_shared_range() {
+        let mut animation = LatticeAnimation::new(demo_header(), "population")
+            .with_colour_scale(ColourScale::new(ColourMap::Greys));
+        for population in [0, 10].iter() {
+            let state = IterationState {
+                global_state: GlobalState::default(),
+                cells: vec![CellState::new(0, point!(x: 0.5, y: 0.5), *population)],
+                network: vec![vec![]],
+            };
+            animation.observe(*population, &state);
+        }
+        let images = animation.images();
+        assert_eq!(images.len(), 2);
+        assert_eq!(&images[0].1.pixels[..3], &[0, 0, 0]);
+        assert_eq!(&images[1].1.pixels[..3], &[255, 255, 255]);
+        let mut gif = vec![];
+        animation.write_gif(&mut gif, 100).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+        animation.write_gif(vec![], u32::MAX).unwrap();
+        assert!(matches!(
+            LatticeAnimation::new(demo_header(), "population").write_gif(vec![], 100),
+            Err(RenderError::NoFrames)
+        ));
+    }
+}
@@ -1,20 +1,43 @@
 #![warn(clippy::all)]
 #![allow(clippy::ptr_arg)]
 
-extern crate pyo3;
-use pyo3::prelude::*;
-
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod io;
 pub mod process_runner;
+#[cfg(feature = "python")]
 pub mod py_interface;
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "python")]
+pub use cellular_automata_derive::CellState;
+#[cfg(feature = "python")]
+pub use cellular_automata_derive::GlobalState;
+
+/// The python module, only built with the `python` feature
+#[cfg(feature = "python")]
+mod python_module {
+    extern crate pyo3;
+    use pyo3::prelude::*;
 
-use py_interface::example_run::run_submodule;
-use py_interface::examples::CellStatePy;
-use py_interface::examples::GlobalStatePy;
+    use crate::py_interface::cancel::CancellationTokenPy;
+    use crate::py_interface::example_gui::GuiModelPy;
+    use crate::py_interface::example_recorder::RecorderPy;
+    use crate::py_interface::example_run::run_submodule;
+    use crate::py_interface::example_simulation::SimulationPy;
+    use crate::py_interface::examples::CellStatePy;
+    use crate::py_interface::examples::GlobalStatePy;
 
-#[pymodule]
-fn cellular_automata(py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_submodule(run_submodule(py)?)?;
-    m.add_class::<CellStatePy>()?;
-    m.add_class::<GlobalStatePy>()?;
-    Ok(())
+    #[pymodule]
+    fn cellular_automata(py: Python, m: &PyModule) -> PyResult<()> {
+        m.add_submodule(run_submodule(py)?)?;
+        m.add_class::<CellStatePy>()?;
+        m.add_class::<GlobalStatePy>()?;
+        m.add_class::<RecorderPy>()?;
+        m.add_class::<SimulationPy>()?;
+        m.add_class::<CancellationTokenPy>()?;
+        m.add_class::<GuiModelPy>()?;
+        Ok(())
+    }
 }
@@ -1,34 +1,24 @@
 /*
-// TODO: Implement Gui Interface
-// TODO: Build
+The cellular_automata command line binary
+
+Runs model configs without Python. See `cellular_automata help` and the cli module.
 */
+use std::process;
 
-mod process_runner;
-use geo::point;
-use process_runner::examples::example_processes::default_cell_processes;
-use process_runner::examples::example_processes::default_global_processes;
-use process_runner::run::run_iteration;
+// extension-module leaves libpython unlinked so an executable cannot be built with it
+#[cfg(feature = "extension-module")]
+compile_error!(
+    "The binary is built without the default features: cargo build --no-default-features --features cli"
+);
 
-use process_runner::examples::example_state::CellState;
-use process_runner::examples::example_state::GlobalState;
-use process_runner::state::IterationState;
+use cellular_automata::cli;
 
 fn main() {
-    let cells = (0..99)
-        .map(|i| CellState::new(i, point!(x: 0.0, y: i as f64/100.0), 5))
-        .collect::<Vec<_>>();
-    let initial_state = IterationState {
-        global_state: GlobalState {
-            iterations: 0,
-            population: 0,
-        },
-        cells: cells,
-        network: vec![vec![]],
-    };
-    let cell_processes = default_cell_processes();
-    let global_processes = default_global_processes();
-    let final_state = run_iteration(&cell_processes, &global_processes, initial_state, true);
-
-    println!("Cell 0 pop! {}", final_state.cells[0].population);
-    println!("Cell 1 pop! {}", final_state.cells[1].population);
+    let stdout = std::io::stdout();
+    let result = cli::parse_args(std::env::args().skip(1))
+        .and_then(|command| cli::execute(command, &mut stdout.lock()));
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
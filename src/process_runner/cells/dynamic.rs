@@ -0,0 +1,828 @@
+/// Dynamic Cell State Module
+///
+/// A cell state whose fields are declared by a `CellSchema` at runtime so that
+/// models can choose their cell layout when they are loaded rather than when
+/// they are compiled.
+///
+/// Each field has a name, a type (int, float, bool or category) and a default.
+/// Processes look up a typed `FieldHandle` from the schema once and then read
+/// and write the field through the handle without matching names. Values are
+/// stored in a vector per type so reading a field is an index into a vector.
+///
+/// Schemas can be read from config files as a list of `FieldSpec`.
+/// Generic loaders create cells with `CellFields::new_at`, which uses the
+/// schema set for the current thread with `with_schema`.
+///
+/// Cells are serialized with the specs of their schema so that checkpoints can
+/// be read without the config that created them.
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::state::CellIndex;
+use super::state::CellStateBase;
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::fields::FieldDescriptor;
+use crate::process_runner::fields::FieldError;
+use crate::process_runner::fields::FieldKind;
+use crate::process_runner::fields::FieldValue;
+use crate::process_runner::fields::Introspect;
+use geo::Point;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Int,
+    Float,
+    Bool,
+    /// One of a fixed list of named categories
+    Category(Vec<String>),
+}
+
+impl FieldType {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FieldType::Int => "int",
+            FieldType::Float => "float",
+            FieldType::Bool => "bool",
+            FieldType::Category(_) => "category",
+        }
+    }
+}
+
+impl From<&FieldType> for FieldKind {
+    fn from(field_type: &FieldType) -> FieldKind {
+        match field_type {
+            FieldType::Int => FieldKind::Int,
+            FieldType::Float => FieldKind::Float,
+            FieldType::Bool => FieldKind::Bool,
+            FieldType::Category(categories) => FieldKind::Category(categories.clone()),
+        }
+    }
+}
+
+/// The declaration of a field
+///
+/// Without a default fields are zero, false or the first category.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub default: Option<FieldValue>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub range: Option<(f64, f64)>,
+}
+
+impl FieldSpec {
+    pub fn new(name: &str, field_type: FieldType) -> FieldSpec {
+        FieldSpec {
+            name: name.to_owned(),
+            field_type,
+            default: None,
+            unit: None,
+            range: None,
+        }
+    }
+
+    pub fn with_default(mut self, default: FieldValue) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    pub fn with_unit(mut self, unit: &str) -> Self {
+        self.unit = Some(unit.to_owned());
+        self
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    Duplicate(String),
+    /// The category field has no categories
+    NoCategories(String),
+    /// The default value does not suit the field type
+    InvalidDefault(FieldError),
+    /// A serialized cell has a value that does not suit its field
+    InvalidValue(FieldError),
+    /// A serialized cell does not have a value for each field
+    ValueCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Duplicate(name) => write!(f, "Field {} is declared twice", name),
+            SchemaError::NoCategories(name) => write!(f, "Field {} has no categories", name),
+            SchemaError::InvalidDefault(e) => write!(f, "Invalid default: {}", e),
+            SchemaError::InvalidValue(e) => write!(f, "Invalid value: {}", e),
+            SchemaError::ValueCount { expected, found } => {
+                write!(f, "Expected {} field values but got {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// The index of a category in the list of categories of its field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Category(pub u32);
+
+/// Field values stored in a vector per type
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Values {
+    ints: Vec<i64>,
+    floats: Vec<f64>,
+    bools: Vec<bool>,
+    categories: Vec<u32>,
+}
+
+impl Values {
+    /// The slot in the vector of the field type for a new field
+    fn push(&mut self, field_type: &FieldType) -> usize {
+        match field_type {
+            FieldType::Int => {
+                self.ints.push(0);
+                self.ints.len() - 1
+            }
+            FieldType::Float => {
+                self.floats.push(0.0);
+                self.floats.len() - 1
+            }
+            FieldType::Bool => {
+                self.bools.push(false);
+                self.bools.len() - 1
+            }
+            FieldType::Category(_) => {
+                self.categories.push(0);
+                self.categories.len() - 1
+            }
+        }
+    }
+
+    fn get(&self, field_type: &FieldType, slot: usize) -> FieldValue {
+        match field_type {
+            FieldType::Int => FieldValue::Int(self.ints[slot]),
+            FieldType::Float => FieldValue::Float(self.floats[slot]),
+            FieldType::Bool => FieldValue::Bool(self.bools[slot]),
+            FieldType::Category(categories) => {
+                FieldValue::Text(categories[self.categories[slot] as usize].clone())
+            }
+        }
+    }
+
+    /// Set a value converting it to the field type
+    ///
+    /// Floats are rounded for int fields. Categories are set by name or index.
+    fn set(
+        &mut self,
+        name: &str,
+        field_type: &FieldType,
+        slot: usize,
+        value: FieldValue,
+    ) -> Result<(), FieldError> {
+        match (field_type, value) {
+            (FieldType::Int, FieldValue::Int(v)) => self.ints[slot] = v,
+            (FieldType::Int, FieldValue::Float(v)) => self.ints[slot] = v.round() as i64,
+            (FieldType::Float, FieldValue::Int(v)) => self.floats[slot] = v as f64,
+            (FieldType::Float, FieldValue::Float(v)) => self.floats[slot] = v,
+            (FieldType::Bool, FieldValue::Bool(v)) => self.bools[slot] = v,
+            (FieldType::Category(categories), FieldValue::Text(v)) => {
+                match categories.iter().position(|c| *c == v) {
+                    Some(i) => self.categories[slot] = i as u32,
+                    None => {
+                        return Err(FieldError::InvalidValue {
+                            name: name.to_owned(),
+                            value: v,
+                        })
+                    }
+                }
+            }
+            (FieldType::Category(categories), FieldValue::Int(v)) => {
+                if v < 0 || v as usize >= categories.len() {
+                    return Err(FieldError::InvalidValue {
+                        name: name.to_owned(),
+                        value: v.to_string(),
+                    });
+                }
+                self.categories[slot] = v as u32;
+            }
+            (field_type, value) => {
+                return Err(FieldError::InvalidType {
+                    name: name.to_owned(),
+                    expected: field_type.type_name(),
+                    found: value.type_name(),
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fields of a dynamic cell state and their defaults
+///
+/// Cells share their schema so it is created once and wrapped in an `Arc`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CellSchema {
+    fields: Vec<FieldSpec>,
+    slots: Vec<usize>,
+    names: HashMap<String, usize>,
+    defaults: Values,
+    /// A hash of the field names and types that handles are stamped with
+    layout: u64,
+}
+
+impl CellSchema {
+    pub fn new() -> CellSchema {
+        CellSchema::default()
+    }
+
+    pub fn from_specs(specs: Vec<FieldSpec>) -> Result<CellSchema, SchemaError> {
+        let mut schema = CellSchema::new();
+        for spec in specs.into_iter() {
+            schema.add_field(spec)?;
+        }
+        Ok(schema)
+    }
+
+    pub fn add_field(&mut self, spec: FieldSpec) -> Result<(), SchemaError> {
+        if self.names.contains_key(&spec.name) {
+            return Err(SchemaError::Duplicate(spec.name));
+        }
+        if spec.field_type == FieldType::Category(vec![]) {
+            return Err(SchemaError::NoCategories(spec.name));
+        }
+        // Check the default before changing the schema
+        let mut default = Values::default();
+        let default_slot = default.push(&spec.field_type);
+        if let Some(value) = spec.default.clone() {
+            default
+                .set(&spec.name, &spec.field_type, default_slot, value)
+                .map_err(SchemaError::InvalidDefault)?;
+        }
+        let slot = self.defaults.push(&spec.field_type);
+        self.defaults
+            .set(
+                &spec.name,
+                &spec.field_type,
+                slot,
+                default.get(&spec.field_type, default_slot),
+            )
+            .expect("The default has been checked");
+        self.names.insert(spec.name.clone(), self.fields.len());
+        self.fields.push(spec);
+        self.slots.push(slot);
+        self.layout = self.hash_layout();
+        Ok(())
+    }
+
+    /// Schemas with the same field names and types in the same order share a layout
+    fn hash_layout(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for field in self.fields.iter() {
+            field.name.hash(&mut hasher);
+            field.field_type.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    pub fn with_field(mut self, spec: FieldSpec) -> Result<Self, SchemaError> {
+        self.add_field(spec)?;
+        Ok(self)
+    }
+
+    pub fn fields(&self) -> &[FieldSpec] {
+        &self.fields
+    }
+
+    pub fn field_names(&self) -> Vec<&str> {
+        self.fields.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldSpec> {
+        self.names.get(name).map(|i| &self.fields[*i])
+    }
+
+    /// Get a typed handle to read and write the field
+    ///
+    /// The handle is only valid for cells of a schema with the same fields in
+    /// the same order; using it with other cells panics.
+    pub fn handle<T: DynamicValue>(&self, name: &str) -> Result<FieldHandle<T>, FieldError> {
+        let i = *self
+            .names
+            .get(name)
+            .ok_or_else(|| FieldError::Unknown(name.to_owned()))?;
+        let field_type = &self.fields[i].field_type;
+        if !T::matches(field_type) {
+            return Err(FieldError::InvalidType {
+                name: name.to_owned(),
+                expected: field_type.type_name(),
+                found: T::TYPE_NAME,
+            });
+        }
+        Ok(FieldHandle {
+            slot: self.slots[i],
+            layout: self.layout,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The categories of a category field
+    pub fn categories(&self, name: &str) -> Option<&[String]> {
+        match self.field(name).map(|f| &f.field_type) {
+            Some(FieldType::Category(categories)) => Some(categories),
+            _ => None,
+        }
+    }
+}
+
+/// A typed reference to a field of a `CellSchema`
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldHandle<T> {
+    slot: usize,
+    /// The layout of the schema the handle was taken from
+    layout: u64,
+    phantom: PhantomData<T>,
+}
+
+impl<T> Clone for FieldHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FieldHandle<T> {}
+
+/// The rust types of the dynamic field types
+pub trait DynamicValue: Copy {
+    const TYPE_NAME: &'static str;
+    fn matches(field_type: &FieldType) -> bool;
+    fn get(cell: &DynamicCellState, slot: usize) -> Self;
+    fn set(cell: &mut DynamicCellState, slot: usize, value: Self);
+}
+
+impl DynamicValue for i64 {
+    const TYPE_NAME: &'static str = "int";
+    fn matches(field_type: &FieldType) -> bool {
+        *field_type == FieldType::Int
+    }
+    fn get(cell: &DynamicCellState, slot: usize) -> i64 {
+        cell.values.ints[slot]
+    }
+    fn set(cell: &mut DynamicCellState, slot: usize, value: i64) {
+        cell.values.ints[slot] = value;
+    }
+}
+
+impl DynamicValue for f64 {
+    const TYPE_NAME: &'static str = "float";
+    fn matches(field_type: &FieldType) -> bool {
+        *field_type == FieldType::Float
+    }
+    fn get(cell: &DynamicCellState, slot: usize) -> f64 {
+        cell.values.floats[slot]
+    }
+    fn set(cell: &mut DynamicCellState, slot: usize, value: f64) {
+        cell.values.floats[slot] = value;
+    }
+}
+
+impl DynamicValue for bool {
+    const TYPE_NAME: &'static str = "bool";
+    fn matches(field_type: &FieldType) -> bool {
+        *field_type == FieldType::Bool
+    }
+    fn get(cell: &DynamicCellState, slot: usize) -> bool {
+        cell.values.bools[slot]
+    }
+    fn set(cell: &mut DynamicCellState, slot: usize, value: bool) {
+        cell.values.bools[slot] = value;
+    }
+}
+
+impl DynamicValue for Category {
+    const TYPE_NAME: &'static str = "category";
+    fn matches(field_type: &FieldType) -> bool {
+        matches!(field_type, FieldType::Category(_))
+    }
+    fn get(cell: &DynamicCellState, slot: usize) -> Category {
+        Category(cell.values.categories[slot])
+    }
+    fn set(cell: &mut DynamicCellState, slot: usize, value: Category) {
+        cell.values.categories[slot] = value.0;
+    }
+}
+
+/// A cell state with the fields of its schema
+///
+/// Cells are not randomized by `randomize`; use a setup process instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicCellState {
+    pub id: CellIndex,
+    pub position: Point<f64>,
+    schema: Arc<CellSchema>,
+    values: Values,
+}
+
+impl DynamicCellState {
+    /// Create a cell with the default value of each field
+    pub fn new(schema: &Arc<CellSchema>, id: CellIndex, position: Point<f64>) -> DynamicCellState {
+        DynamicCellState {
+            id,
+            position,
+            schema: schema.clone(),
+            values: schema.defaults.clone(),
+        }
+    }
+
+    pub fn schema(&self) -> &Arc<CellSchema> {
+        &self.schema
+    }
+
+    /// Panics if the handle is from a schema with a different layout
+    pub fn get<T: DynamicValue>(&self, handle: FieldHandle<T>) -> T {
+        self.check_handle(&handle);
+        T::get(self, handle.slot)
+    }
+
+    /// Panics if the handle is from a schema with a different layout
+    pub fn set<T: DynamicValue>(&mut self, handle: FieldHandle<T>, value: T) {
+        self.check_handle(&handle);
+        T::set(self, handle.slot, value)
+    }
+
+    fn check_handle<T>(&self, handle: &FieldHandle<T>) {
+        assert_eq!(
+            handle.layout, self.schema.layout,
+            "The field handle is from a schema with different fields"
+        );
+    }
+}
+
+impl Introspect for DynamicCellState {
+    fn describe_fields(&self) -> Vec<FieldDescriptor> {
+        self.schema
+            .fields
+            .iter()
+            .map(|field| FieldDescriptor {
+                unit: field.unit.clone(),
+                range: field.range,
+                ..FieldDescriptor::new(&field.name, FieldKind::from(&field.field_type))
+            })
+            .collect()
+    }
+
+    /// Categories are read as their name
+    fn field_value(&self, name: &str) -> Option<FieldValue> {
+        let i = *self.schema.names.get(name)?;
+        Some(
+            self.values
+                .get(&self.schema.fields[i].field_type, self.schema.slots[i]),
+        )
+    }
+
+    /// Floats are rounded for int fields and categories are set by name or index
+    fn set_field_value(&mut self, name: &str, value: FieldValue) -> Result<(), FieldError> {
+        let i = *self
+            .schema
+            .names
+            .get(name)
+            .ok_or_else(|| FieldError::Unknown(name.to_owned()))?;
+        let schema = self.schema.clone();
+        self.values
+            .set(name, &schema.fields[i].field_type, schema.slots[i], value)
+    }
+}
+
+impl CellStateBase for DynamicCellState {
+    fn id(&self) -> CellIndex {
+        self.id
+    }
+    fn position(&self) -> Point<f64> {
+        self.position
+    }
+    fn randomize(&self) -> DynamicCellState {
+        self.clone()
+    }
+}
+
+thread_local! {
+    /// The schema of cells created with `CellFields::new_at` on this thread
+    static NEW_CELL_SCHEMA: RefCell<Option<Arc<CellSchema>>> = const { RefCell::new(None) };
+
+    /// The schema of the last deserialized cell so that cells of a state share it
+    static LAST_SCHEMA: RefCell<Option<Arc<CellSchema>>> = const { RefCell::new(None) };
+}
+
+/// Run f with the schema of the cells created by `CellFields::new_at`
+///
+/// Generic loaders such as `GeoJsonLoader` create cells without a schema so
+/// they are run inside this. Deserialized cells with the same fields share the
+/// schema. The previous schema is restored when f returns.
+pub fn with_schema<R>(schema: &Arc<CellSchema>, f: impl FnOnce() -> R) -> R {
+    let previous = NEW_CELL_SCHEMA.with(|current| current.replace(Some(schema.clone())));
+    let result = f();
+    NEW_CELL_SCHEMA.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+impl CellFields for DynamicCellState {
+    /// The cell has the schema set with `with_schema`, or no fields outside it
+    fn new_at(id: CellIndex, position: Point<f64>) -> DynamicCellState {
+        let schema = NEW_CELL_SCHEMA
+            .with(|current| current.borrow().clone())
+            .unwrap_or_default();
+        DynamicCellState::new(&schema, id, position)
+    }
+}
+
+/// A schema with the fields, reusing the current or last schema if they match
+fn shared_schema(fields: Vec<FieldSpec>) -> Result<Arc<CellSchema>, SchemaError> {
+    let matching = |schema: &RefCell<Option<Arc<CellSchema>>>| {
+        schema
+            .borrow()
+            .as_ref()
+            .filter(|schema| schema.fields == fields)
+            .cloned()
+    };
+    if let Some(schema) = NEW_CELL_SCHEMA
+        .with(matching)
+        .or_else(|| LAST_SCHEMA.with(matching))
+    {
+        return Ok(schema);
+    }
+    let schema = Arc::new(CellSchema::from_specs(fields)?);
+    LAST_SCHEMA.with(|last| *last.borrow_mut() = Some(schema.clone()));
+    Ok(schema)
+}
+
+/// The serialized form of a dynamic cell with its schema specs and a value per field
+#[derive(Serialize, Deserialize)]
+struct DynamicCellData {
+    id: CellIndex,
+    position: Point<f64>,
+    fields: Vec<FieldSpec>,
+    values: Vec<FieldValue>,
+}
+
+impl From<&DynamicCellState> for DynamicCellData {
+    fn from(cell: &DynamicCellState) -> DynamicCellData {
+        DynamicCellData {
+            id: cell.id,
+            position: cell.position,
+            fields: cell.schema.fields.clone(),
+            values: cell
+                .field_values()
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<DynamicCellData> for DynamicCellState {
+    type Error = SchemaError;
+
+    fn try_from(data: DynamicCellData) -> Result<DynamicCellState, SchemaError> {
+        if data.values.len() != data.fields.len() {
+            return Err(SchemaError::ValueCount {
+                expected: data.fields.len(),
+                found: data.values.len(),
+            });
+        }
+        let names: Vec<String> = data.fields.iter().map(|f| f.name.clone()).collect();
+        let schema = shared_schema(data.fields)?;
+        let mut cell = DynamicCellState::new(&schema, data.id, data.position);
+        for (name, value) in names.iter().zip(data.values) {
+            cell.set_field_value(name, value)
+                .map_err(SchemaError::InvalidValue)?;
+        }
+        Ok(cell)
+    }
+}
+
+impl Serialize for DynamicCellState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DynamicCellData::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DynamicCellState {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = DynamicCellData::deserialize(deserializer)?;
+        DynamicCellState::try_from(data).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::cells::run::CellUpdate;
+    use geo::point;
+
+    fn demo_schema() -> Arc<CellSchema> {
+        let schema = CellSchema::from_specs(vec![
+            FieldSpec::new("population", FieldType::Int).with_default(FieldValue::Int(10)),
+            FieldSpec::new("elevation", FieldType::Float),
+            FieldSpec::new("flooded", FieldType::Bool),
+            FieldSpec::new(
+                "landuse",
+                FieldType::Category(vec!["rural".to_owned(), "urban".to_owned()]),
+            )
+            .with_default(FieldValue::Text("urban".to_owned())),
+        ])
+        .unwrap();
+        Arc::new(schema)
+    }
+
+    #[test]
+    fn should_create_cells_with_schema_defaults() {
+        let schema = demo_schema();
+        let cell = DynamicCellState::new(&schema, CellIndex(3), point!(x: 1.0, y: 2.0));
+        assert_eq!(cell.id(), CellIndex(3));
+        assert_eq!(
+            cell.field_values(),
+            vec![
+                ("population".to_owned(), FieldValue::Int(10)),
+                ("elevation".to_owned(), FieldValue::Float(0.0)),
+                ("flooded".to_owned(), FieldValue::Bool(false)),
+                ("landuse".to_owned(), FieldValue::Text("urban".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_describe_schema_fields() {
+        let schema = CellSchema::from_specs(vec![FieldSpec::new("water_depth", FieldType::Float)
+            .with_unit("m")
+            .with_range(0.0, 10.0)])
+        .unwrap();
+        let cell = DynamicCellState::new(&Arc::new(schema), CellIndex(0), point!(x: 0.0, y: 0.0));
+        let fields = cell.describe_fields();
+        assert_eq!(fields[0].label, "Water depth");
+        assert_eq!(fields[0].kind, FieldKind::Float);
+        assert_eq!(fields[0].unit, Some("m".to_owned()));
+        assert_eq!(fields[0].range, Some((0.0, 10.0)));
+        assert_eq!(cell.numeric_field_value("water_depth"), Some(0.0));
+    }
+
+    #[test]
+    fn should_read_and_write_fields_by_handle() {
+        let schema = demo_schema();
+        let population = schema.handle::<i64>("population").unwrap();
+        let landuse = schema.handle::<Category>("landuse").unwrap();
+        assert_eq!(
+            schema.handle::<f64>("population"),
+            Err(FieldError::InvalidType {
+                name: "population".to_owned(),
+                expected: "int",
+                found: "float",
+            })
+        );
+        let cell = DynamicCellState::new(&schema, CellIndex(0), point!(x: 0.0, y: 0.0));
+        // A process that only knows the handles
+        let update = CellUpdate::new(
+            cell.id(),
+            Box::new(move |mut c: DynamicCellState| {
+                let value = c.get(population);
+                c.set(population, value * 2);
+                c.set(landuse, Category(0));
+                c
+            }),
+        );
+        let updated = (update.action)(cell);
+        assert_eq!(updated.get(population), 20);
+        assert_eq!(
+            updated.field_value("landuse"),
+            Some(FieldValue::Text("rural".to_owned()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The field handle is from a schema with different fields")]
+    fn should_reject_handles_from_other_schemas() {
+        let population = demo_schema().handle::<i64>("population").unwrap();
+        let other = CellSchema::from_specs(vec![
+            FieldSpec::new("height", FieldType::Int),
+            FieldSpec::new("population", FieldType::Int),
+        ])
+        .unwrap();
+        let cell = DynamicCellState::new(&Arc::new(other), CellIndex(0), point!(x: 0.0, y: 0.0));
+        cell.get(population);
+    }
+
+    #[test]
+    fn should_serialize_cells_with_their_schema() {
+        let schema = demo_schema();
+        let population = schema.handle::<i64>("population").unwrap();
+        let cells: Vec<DynamicCellState> = (0..3)
+            .map(|i| {
+                let mut cell = DynamicCellState::new(&schema, CellIndex(i), point!(x: 0.0, y: 0.0));
+                cell.set(population, i as i64);
+                cell
+            })
+            .collect();
+        let json = serde_json::to_string(&cells).unwrap();
+        let loaded: Vec<DynamicCellState> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, cells);
+        assert!(Arc::ptr_eq(loaded[0].schema(), loaded[2].schema()));
+        assert_eq!(loaded[2].get(population), 2);
+        let loaded: Vec<DynamicCellState> =
+            with_schema(&schema, || serde_json::from_str(&json).unwrap());
+        assert!(Arc::ptr_eq(loaded[0].schema(), &schema));
+        let invalid = serde_json::to_string(&cells[0])
+            .unwrap()
+            .replace("\"urban\"}]", "\"forest\"}]");
+        assert!(serde_json::from_str::<DynamicCellState>(&invalid).is_err());
+    }
+
+    #[test]
+    fn should_create_cells_with_the_current_schema() {
+        let schema = demo_schema();
+        let cell = with_schema(&schema, || {
+            DynamicCellState::new_at(CellIndex(1), point!(x: 2.0, y: 3.0))
+        });
+        assert!(Arc::ptr_eq(cell.schema(), &schema));
+        assert_eq!(cell.numeric_field_value("population"), Some(10.0));
+        let cell = DynamicCellState::new_at(CellIndex(1), point!(x: 2.0, y: 3.0));
+        assert!(cell.describe_fields().is_empty());
+    }
+
+    #[test]
+    fn should_check_values_set_by_name() {
+        let schema = demo_schema();
+        let mut cell = DynamicCellState::new(&schema, CellIndex(0), point!(x: 0.0, y: 0.0));
+        cell.set_field_value("elevation", FieldValue::Int(4))
+            .unwrap();
+        cell.set_field_value("population", FieldValue::Float(2.6))
+            .unwrap();
+        assert_eq!(cell.field_value("elevation"), Some(FieldValue::Float(4.0)));
+        assert_eq!(cell.field_value("population"), Some(FieldValue::Int(3)));
+        assert_eq!(
+            cell.set_field_value("landuse", FieldValue::Text("forest".to_owned())),
+            Err(FieldError::InvalidValue {
+                name: "landuse".to_owned(),
+                value: "forest".to_owned(),
+            })
+        );
+        assert_eq!(
+            cell.set_field_value("flooded", FieldValue::Int(1)),
+            Err(FieldError::InvalidType {
+                name: "flooded".to_owned(),
+                expected: "bool",
+                found: "int",
+            })
+        );
+    }
+
+    #[test]
+    fn should_read_schemas_from_config() {
+        let specs: Vec<FieldSpec> = serde_json::from_str(
+            r#"[
+                {"name": "population", "type": "int", "default": {"Int": 5}},
+                {"name": "landuse", "type": {"category": ["rural", "urban"]}}
+            ]"#,
+        )
+        .unwrap();
+        let schema = CellSchema::from_specs(specs).unwrap();
+        assert_eq!(schema.field_names(), vec!["population", "landuse"]);
+        assert_eq!(
+            schema.categories("landuse"),
+            Some(&["rural".to_owned(), "urban".to_owned()][..])
+        );
+        let mut invalid = schema.clone();
+        assert!(matches!(
+            invalid.add_field(
+                FieldSpec::new("height", FieldType::Float).with_default(FieldValue::Bool(true))
+            ),
+            Err(SchemaError::InvalidDefault(_))
+        ));
+        assert_eq!(invalid, schema);
+        assert_eq!(
+            schema
+                .with_field(FieldSpec::new("population", FieldType::Float))
+                .err(),
+            Some(SchemaError::Duplicate("population".to_owned()))
+        );
+    }
+}
@@ -1,2 +1,3 @@
+pub mod dynamic;
 pub mod run;
 pub mod state;
@@ -7,6 +7,7 @@ use super::state::CellIndex;
 use super::state::CellStateBase;
 use crate::process_runner::global::run::GlobalUpdate;
 use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::parameters::Parameters;
 
 /// A function that takes a CellState, makes a modification and returns the modified CellState
 type CellActionFunc<T> = Box<dyn Fn(T) -> T>;
@@ -34,9 +35,14 @@ impl<T: CellStateBase> CellUpdate<T> {
     }
 }
 
-// A function that takes a cell and its neighbours and returns a CellUpdate instance
-type ProcessFuncT<C, G> =
-    Box<dyn Fn(&C, &Vec<&C>, &G) -> (Vec<CellUpdate<C>>, Vec<GlobalUpdate<G>>)>;
+// A function that takes a cell, its neighbours and the model parameters and returns a CellUpdate instance
+//
+// Process functions must be thread safe so that separate runs can share them.
+type ProcessFuncT<C, G> = Box<
+    dyn Fn(&C, &Vec<&C>, &G, &Parameters) -> (Vec<CellUpdate<C>>, Vec<GlobalUpdate<G>>)
+        + Send
+        + Sync,
+>;
 
 pub struct Process<C: CellStateBase, G: GlobalStateBase> {
     pub id: u32,
@@ -76,9 +82,10 @@ pub fn run_process<C: CellStateBase, G: GlobalStateBase>(
     process: &Process<C, G>,
     neighbours: &Vec<&C>, // A list of the neighbours states
     global_state: &G,
+    parameters: &Parameters,
 ) -> (Vec<CellUpdate<C>>, Vec<GlobalUpdate<G>>) {
     let func = &process.func;
-    let updates = func(&cell, &neighbours, &global_state);
+    let updates = func(cell, neighbours, global_state, parameters);
     let cell_updates: Vec<CellUpdate<C>> = updates.0;
     let global_updates: Vec<GlobalUpdate<G>> = updates.1;
     // TODO: implement global updates
@@ -91,6 +98,7 @@ pub fn run_processes<C: CellStateBase, G: GlobalStateBase>(
     network: &Vec<Vec<CellIndex>>,
     processes: &Vec<&Process<C, G>>,
     global_state: &G,
+    parameters: &Parameters,
 ) -> (Vec<CellUpdate<C>>, Vec<GlobalUpdate<G>>) {
     let mut cell_updates: Vec<CellUpdate<C>> = Vec::new();
     let mut global_updates: Vec<GlobalUpdate<G>> = Vec::new();
@@ -103,7 +111,8 @@ pub fn run_processes<C: CellStateBase, G: GlobalStateBase>(
             .map(|CellIndex(id)| &cells[*id as usize])
             .collect::<Vec<_>>();
         for process in processes.iter() {
-            let mut updates = run_process::<C, G>(&cell, &process, &neighbours, &global_state);
+            let mut updates =
+                run_process::<C, G>(cell, process, &neighbours, global_state, parameters);
             cell_updates.append(&mut updates.0);
             global_updates.append(&mut updates.1);
         }
@@ -117,6 +126,7 @@ pub fn run_process_on_cells<C: CellStateBase, G: GlobalStateBase>(
     network: &Vec<Vec<CellIndex>>,
     process: &Process<C, G>,
     global_state: &G,
+    parameters: &Parameters,
 ) -> (Vec<CellUpdate<C>>, Vec<GlobalUpdate<G>>) {
     let mut cell_updates: Vec<CellUpdate<C>> = Vec::new();
     let mut global_updates: Vec<GlobalUpdate<G>> = Vec::new();
@@ -128,7 +138,7 @@ pub fn run_process_on_cells<C: CellStateBase, G: GlobalStateBase>(
             // Note we use tuple struct destructuring here
             .map(|CellIndex(id)| &cells[*id as usize])
             .collect::<Vec<_>>();
-        let mut updates = run_process::<C, G>(&cell, &process, &neighbours, &global_state);
+        let mut updates = run_process::<C, G>(cell, process, &neighbours, global_state, parameters);
         cell_updates.append(&mut updates.0);
         global_updates.append(&mut updates.1);
     }
@@ -165,6 +175,7 @@ mod tests {
         cell_state: &CellState,
         _neighbours: &Vec<&CellState>,
         global_state: &GlobalState,
+        _parameters: &Parameters,
     ) -> (Vec<CellUpdate<CellState>>, Vec<GlobalUpdate<GlobalState>>) {
         let a = global_state.population;
         (
@@ -282,6 +293,7 @@ mod tests {
                 &processes[0],
                 &neighbours,
                 &global_state,
+                &Parameters::default(),
             )
         }
 
@@ -320,6 +332,7 @@ mod tests {
                 &network,
                 &processes.iter().collect(),
                 &global_state,
+                &Parameters::default(),
             )
         }
 
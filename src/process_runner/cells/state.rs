@@ -1,4 +1,6 @@
 use geo::Point;
+use serde::Deserialize;
+use serde::Serialize;
 use std::fmt;
 
 pub trait CellStateBase: fmt::Debug + Clone {
@@ -7,7 +9,7 @@ pub trait CellStateBase: fmt::Debug + Clone {
     fn randomize(&self) -> Self;
 }
 
-#[derive(Debug, Clone, PartialEq, Copy, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Copy, Eq, Hash, Serialize, Deserialize)]
 pub struct CellIndex(pub u32);
 
 impl fmt::Display for CellIndex {
@@ -17,16 +19,16 @@ impl fmt::Display for CellIndex {
     }
 }
 
-impl Into<String> for CellIndex {
-    fn into(self) -> String {
-        let CellIndex(v) = self;
+impl From<CellIndex> for String {
+    fn from(src: CellIndex) -> String {
+        let CellIndex(v) = src;
         format!("{}", v)
     }
 }
 
-impl Into<usize> for CellIndex {
-    fn into(self) -> usize {
-        let CellIndex(v) = self;
+impl From<CellIndex> for usize {
+    fn from(src: CellIndex) -> usize {
+        let CellIndex(v) = src;
         v as usize
     }
 }
@@ -34,7 +36,7 @@ impl Into<usize> for CellIndex {
 impl From<CellIndex> for u32 {
     fn from(src: CellIndex) -> u32 {
         let CellIndex(v) = src;
-        v as u32
+        v
     }
 }
 
@@ -0,0 +1,583 @@
+/// Ensemble Module
+///
+/// Run replicates of the same initial state and processes with different seeds
+/// and summarise cell and global fields across the replicates.
+///
+/// Replicates are advanced together one iteration at a time so only the current
+/// state of each replicate is held in memory. Each worker thread keeps its
+/// replicates for the whole run and sends back the field values of recorded
+/// iterations. Summaries are produced for each recorded iteration and can be
+/// streamed to a callback rather than collected.
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::process_runner::cells::run::Process as CellProcess;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::random;
+use crate::process_runner::recorder::percentile_of_sorted;
+use crate::process_runner::recorder::CellFieldFn;
+use crate::process_runner::recorder::GlobalFieldFn;
+use crate::process_runner::run::run_iteration;
+use crate::process_runner::state::IterationState;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnsembleError {
+    /// Cells are summarised by index so every replicate needs the same number
+    CellCountMismatch {
+        iteration: u32,
+        replicate: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The quantile is not a finite number in the range 0-1
+    InvalidQuantile(f64),
+}
+
+impl fmt::Display for EnsembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnsembleError::CellCountMismatch {
+                iteration,
+                replicate,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Replicate {} has {} cells at iteration {} but replicate 0 has {}",
+                replicate, found, iteration, expected
+            ),
+            EnsembleError::InvalidQuantile(q) => {
+                write!(f, "Invalid quantile: {} is not in the range 0-1", q)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnsembleError {}
+
+/// Summary of a value across replicates
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub mean: f64,
+    /// Sample variance. Zero if there is a single replicate
+    pub variance: f64,
+    /// Values at each of the ensemble quantiles
+    pub quantiles: Vec<f64>,
+}
+
+impl Summary {
+    /// Summarise the values with the quantiles, which must be in the range 0-1
+    pub fn from_values(mut values: Vec<f64>, quantiles: &[f64]) -> Summary {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = match values.len() {
+            0 | 1 => 0.0,
+            _ => values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0),
+        };
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        Summary {
+            mean,
+            variance,
+            quantiles: quantiles
+                .iter()
+                .map(|q| percentile_of_sorted(&values, q * 100.0))
+                .collect(),
+        }
+    }
+}
+
+/// Summaries of a single iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnsembleRecord {
+    pub iteration: u32,
+    /// Summaries for each cell field, indexed by field then cell
+    pub cell_fields: Vec<Vec<Summary>>,
+    /// Summaries for each global field
+    pub global_fields: Vec<Summary>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnsembleResults {
+    pub cell_field_names: Vec<String>,
+    pub global_field_names: Vec<String>,
+    pub quantiles: Vec<f64>,
+    pub records: Vec<EnsembleRecord>,
+}
+
+impl EnsembleResults {
+    pub fn iterations(&self) -> Vec<u32> {
+        self.records.iter().map(|r| r.iteration).collect()
+    }
+
+    /// Get the summary of a cell field for every recorded iteration
+    pub fn cell_series(&self, field: &str, cell_index: usize) -> Option<Vec<&Summary>> {
+        let field_index = self.cell_field_names.iter().position(|n| n == field)?;
+        self.records
+            .iter()
+            .map(|r| r.cell_fields[field_index].get(cell_index))
+            .collect()
+    }
+
+    /// Get the summary of a global field for every recorded iteration
+    pub fn global_series(&self, field: &str) -> Option<Vec<&Summary>> {
+        let field_index = self.global_field_names.iter().position(|n| n == field)?;
+        Some(
+            self.records
+                .iter()
+                .map(|r| &r.global_fields[field_index])
+                .collect(),
+        )
+    }
+
+    /// Export in long format with one row per iteration, field and cell
+    ///
+    /// The cell column is empty for global fields.
+    pub fn to_csv(&self) -> String {
+        let mut columns = vec![
+            "iteration".to_owned(),
+            "field".to_owned(),
+            "cell".to_owned(),
+            "mean".to_owned(),
+            "variance".to_owned(),
+        ];
+        columns.extend(self.quantiles.iter().map(|q| format!("q{}", q)));
+        let mut out = columns.join(",");
+        out.push('\n');
+        let row = |iteration: u32, field: &str, cell: String, summary: &Summary| {
+            let mut values = vec![
+                iteration.to_string(),
+                field.to_owned(),
+                cell,
+                summary.mean.to_string(),
+                summary.variance.to_string(),
+            ];
+            values.extend(summary.quantiles.iter().map(|v| v.to_string()));
+            values.join(",") + "\n"
+        };
+        for record in self.records.iter() {
+            for (name, summary) in self.global_field_names.iter().zip(&record.global_fields) {
+                out.push_str(&row(record.iteration, name, "".to_owned(), summary));
+            }
+            for (name, cells) in self.cell_field_names.iter().zip(&record.cell_fields) {
+                for (i, summary) in cells.iter().enumerate() {
+                    out.push_str(&row(record.iteration, name, i.to_string(), summary));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// A replicate with its own random stream
+struct Replicate<C: CellStateBase, G: GlobalStateBase> {
+    state: Option<IterationState<C, G>>,
+    rng: Option<StdRng>,
+}
+
+/// The field values of a replicate at a recorded iteration
+#[derive(Debug, Clone, PartialEq)]
+struct ReplicateValues {
+    cells: usize,
+    /// Values indexed by field then cell
+    cell_fields: Vec<Vec<f64>>,
+    global_fields: Vec<f64>,
+}
+
+pub struct Ensemble<C: CellStateBase, G: GlobalStateBase> {
+    replicates: usize,
+    iterations: u32,
+    base_seed: u64,
+    record_interval: u32,
+    threads: usize,
+    update_per_process: bool,
+    quantiles: Vec<f64>,
+    cell_fields: Vec<(String, CellFieldFn<C>)>,
+    global_fields: Vec<(String, GlobalFieldFn<G>)>,
+}
+
+impl<C: CellStateBase + Send, G: GlobalStateBase + Send> Ensemble<C, G> {
+    /// Create an ensemble of replicate runs
+    ///
+    /// Defaults to recording every iteration with the 0.05, 0.5 and 0.95 quantiles.
+    pub fn new(replicates: usize, iterations: u32) -> Ensemble<C, G> {
+        Ensemble {
+            replicates,
+            iterations,
+            base_seed: 0,
+            record_interval: 1,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            update_per_process: true,
+            quantiles: vec![0.05, 0.5, 0.95],
+            cell_fields: vec![],
+            global_fields: vec![],
+        }
+    }
+
+    /// Set the seed that the seed of each replicate is derived from
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.base_seed = seed;
+        self
+    }
+
+    /// Only summarise every nth iteration
+    pub fn with_record_interval(mut self, record_interval: u32) -> Self {
+        self.record_interval = record_interval.max(1);
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_update_per_process(mut self, update_per_process: bool) -> Self {
+        self.update_per_process = update_per_process;
+        self
+    }
+
+    /// Set the quantiles (0-1) calculated for each summary
+    pub fn with_quantiles(mut self, quantiles: Vec<f64>) -> Result<Self, EnsembleError> {
+        if let Some(q) = quantiles.iter().find(|q| !(0.0..=1.0).contains(*q)) {
+            return Err(EnsembleError::InvalidQuantile(*q));
+        }
+        self.quantiles = quantiles;
+        Ok(self)
+    }
+
+    pub fn with_cell_field(mut self, name: &str, field: CellFieldFn<C>) -> Self {
+        self.cell_fields.push((name.to_owned(), field));
+        self
+    }
+
+    pub fn with_global_field(mut self, name: &str, field: GlobalFieldFn<G>) -> Self {
+        self.global_fields.push((name.to_owned(), field));
+        self
+    }
+
+    fn is_recorded(&self, iteration: u32) -> bool {
+        iteration.is_multiple_of(self.record_interval)
+    }
+
+    fn replicate_values(&self, state: &IterationState<C, G>) -> ReplicateValues {
+        ReplicateValues {
+            cells: state.cells.len(),
+            cell_fields: self
+                .cell_fields
+                .iter()
+                .map(|(_, field)| state.cells.iter().map(field).collect())
+                .collect(),
+            global_fields: self
+                .global_fields
+                .iter()
+                .map(|(_, field)| field(&state.global_state))
+                .collect(),
+        }
+    }
+
+    fn summarise(
+        &self,
+        iteration: u32,
+        replicates: &[ReplicateValues],
+    ) -> Result<EnsembleRecord, EnsembleError> {
+        let cell_count = replicates.first().map_or(0, |r| r.cells);
+        if let Some((replicate, r)) = replicates
+            .iter()
+            .enumerate()
+            .find(|(_, r)| r.cells != cell_count)
+        {
+            return Err(EnsembleError::CellCountMismatch {
+                iteration,
+                replicate,
+                expected: cell_count,
+                found: r.cells,
+            });
+        }
+        Ok(EnsembleRecord {
+            iteration,
+            cell_fields: (0..self.cell_fields.len())
+                .map(|f| {
+                    (0..cell_count)
+                        .map(|i| {
+                            let values = replicates.iter().map(|r| r.cell_fields[f][i]).collect();
+                            Summary::from_values(values, &self.quantiles)
+                        })
+                        .collect()
+                })
+                .collect(),
+            global_fields: (0..self.global_fields.len())
+                .map(|f| {
+                    let values = replicates.iter().map(|r| r.global_fields[f]).collect();
+                    Summary::from_values(values, &self.quantiles)
+                })
+                .collect(),
+        })
+    }
+
+    /// Run the ensemble passing the summary of each recorded iteration to a callback
+    ///
+    /// Returns an error if the replicates have different numbers of cells.
+    pub fn run_with<F: FnMut(EnsembleRecord)>(
+        &self,
+        initial_state: &IterationState<C, G>,
+        parameters: &Parameters,
+        cell_processes: &Vec<CellProcess<C, G>>,
+        global_processes: &Vec<GlobalProcess<C, G>>,
+        mut on_record: F,
+    ) -> Result<(), EnsembleError> {
+        let chunk_size = self.replicates.div_ceil(self.threads).max(1);
+        let mut chunks: Vec<Vec<(usize, Replicate<C, G>)>> = vec![];
+        for i in 0..self.replicates {
+            if i % chunk_size == 0 {
+                chunks.push(vec![]);
+            }
+            chunks.last_mut().unwrap().push((
+                i,
+                Replicate {
+                    state: Some(initial_state.clone()),
+                    rng: Some(StdRng::seed_from_u64(random::derive_seed(
+                        self.base_seed,
+                        i as u64,
+                    ))),
+                },
+            ));
+        }
+
+        thread::scope(|scope| {
+            // Each worker is told the iteration to run and replies with the
+            // values of its replicates if the iteration is recorded
+            let workers = chunks
+                .into_iter()
+                .map(|mut chunk| {
+                    let (iteration_sender, iteration_receiver) = mpsc::channel::<u32>();
+                    let (values_sender, values_receiver) = mpsc::channel();
+                    scope.spawn(move || {
+                        for iteration in iteration_receiver {
+                            let mut values = vec![];
+                            for (i, replicate) in chunk.iter_mut() {
+                                let previous_rng =
+                                    random::replace_rng(replicate.rng.take().unwrap());
+                                let state = run_iteration(
+                                    cell_processes,
+                                    global_processes,
+                                    replicate.state.take().unwrap(),
+                                    parameters,
+                                    self.update_per_process,
+                                );
+                                replicate.rng = Some(random::replace_rng(previous_rng));
+                                if self.is_recorded(iteration) {
+                                    values.push((*i, self.replicate_values(&state)));
+                                }
+                                replicate.state = Some(state);
+                            }
+                            if values_sender.send(values).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    (iteration_sender, values_receiver)
+                })
+                .collect::<Vec<_>>();
+
+            for iteration in 1..=self.iterations {
+                for (iteration_sender, _) in workers.iter() {
+                    // A worker only stops early if it panicked, which the
+                    // scope raises when it ends
+                    if iteration_sender.send(iteration).is_err() {
+                        return Ok(());
+                    }
+                }
+                let mut values = Vec::with_capacity(self.replicates);
+                for (_, values_receiver) in workers.iter() {
+                    match values_receiver.recv() {
+                        Ok(worker_values) => values.extend(worker_values),
+                        Err(_) => return Ok(()),
+                    }
+                }
+                if self.is_recorded(iteration) {
+                    values.sort_by_key(|(i, _)| *i);
+                    let values = values.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+                    on_record(self.summarise(iteration, &values)?);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Run the ensemble and collect the summaries of every recorded iteration
+    pub fn run(
+        &self,
+        initial_state: &IterationState<C, G>,
+        parameters: &Parameters,
+        cell_processes: &Vec<CellProcess<C, G>>,
+        global_processes: &Vec<GlobalProcess<C, G>>,
+    ) -> Result<EnsembleResults, EnsembleError> {
+        let mut records = vec![];
+        self.run_with(
+            initial_state,
+            parameters,
+            cell_processes,
+            global_processes,
+            |record| records.push(record),
+        )?;
+        Ok(EnsembleResults {
+            cell_field_names: self.cell_fields.iter().map(|(n, _)| n.to_owned()).collect(),
+            global_field_names: self
+                .global_fields
+                .iter()
+                .map(|(n, _)| n.to_owned())
+                .collect(),
+            quantiles: self.quantiles.clone(),
+            records,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::cells::run::CellUpdate;
+    use crate::process_runner::examples::example_processes::*;
+    use crate::process_runner::examples::example_state::*;
+    use crate::process_runner::global::run::GlobalUpdate;
+    use crate::process_runner::recorder::numeric_field_reader;
+    use geo::point;
+    use rand::Rng;
+
+    fn random_population(
+        cell_state: &CellState,
+        _neighbours: &Vec<&CellState>,
+        _global_state: &GlobalState,
+        _parameters: &Parameters,
+    ) -> (Vec<CellUpdate<CellState>>, Vec<GlobalUpdate<GlobalState>>) {
+        let new_population: u32 = random::with_rng(|rng| rng.gen_range(0..100));
+        (
+            vec![CellUpdate {
+                target_cell: cell_state.id,
+                action: Box::new(move |mut cell_state: CellState| -> CellState {
+                    cell_state.population = new_population;
+                    cell_state
+                }),
+            }],
+            vec![],
+        )
+    }
+
+    fn demo_state() -> IterationState<CellState, GlobalState> {
+        IterationState {
+            global_state: GlobalState::default(),
+            cells: (0..3)
+                .map(|i| CellState::new(i, point!(x: i as f64 * 10.0, y: 0.0), 100))
+                .collect(),
+            network: vec![vec![]],
+        }
+    }
+
+    fn demo_ensemble(threads: usize) -> Ensemble<CellState, GlobalState> {
+        Ensemble::new(8, 4)
+            .with_seed(3)
+            .with_threads(threads)
+            .with_cell_field("population", numeric_field_reader("population"))
+            .with_global_field("iterations", numeric_field_reader("iterations"))
+    }
+
+    #[test]
+    fn should_summarise_values() {
+        let summary = Summary::from_values(vec![1.0, 2.0, 3.0, 4.0], &[0.0, 0.5, 1.0]);
+        assert_eq!(summary.mean, 2.5);
+        assert!((summary.variance - 5.0 / 3.0).abs() < 1e-12);
+        assert_eq!(summary.quantiles, vec![1.0, 2.5, 4.0]);
+    }
+
+    #[test]
+    fn should_have_no_variance_for_deterministic_processes() {
+        let results = demo_ensemble(2)
+            .run(
+                &demo_state(),
+                &default_parameters(),
+                &vec![CellProcess::new(0, Box::new(population_growth))],
+                &default_global_processes(),
+            )
+            .unwrap();
+        assert_eq!(results.iterations(), vec![1, 2, 3, 4]);
+        let series = results.cell_series("population", 0).unwrap();
+        assert_eq!(series[0].mean, 110.0);
+        assert_eq!(series[0].variance, 0.0);
+        let iterations = results.global_series("iterations").unwrap();
+        assert_eq!(iterations[3].mean, 4.0);
+    }
+
+    #[test]
+    fn should_vary_between_replicates_and_be_reproducible() {
+        let cell_processes = vec![CellProcess::new(0, Box::new(random_population))];
+        let run = |threads| {
+            demo_ensemble(threads)
+                .run(
+                    &demo_state(),
+                    &default_parameters(),
+                    &cell_processes,
+                    &default_global_processes(),
+                )
+                .unwrap()
+        };
+        let results = run(1);
+        let series = results.cell_series("population", 1).unwrap();
+        assert!(series.iter().all(|s| s.variance > 0.0));
+        assert!(series.iter().all(|s| s.quantiles[0] <= s.quantiles[2]));
+        assert_eq!(results, run(3));
+    }
+
+    #[test]
+    fn should_only_summarise_recorded_iterations() {
+        let mut iterations = vec![];
+        demo_ensemble(2)
+            .with_record_interval(2)
+            .run_with(
+                &demo_state(),
+                &default_parameters(),
+                &default_cell_processes(),
+                &default_global_processes(),
+                |record| iterations.push(record.iteration),
+            )
+            .unwrap();
+        assert_eq!(iterations, vec![2, 4]);
+    }
+
+    #[test]
+    fn should_reject_invalid_quantiles() {
+        for q in [-0.1, 1.5, 50.0, f64::NAN, f64::INFINITY].iter() {
+            assert!(matches!(
+                demo_ensemble(1).with_quantiles(vec![0.5, *q]),
+                Err(EnsembleError::InvalidQuantile(_))
+            ));
+        }
+        let ensemble = demo_ensemble(1).with_quantiles(vec![0.0, 1.0]).unwrap();
+        assert_eq!(ensemble.quantiles, vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn should_reject_replicates_with_different_cell_counts() {
+        let ensemble = demo_ensemble(1);
+        let mut state = demo_state();
+        let values = ensemble.replicate_values(&state);
+        state.cells.pop();
+        let fewer_cells = ensemble.replicate_values(&state);
+        assert!(ensemble
+            .summarise(1, &[values.clone(), values.clone()])
+            .is_ok());
+        assert_eq!(
+            ensemble.summarise(1, &[values, fewer_cells]),
+            Err(EnsembleError::CellCountMismatch {
+                iteration: 1,
+                replicate: 1,
+                expected: 3,
+                found: 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,303 @@
+/// Events Module
+///
+/// Scheduled interventions that are applied by the simulation at the start of
+/// an iteration before any processes run.
+///
+/// An event applies on every iteration that its trigger is active. For example
+/// an event with an `At(20)` trigger applies once, while an event that disables
+/// a process with a `From(50)` trigger keeps the process disabled from iteration 50.
+/// As events only depend on the iteration and the state, a scenario is reproducible.
+use std::fmt;
+
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::state::IterationState;
+
+/// A function that selects the cells an event applies to
+pub type CellPredicate<C, G> = Box<dyn Fn(&C, &G) -> bool + Send + Sync>;
+
+/// A function that modifies a cell
+pub type CellEventFn<C> = Box<dyn Fn(C) -> C + Send + Sync>;
+
+/// A function that modifies the global state
+pub type GlobalEventFn<G> = Box<dyn Fn(G) -> G + Send + Sync>;
+
+/// A condition on the iteration number and global state
+pub type ConditionFn<G> = Box<dyn Fn(u32, &G) -> bool + Send + Sync>;
+
+pub enum Trigger<G: GlobalStateBase> {
+    /// Only at this iteration
+    At(u32),
+    /// At this iteration and every iteration after
+    From(u32),
+    /// Between the two iterations inclusive
+    Between(u32, u32),
+    /// Every interval iterations starting at start
+    Every { start: u32, interval: u32 },
+    /// Whenever the condition on the iteration and global state is true
+    When(ConditionFn<G>),
+}
+
+impl<G: GlobalStateBase> Trigger<G> {
+    pub fn is_active(&self, iteration: u32, global_state: &G) -> bool {
+        match self {
+            Trigger::At(i) => iteration == *i,
+            Trigger::From(i) => iteration >= *i,
+            Trigger::Between(start, end) => iteration >= *start && iteration <= *end,
+            Trigger::Every { start, interval } => {
+                iteration >= *start && (iteration - start).is_multiple_of((*interval).max(1))
+            }
+            Trigger::When(condition) => condition(iteration, global_state),
+        }
+    }
+}
+
+impl<G: GlobalStateBase> fmt::Debug for Trigger<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::At(i) => write!(f, "At({})", i),
+            Trigger::From(i) => write!(f, "From({})", i),
+            Trigger::Between(start, end) => write!(f, "Between({}, {})", start, end),
+            Trigger::Every { start, interval } => write!(f, "Every({}, {})", start, interval),
+            Trigger::When(_) => write!(f, "When(..)"),
+        }
+    }
+}
+
+pub enum EventAction<C: CellStateBase, G: GlobalStateBase> {
+    /// Update every cell that matches the predicate
+    UpdateCells {
+        predicate: CellPredicate<C, G>,
+        update: CellEventFn<C>,
+    },
+    UpdateGlobal(GlobalEventFn<G>),
+    /// Do not run the cell process with this id while the trigger is active
+    DisableCellProcess(u32),
+    /// Do not run the global process with this id while the trigger is active
+    DisableGlobalProcess(u32),
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> fmt::Debug for EventAction<C, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventAction::UpdateCells { .. } => write!(f, "UpdateCells"),
+            EventAction::UpdateGlobal(_) => write!(f, "UpdateGlobal"),
+            EventAction::DisableCellProcess(id) => write!(f, "DisableCellProcess({})", id),
+            EventAction::DisableGlobalProcess(id) => write!(f, "DisableGlobalProcess({})", id),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Event<C: CellStateBase, G: GlobalStateBase> {
+    pub name: String,
+    pub trigger: Trigger<G>,
+    pub action: EventAction<C, G>,
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> Event<C, G> {
+    pub fn new(name: &str, trigger: Trigger<G>, action: EventAction<C, G>) -> Event<C, G> {
+        Event {
+            name: name.to_owned(),
+            trigger,
+            action,
+        }
+    }
+}
+
+/// Events in the order they are applied
+#[derive(Debug)]
+pub struct EventSchedule<C: CellStateBase, G: GlobalStateBase> {
+    events: Vec<Event<C, G>>,
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> Default for EventSchedule<C, G> {
+    fn default() -> Self {
+        EventSchedule { events: vec![] }
+    }
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> EventSchedule<C, G> {
+    pub fn new(events: Vec<Event<C, G>>) -> EventSchedule<C, G> {
+        EventSchedule { events }
+    }
+
+    pub fn add(&mut self, event: Event<C, G>) {
+        self.events.push(event);
+    }
+
+    pub fn with_event(mut self, event: Event<C, G>) -> Self {
+        self.add(event);
+        self
+    }
+
+    pub fn events(&self) -> &Vec<Event<C, G>> {
+        &self.events
+    }
+
+    /// Get the events that are active for the iteration
+    ///
+    /// Triggers are checked against the global state at the start of the iteration.
+    pub fn active_events(&self, iteration: u32, global_state: &G) -> Vec<&Event<C, G>> {
+        self.events
+            .iter()
+            .filter(|e| e.trigger.is_active(iteration, global_state))
+            .collect()
+    }
+
+    /// Apply the cell and global updates of the active events
+    pub fn apply(&self, iteration: u32, state: IterationState<C, G>) -> IterationState<C, G> {
+        let active_events = self.active_events(iteration, &state.global_state);
+        active_events
+            .iter()
+            .fold(state, |mut state, event| match &event.action {
+                EventAction::UpdateCells { predicate, update } => {
+                    let global_state = &state.global_state;
+                    state.cells = state
+                        .cells
+                        .into_iter()
+                        .map(|cell| match predicate(&cell, global_state) {
+                            true => update(cell),
+                            false => cell,
+                        })
+                        .collect();
+                    state
+                }
+                EventAction::UpdateGlobal(update) => {
+                    state.global_state = update(state.global_state);
+                    state
+                }
+                EventAction::DisableCellProcess(_) | EventAction::DisableGlobalProcess(_) => state,
+            })
+    }
+
+    /// The ids of the cell processes disabled for the iteration
+    pub fn disabled_cell_processes(&self, iteration: u32, global_state: &G) -> Vec<u32> {
+        self.active_events(iteration, global_state)
+            .iter()
+            .filter_map(|e| match e.action {
+                EventAction::DisableCellProcess(id) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The ids of the global processes disabled for the iteration
+    pub fn disabled_global_processes(&self, iteration: u32, global_state: &G) -> Vec<u32> {
+        self.active_events(iteration, global_state)
+            .iter()
+            .filter_map(|e| match e.action {
+                EventAction::DisableGlobalProcess(id) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_state::*;
+    use geo::point;
+
+    fn demo_state() -> IterationState<CellState, GlobalState> {
+        IterationState {
+            global_state: GlobalState::default(),
+            cells: (0..4)
+                .map(|i| CellState::new(i, point!(x: i as f64, y: 0.0), 10))
+                .collect(),
+            network: vec![vec![]],
+        }
+    }
+
+    fn set_population_event(trigger: Trigger<GlobalState>) -> Event<CellState, GlobalState> {
+        Event::new(
+            "Set population in west",
+            trigger,
+            EventAction::UpdateCells {
+                predicate: Box::new(|cell: &CellState, _| cell.position.x() < 2.0),
+                update: Box::new(|mut cell: CellState| {
+                    cell.population = 99;
+                    cell
+                }),
+            },
+        )
+    }
+
+    #[test]
+    fn should_check_if_triggers_are_active() {
+        let global_state = GlobalState::new(10);
+        assert!(Trigger::At(3).is_active(3, &global_state));
+        assert!(!Trigger::At(3).is_active(4, &global_state));
+        assert!(Trigger::From(3).is_active(40, &global_state));
+        assert!(!Trigger::Between(3, 5).is_active(6, &global_state));
+        let every = Trigger::Every {
+            start: 2,
+            interval: 3,
+        };
+        assert_eq!(
+            (0..10)
+                .filter(|i| every.is_active(*i, &global_state))
+                .collect::<Vec<_>>(),
+            vec![2, 5, 8]
+        );
+        let when: Trigger<GlobalState> =
+            Trigger::When(Box::new(|_, g: &GlobalState| g.population > 5));
+        assert!(when.is_active(0, &global_state));
+    }
+
+    #[test]
+    fn should_update_matching_cells_when_active() {
+        let schedule = EventSchedule::default().with_event(set_population_event(Trigger::At(2)));
+        let state = schedule.apply(1, demo_state());
+        assert!(state.cells.iter().all(|c| c.population == 10));
+        let state = schedule.apply(2, state);
+        assert_eq!(
+            state.cells.iter().map(|c| c.population).collect::<Vec<_>>(),
+            vec![99, 99, 10, 10]
+        );
+    }
+
+    #[test]
+    fn should_update_global_state() {
+        let schedule = EventSchedule::new(vec![Event::new(
+            "Reset global population",
+            Trigger::From(1),
+            EventAction::UpdateGlobal(Box::new(|mut g: GlobalState| {
+                g.population = 0;
+                g
+            })),
+        )]);
+        let mut state = demo_state();
+        state.global_state.population = 5;
+        assert_eq!(schedule.apply(1, state).global_state.population, 0);
+    }
+
+    #[test]
+    fn should_list_disabled_processes() {
+        let schedule: EventSchedule<CellState, GlobalState> = EventSchedule::new(vec![
+            Event::new(
+                "Disable migration",
+                Trigger::From(50),
+                EventAction::DisableCellProcess(1),
+            ),
+            Event::new(
+                "Pause global process",
+                Trigger::Between(10, 20),
+                EventAction::DisableGlobalProcess(0),
+            ),
+        ]);
+        let global_state = GlobalState::default();
+        assert!(schedule
+            .disabled_cell_processes(49, &global_state)
+            .is_empty());
+        assert_eq!(schedule.disabled_cell_processes(60, &global_state), vec![1]);
+        assert_eq!(
+            schedule.disabled_global_processes(15, &global_state),
+            vec![0]
+        );
+        assert!(schedule
+            .disabled_global_processes(21, &global_state)
+            .is_empty());
+    }
+}
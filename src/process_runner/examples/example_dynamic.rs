@@ -0,0 +1,114 @@
+/// Example Dynamic Model
+///
+/// Processes for `DynamicCellState` cells whose fields are chosen by a run
+/// config. Each cell process is registered only when the schema has the fields
+/// it needs, so configs can only choose processes that suit their cells.
+use super::example_processes::default_parameters;
+use super::example_state::GlobalState;
+use crate::process_runner::cells::dynamic::CellSchema;
+use crate::process_runner::cells::dynamic::DynamicCellState;
+use crate::process_runner::cells::run::CellUpdate;
+use crate::process_runner::cells::run::Process as CellProcess;
+use crate::process_runner::global::run::GlobalUpdate;
+use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::registry::ProcessInfo;
+use crate::process_runner::registry::ProcessRegistry;
+
+/// Registry of the processes that suit cells of the schema
+pub fn dynamic_registry(schema: &CellSchema) -> ProcessRegistry<DynamicCellState, GlobalState> {
+    let mut registry = ProcessRegistry::new().with_global_process(
+        ProcessInfo::new(0, "count_iterations", "Increase the iteration count by 1"),
+        Box::new(|id| {
+            GlobalProcess::new(
+                id,
+                Box::new(|_, _, _| {
+                    vec![GlobalUpdate {
+                        id: "count_iterations".to_owned(),
+                        action: Box::new(|mut global_state: GlobalState| {
+                            global_state.iterations += 1;
+                            global_state
+                        }),
+                    }]
+                }),
+            )
+        }),
+    );
+    if let Ok(population) = schema.handle::<i64>("population") {
+        registry = registry.with_cell_process(
+            ProcessInfo::new(
+                0,
+                "population_growth",
+                "Grow the int population field by the growth rate",
+            )
+            .with_parameter(default_parameters().specs()[0].clone()),
+            Box::new(move |id| {
+                CellProcess::new(
+                    id,
+                    Box::new(move |cell: &DynamicCellState, _, _, parameters| {
+                        let growth_rate = match parameters.get_f64("growth_rate") {
+                            Ok(growth_rate) => growth_rate,
+                            Err(_) => return (vec![], vec![]),
+                        };
+                        let change = cell.get(population) as f64 * growth_rate / 100.0;
+                        (
+                            vec![CellUpdate::new(
+                                cell.id,
+                                Box::new(move |mut cell: DynamicCellState| {
+                                    let value = cell.get(population) as f64 + change;
+                                    cell.set(population, value.round().max(0.0) as i64);
+                                    cell
+                                }),
+                            )],
+                            vec![],
+                        )
+                    }),
+                )
+            }),
+        );
+    }
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::cells::dynamic::FieldSpec;
+    use crate::process_runner::cells::dynamic::FieldType;
+    use crate::process_runner::cells::state::CellIndex;
+    use crate::process_runner::fields::Introspect;
+    use crate::process_runner::run::run_iteration;
+    use crate::process_runner::state::IterationState;
+    use geo::point;
+    use std::sync::Arc;
+
+    #[test]
+    fn should_register_processes_for_the_schema_fields() {
+        let schema = Arc::new(
+            CellSchema::from_specs(vec![FieldSpec::new("population", FieldType::Int)]).unwrap(),
+        );
+        let registry = dynamic_registry(&schema);
+        let mut cell = DynamicCellState::new(&schema, CellIndex(0), point!(x: 0.0, y: 0.0));
+        cell.set(schema.handle::<i64>("population").unwrap(), 100);
+        let state = run_iteration(
+            &registry.cell_processes(&["population_growth"]).unwrap(),
+            &registry.global_processes(&["count_iterations"]).unwrap(),
+            IterationState {
+                cells: vec![cell],
+                global_state: GlobalState::default(),
+                network: vec![vec![]],
+            },
+            &registry.parameters(),
+            true,
+        );
+        assert_eq!(
+            state.cells[0].numeric_field_value("population"),
+            Some(110.0)
+        );
+        assert_eq!(state.global_state.iterations, 1);
+
+        let schema = CellSchema::from_specs(vec![FieldSpec::new("height", FieldType::Float)]);
+        assert!(dynamic_registry(&schema.unwrap())
+            .cell_process("population_growth")
+            .is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use super::example_processes::default_parameters;
+use super::example_processes::default_registry;
+use super::example_processes::CellProcessT;
+use super::example_processes::GlobalProcessT;
+use super::example_processes::DEFAULT_CELL_PROCESS_NAMES;
+use super::example_state::CellState;
+use super::example_state::GlobalState;
+use crate::process_runner::cells::run::CellUpdate;
+use crate::process_runner::gui::numeric_layers;
+use crate::process_runner::gui::CellAction;
+use crate::process_runner::gui::GuiModel;
+use crate::process_runner::gui::Layer;
+use crate::process_runner::parameters::Parameters;
+use geo::Point;
+
+/// Action that sets the cell population to a fixed value
+pub fn set_population_action(population: u32) -> CellAction<CellState> {
+    CellAction::new(
+        &format!("setpop{}", population),
+        &format!("Set pop to {}", population),
+        "Population",
+        Box::new(move |cell: &CellState| CellUpdate {
+            target_cell: cell.id,
+            action: Box::new(move |mut cell_state: CellState| -> CellState {
+                cell_state.population = population;
+                cell_state
+            }),
+        }),
+    )
+}
+
+/// The example model as shown in the web GUI
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExampleGuiModel;
+
+impl GuiModel for ExampleGuiModel {
+    type Cell = CellState;
+    type Global = GlobalState;
+
+    fn layers(&self) -> Vec<Layer<CellState>> {
+        numeric_layers(&CellState::default())
+    }
+
+    fn cell_actions(&self) -> Vec<CellAction<CellState>> {
+        vec![
+            set_population_action(99999),
+            set_population_action(150),
+            set_population_action(99),
+            set_population_action(50),
+        ]
+    }
+
+    fn parameters(&self) -> Parameters {
+        default_parameters()
+    }
+
+    /// The default processes and population growth so the growth rate control has an effect
+    fn cell_processes(&self) -> Vec<CellProcessT> {
+        let mut names = DEFAULT_CELL_PROCESS_NAMES.to_vec();
+        names.push("population_growth");
+        default_registry()
+            .cell_processes(&names)
+            .expect("Default processes are registered")
+    }
+
+    fn global_processes(&self) -> Vec<GlobalProcessT> {
+        default_registry()
+            .global_processes(&["count_iterations"])
+            .expect("Default processes are registered")
+    }
+
+    fn new_cell(&self, id: u32, position: Point<f64>) -> CellState {
+        CellState::new(id, position, 0)
+    }
+}
@@ -4,6 +4,11 @@ use crate::process_runner::cells::run::CellUpdate;
 use crate::process_runner::cells::run::Process as CellProcess;
 use crate::process_runner::global::run::GlobalUpdate;
 use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::parameters::ParameterSpec;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::registry::ProcessInfo;
+use crate::process_runner::registry::ProcessRegistry;
 
 pub type CellProcessT = CellProcess<CellState, GlobalState>;
 pub type GlobalProcessT = GlobalProcess<CellState, GlobalState>;
@@ -12,20 +17,24 @@ pub fn increase_population_by_10_percent(
     cell_state: &CellState,
     _neighbours: &Vec<&CellState>,
     _global_state: &GlobalState,
+    _parameters: &Parameters,
 ) -> (Vec<CellUpdate<CellState>>, Vec<GlobalUpdate<GlobalState>>) {
     (
         vec![CellUpdate {
             target_cell: cell_state.id,
             action: Box::new(|mut cell_state: CellState| -> CellState {
-                cell_state.population += (cell_state.population as f64 / 10.0).floor() as u32;
+                cell_state.population = cell_state
+                    .population
+                    .saturating_add((cell_state.population as f64 / 10.0).floor() as u32);
                 cell_state
             }),
         }],
         vec![GlobalUpdate::<GlobalState> {
             id: format!("{}", cell_state.id),
             action: Box::new(|mut global_state_loc: GlobalState| -> GlobalState {
-                global_state_loc.population +=
-                    (global_state_loc.population as f64 / 10.0).floor() as u32;
+                global_state_loc.population = global_state_loc
+                    .population
+                    .saturating_add((global_state_loc.population as f64 / 10.0).floor() as u32);
                 global_state_loc
             }),
         }],
@@ -37,6 +46,7 @@ pub fn set_population_to_100(
     cell_state: &CellState,
     _neighbours: &Vec<&CellState>,
     _global_state: &GlobalState,
+    _parameters: &Parameters,
 ) -> (Vec<CellUpdate<CellState>>, Vec<GlobalUpdate<GlobalState>>) {
     (
         vec![CellUpdate {
@@ -56,6 +66,7 @@ pub fn conditional_pop_reset(
     cell_state: &CellState,
     _neighbours: &Vec<&CellState>,
     _global_state: &GlobalState,
+    _parameters: &Parameters,
 ) -> (Vec<CellUpdate<CellState>>, Vec<GlobalUpdate<GlobalState>>) {
     let new_population: u32 = if cell_state.population > 5 {
         cell_state.population
@@ -78,17 +89,46 @@ pub fn population_migration(
     cell_state: &CellState,
     neighbours: &Vec<&CellState>,
     _global_state: &GlobalState,
+    _parameters: &Parameters,
 ) -> (Vec<CellUpdate<CellState>>, Vec<GlobalUpdate<GlobalState>>) {
-    let mut movement = 0;
+    let mut movement: u32 = 0;
     for n in neighbours.iter() {
-        movement += (n.population as f64 / 10.0).ceil() as u32;
+        movement = movement.saturating_add((n.population as f64 / 10.0).ceil() as u32);
     }
     (
         vec![CellUpdate {
             target_cell: cell_state.id,
             // Note: We use the move keyword here to allow external variables to be captured by the closure
             action: Box::new(move |mut cell_state: CellState| -> CellState {
-                cell_state.population += movement as u32;
+                cell_state.population = cell_state.population.saturating_add(movement);
+                cell_state
+            }),
+        }],
+        vec![],
+    )
+}
+
+/// Example process that grows the population by the "growth_rate" parameter percentage
+///
+/// It is not one of the default processes so it only runs when it is chosen.
+/// Cells are unchanged if the parameters have no growth rate.
+pub fn population_growth(
+    cell_state: &CellState,
+    _neighbours: &Vec<&CellState>,
+    _global_state: &GlobalState,
+    parameters: &Parameters,
+) -> (Vec<CellUpdate<CellState>>, Vec<GlobalUpdate<GlobalState>>) {
+    let growth_rate = match parameters.get_f64("growth_rate") {
+        Ok(growth_rate) => growth_rate,
+        Err(_) => return (vec![], vec![]),
+    };
+    // The change is added rather than set so that it combines with other processes
+    let change = cell_state.population as f64 * growth_rate / 100.0;
+    (
+        vec![CellUpdate {
+            target_cell: cell_state.id,
+            action: Box::new(move |mut cell_state: CellState| -> CellState {
+                cell_state.population = (cell_state.population as f64 + change).max(0.0) as u32;
                 cell_state
             }),
         }],
@@ -100,6 +140,7 @@ pub fn population_migration(
 pub fn example_global_process(
     _cells: &Vec<&CellState>,
     _global_state: &GlobalState,
+    _parameters: &Parameters,
 ) -> Vec<GlobalUpdate<GlobalState>> {
     vec![GlobalUpdate {
         id: "Example global process".to_owned(),
@@ -111,6 +152,7 @@ pub fn example_global_process(
 pub fn example_global_process_iter(
     _cells: &Vec<&CellState>,
     _global_state: &GlobalState,
+    _parameters: &Parameters,
 ) -> Vec<GlobalUpdate<GlobalState>> {
     vec![GlobalUpdate {
         id: "Example global process iter".to_owned(),
@@ -138,3 +180,102 @@ pub fn default_global_processes() -> Vec<GlobalProcessT> {
         GlobalProcess::new(0, Box::new(example_global_process_iter)),
     ]
 }
+
+// Default example parameters
+#[allow(dead_code)]
+pub fn default_parameters() -> Parameters {
+    Parameters::new(vec![ParameterSpec::new(
+        "growth_rate",
+        "Growth Rate",
+        ParameterValue::Float(10.0),
+    )
+    .with_range(-200.0, 200.0)])
+}
+
+// Registry of the example processes that can be selected by name
+#[allow(dead_code)]
+pub fn default_registry() -> ProcessRegistry<CellState, GlobalState> {
+    ProcessRegistry::new()
+        .with_cell_process(
+            ProcessInfo::new(
+                0,
+                "increase_population_by_10_percent",
+                "Increase the cell population by 10%",
+            ),
+            Box::new(|id| CellProcess::new(id, Box::new(increase_population_by_10_percent))),
+        )
+        .with_cell_process(
+            ProcessInfo::new(
+                1,
+                "population_migration",
+                "Move 10% of the population of each neighbour into the cell",
+            ),
+            Box::new(|id| CellProcess::new(id, Box::new(population_migration))),
+        )
+        .with_cell_process(
+            ProcessInfo::new(
+                2,
+                "population_growth",
+                "Grow the cell population by the growth rate",
+            )
+            .with_parameter(default_parameters().specs()[0].clone()),
+            Box::new(|id| CellProcess::new(id, Box::new(population_growth))),
+        )
+        .with_cell_process(
+            ProcessInfo::new(3, "set_population_to_100", "Set the cell population to 100"),
+            Box::new(|id| CellProcess::new(id, Box::new(set_population_to_100))),
+        )
+        .with_cell_process(
+            ProcessInfo::new(
+                4,
+                "conditional_pop_reset",
+                "Set the cell population to 0 unless it is greater than 5",
+            ),
+            Box::new(|id| CellProcess::new(id, Box::new(conditional_pop_reset))),
+        )
+        .with_global_process(
+            ProcessInfo::new(0, "count_iterations", "Increase the iteration count by 1"),
+            Box::new(|id| GlobalProcess::new(id, Box::new(example_global_process_iter))),
+        )
+}
+
+/// Names of the registered processes run when none are chosen
+pub const DEFAULT_CELL_PROCESS_NAMES: [&str; 2] =
+    ["increase_population_by_10_percent", "population_migration"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::point;
+
+    #[test]
+    fn population_growth_uses_growth_rate_parameter() {
+        let cell = CellState::new(0, point!(x: 0.0, y: 0.0), 100);
+        let parameters = default_parameters()
+            .with("growth_rate", ParameterValue::Float(-50.0))
+            .unwrap();
+        let (cell_updates, _) =
+            population_growth(&cell, &vec![], &GlobalState::default(), &parameters);
+        let updated_cell = (cell_updates[0].action)(cell);
+        assert_eq!(updated_cell.population, 50);
+        let (cell_updates, _) = population_growth(
+            &updated_cell,
+            &vec![],
+            &GlobalState::default(),
+            &Parameters::default(),
+        );
+        assert!(cell_updates.is_empty());
+    }
+
+    #[test]
+    fn default_registry_matches_default_processes() {
+        let registry = default_registry();
+        let processes = registry
+            .cell_processes(&DEFAULT_CELL_PROCESS_NAMES)
+            .unwrap();
+        let ids: Vec<u32> = processes.iter().map(|p| p.id).collect();
+        let default_ids: Vec<u32> = default_cell_processes().iter().map(|p| p.id).collect();
+        assert_eq!(ids, default_ids);
+        assert_eq!(registry.parameters(), default_parameters());
+    }
+}
@@ -1,14 +1,23 @@
 use crate::process_runner::cells::state::CellIndex;
 use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::fields::FieldDescriptor;
+use crate::process_runner::fields::FieldError;
+use crate::process_runner::fields::FieldKind;
+use crate::process_runner::fields::FieldValue;
+use crate::process_runner::fields::Introspect;
 use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::random::with_rng;
 use geo::point;
 use geo::Coordinate;
 use geo::Point;
 use rand::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
 
 type PointF64 = Point<f64>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CellState {
     pub id: CellIndex,
     pub position: PointF64,
@@ -51,14 +60,61 @@ impl CellStateBase for CellState {
     }
     fn randomize(&self) -> CellState {
         let mut self_copy = self.clone();
-        let mut rng = rand::thread_rng();
-        self_copy.population = rng.gen();
+        self_copy.population = with_rng(|rng| rng.gen());
         self_copy
     }
 }
 
+impl CellFields for CellState {
+    fn new_at(id: CellIndex, position: Point<f64>) -> CellState {
+        CellState {
+            id,
+            position,
+            ..CellState::default()
+        }
+    }
+}
+
+/// Convert an int or float field value to a u32
+///
+/// Floats are rounded and values are clamped to the u32 range.
+fn u32_from_field(name: &str, value: FieldValue) -> Result<u32, FieldError> {
+    match value {
+        FieldValue::Int(v) => Ok(v.clamp(0, u32::MAX as i64) as u32),
+        FieldValue::Float(v) => Ok(v.round() as u32),
+        _ => Err(FieldError::InvalidType {
+            name: name.to_owned(),
+            expected: "int",
+            found: value.type_name(),
+        }),
+    }
+}
+
+impl Introspect for CellState {
+    fn describe_fields(&self) -> Vec<FieldDescriptor> {
+        vec![FieldDescriptor::new("population", FieldKind::Int)
+            .with_unit("people")
+            .with_range(0.0, u32::MAX as f64)]
+    }
+
+    fn field_value(&self, name: &str) -> Option<FieldValue> {
+        match name {
+            "population" => Some(FieldValue::Int(self.population as i64)),
+            _ => None,
+        }
+    }
+
+    fn set_field_value(&mut self, name: &str, value: FieldValue) -> Result<(), FieldError> {
+        match name {
+            "population" => self.population = u32_from_field(name, value)?,
+            _ => return Err(FieldError::Unknown(name.to_owned())),
+        }
+        Ok(())
+    }
+}
+
 // Global State
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GlobalState {
     pub iterations: u32,
     pub population: u32,
@@ -76,6 +132,32 @@ impl GlobalState {
 
 impl GlobalStateBase for GlobalState {}
 
+impl Introspect for GlobalState {
+    fn describe_fields(&self) -> Vec<FieldDescriptor> {
+        vec![
+            FieldDescriptor::new("iterations", FieldKind::Int),
+            FieldDescriptor::new("population", FieldKind::Int).with_unit("people"),
+        ]
+    }
+
+    fn field_value(&self, name: &str) -> Option<FieldValue> {
+        match name {
+            "iterations" => Some(FieldValue::Int(self.iterations as i64)),
+            "population" => Some(FieldValue::Int(self.population as i64)),
+            _ => None,
+        }
+    }
+
+    fn set_field_value(&mut self, name: &str, value: FieldValue) -> Result<(), FieldError> {
+        match name {
+            "iterations" => self.iterations = u32_from_field(name, value)?,
+            "population" => self.population = u32_from_field(name, value)?,
+            _ => return Err(FieldError::Unknown(name.to_owned())),
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,4 +176,60 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn should_set_numeric_fields_by_name() {
+        let mut cell = CellState::new(0, point!(x: 0.0, y: 0.0), 10);
+        cell.set_numeric_field_value("population", 12.6).unwrap();
+        assert_eq!(cell.population, 13);
+        cell.set_numeric_field_value("population", -4.0).unwrap();
+        assert_eq!(cell.population, 0);
+        assert_eq!(
+            cell.set_numeric_field_value("x", 3.5),
+            Err(FieldError::Unknown("x".to_owned()))
+        );
+        let mut global = GlobalState::new(4);
+        global.set_numeric_field_value("iterations", 3.0).unwrap();
+        assert_eq!(global.iterations, 3);
+    }
+
+    #[test]
+    fn should_set_fields_by_value() {
+        let mut cell = CellState::new_at(CellIndex(2), point!(x: 1.0, y: 2.0));
+        cell.set_field_value("population", FieldValue::Float(7.4))
+            .unwrap();
+        assert_eq!(
+            cell.field_values(),
+            vec![("population".to_owned(), FieldValue::Int(7))]
+        );
+        assert_eq!(
+            cell.set_field_value("population", FieldValue::Text("many".to_owned())),
+            Err(FieldError::InvalidType {
+                name: "population".to_owned(),
+                expected: "int",
+                found: "text",
+            })
+        );
+        assert_eq!(
+            cell.set_field_value("jobs", FieldValue::Int(1)),
+            Err(FieldError::Unknown("jobs".to_owned()))
+        );
+    }
+
+    #[test]
+    fn should_describe_cell_and_global_fields() {
+        let cell = CellState::new(0, point!(x: 0.0, y: 0.0), 10);
+        let fields = cell.describe_fields();
+        assert_eq!(fields[0].label, "Population");
+        assert_eq!(fields[0].unit, Some("people".to_owned()));
+        assert_eq!(cell.numeric_field_value("population"), Some(10.0));
+        let global = GlobalState::new(4);
+        assert_eq!(
+            global.field_values(),
+            vec![
+                ("iterations".to_owned(), FieldValue::Int(1)),
+                ("population".to_owned(), FieldValue::Int(4)),
+            ]
+        );
+    }
 }
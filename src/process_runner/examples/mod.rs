@@ -1,2 +1,4 @@
+pub mod example_dynamic;
+pub mod example_gui;
 pub mod example_processes;
 pub mod example_state;
@@ -0,0 +1,335 @@
+/// Fields Module
+///
+/// Access to cell fields by name so that generic code such as file loaders and
+/// exporters can read and write cells without knowing their type.
+///
+/// `Introspect` describes the fields of a cell or global state with their
+/// types, units and value ranges so exporters, recorders and GUI layer lists
+/// can be built for any model.
+///
+/// `FieldPrimitive` converts the rust types of fields to and from field values
+/// so the state derive macros can implement `Introspect`.
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use geo::Point;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FieldValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+}
+
+impl FieldValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FieldValue::Int(_) => "int",
+            FieldValue::Float(_) => "float",
+            FieldValue::Bool(_) => "bool",
+            FieldValue::Text(_) => "text",
+        }
+    }
+
+    /// The value as a number; text is not numeric
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            FieldValue::Int(v) => Some(v as f64),
+            FieldValue::Float(v) => Some(v),
+            FieldValue::Bool(v) => Some(v as u8 as f64),
+            FieldValue::Text(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Int(v) => write!(f, "{}", v),
+            FieldValue::Float(v) => write!(f, "{}", v),
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::Text(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldError {
+    Unknown(String),
+    InvalidType {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The value has the right type but is not allowed, e.g. an unknown category
+    InvalidValue {
+        name: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Unknown(name) => write!(f, "Unknown field: {}", name),
+            FieldError::InvalidType {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Field {} expects a {} value but got {}",
+                name, expected, found
+            ),
+            FieldError::InvalidValue { name, value } => {
+                write!(f, "Field {} cannot be set to {}", name, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// The type of a field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FieldKind {
+    Int,
+    Float,
+    Bool,
+    Text,
+    /// One of a fixed list of named categories
+    Category(Vec<String>),
+}
+
+impl FieldKind {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FieldKind::Int => "int",
+            FieldKind::Float => "float",
+            FieldKind::Bool => "bool",
+            FieldKind::Text => "text",
+            FieldKind::Category(_) => "category",
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, FieldKind::Int | FieldKind::Float | FieldKind::Bool)
+    }
+}
+
+/// The description of a field of a cell or global state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub label: String,
+    pub kind: FieldKind,
+    pub unit: Option<String>,
+    /// The minimum and maximum values a numeric field is expected to take
+    pub range: Option<(f64, f64)>,
+}
+
+impl FieldDescriptor {
+    /// The label is the name with the first letter capitalised
+    pub fn new(name: &str, kind: FieldKind) -> FieldDescriptor {
+        let mut chars = name.chars();
+        let label = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+        FieldDescriptor {
+            name: name.to_owned(),
+            label: label.replace('_', " "),
+            kind,
+            unit: None,
+            range: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = label.to_owned();
+        self
+    }
+
+    pub fn with_unit(mut self, unit: &str) -> Self {
+        self.unit = Some(unit.to_owned());
+        self
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+}
+
+/// A state that can describe its fields and read and write them by name
+///
+/// Implemented by cell and global states. The descriptions are the same for
+/// every cell of a model unless its fields are chosen at runtime.
+/// The id and position of a cell are not fields.
+pub trait Introspect {
+    /// The fields in a stable order
+    fn describe_fields(&self) -> Vec<FieldDescriptor>;
+
+    fn field_value(&self, name: &str) -> Option<FieldValue>;
+
+    /// Set a field by name, checking the value against the field type
+    fn set_field_value(&mut self, name: &str, value: FieldValue) -> Result<(), FieldError>;
+
+    /// The value of the field as a number, if it exists and is numeric
+    fn numeric_field_value(&self, name: &str) -> Option<f64> {
+        self.field_value(name).and_then(|v| v.as_f64())
+    }
+
+    /// Set a numeric field from a number converted to the field type
+    ///
+    /// Ints are rounded and bools are true for any value other than 0.
+    fn set_numeric_field_value(&mut self, name: &str, value: f64) -> Result<(), FieldError> {
+        let field = self
+            .describe_fields()
+            .into_iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| FieldError::Unknown(name.to_owned()))?;
+        let value = match field.kind {
+            FieldKind::Int => FieldValue::Int(value.round() as i64),
+            FieldKind::Float => FieldValue::Float(value),
+            FieldKind::Bool => FieldValue::Bool(value != 0.0),
+            _ => {
+                return Err(FieldError::InvalidType {
+                    name: name.to_owned(),
+                    expected: field.kind.type_name(),
+                    found: "float",
+                })
+            }
+        };
+        self.set_field_value(name, value)
+    }
+
+    /// All fields in description order
+    fn field_values(&self) -> Vec<(String, FieldValue)> {
+        self.describe_fields()
+            .into_iter()
+            .filter_map(|field| {
+                let value = self.field_value(&field.name)?;
+                Some((field.name, value))
+            })
+            .collect()
+    }
+}
+
+/// A cell that generic loaders can create and then fill by field name
+pub trait CellFields: CellStateBase + Introspect {
+    /// Create a cell with default fields
+    fn new_at(id: CellIndex, position: Point<f64>) -> Self;
+}
+
+/// A rust type that converts to and from a field value
+///
+/// Ints are clamped to the range of the type and floats are rounded when they
+/// are set on int fields, as for `set_numeric_field_value`.
+pub trait FieldPrimitive: Sized {
+    fn field_kind() -> FieldKind;
+
+    fn to_field_value(&self) -> FieldValue;
+
+    fn from_field_value(name: &str, value: FieldValue) -> Result<Self, FieldError>;
+}
+
+fn invalid_type(name: &str, expected: &'static str, value: &FieldValue) -> FieldError {
+    FieldError::InvalidType {
+        name: name.to_owned(),
+        expected,
+        found: value.type_name(),
+    }
+}
+
+macro_rules! int_field_primitive {
+    ($($t:ty),*) => {$(
+        impl FieldPrimitive for $t {
+            fn field_kind() -> FieldKind {
+                FieldKind::Int
+            }
+
+            #[allow(clippy::useless_conversion)]
+            fn to_field_value(&self) -> FieldValue {
+                FieldValue::Int(i64::try_from(*self).unwrap_or(i64::MAX))
+            }
+
+            #[allow(clippy::useless_conversion)]
+            fn from_field_value(name: &str, value: FieldValue) -> Result<Self, FieldError> {
+                match value {
+                    FieldValue::Int(v) => Ok(<$t>::try_from(v).unwrap_or(match v < 0 {
+                        true => <$t>::MIN,
+                        false => <$t>::MAX,
+                    })),
+                    FieldValue::Float(v) => Ok(v.round() as $t),
+                    _ => Err(invalid_type(name, "int", &value)),
+                }
+            }
+        }
+    )*};
+}
+
+int_field_primitive!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float_field_primitive {
+    ($($t:ty),*) => {$(
+        impl FieldPrimitive for $t {
+            fn field_kind() -> FieldKind {
+                FieldKind::Float
+            }
+
+            fn to_field_value(&self) -> FieldValue {
+                FieldValue::Float(*self as f64)
+            }
+
+            fn from_field_value(name: &str, value: FieldValue) -> Result<Self, FieldError> {
+                match value {
+                    FieldValue::Int(v) => Ok(v as $t),
+                    FieldValue::Float(v) => Ok(v as $t),
+                    _ => Err(invalid_type(name, "float", &value)),
+                }
+            }
+        }
+    )*};
+}
+
+float_field_primitive!(f32, f64);
+
+impl FieldPrimitive for bool {
+    fn field_kind() -> FieldKind {
+        FieldKind::Bool
+    }
+
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Bool(*self)
+    }
+
+    fn from_field_value(name: &str, value: FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::Bool(v) => Ok(v),
+            _ => Err(invalid_type(name, "bool", &value)),
+        }
+    }
+}
+
+impl FieldPrimitive for String {
+    fn field_kind() -> FieldKind {
+        FieldKind::Text
+    }
+
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Text(self.clone())
+    }
+
+    fn from_field_value(name: &str, value: FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::Text(v) => Ok(v),
+            _ => Err(invalid_type(name, "text", &value)),
+        }
+    }
+}
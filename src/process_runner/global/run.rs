@@ -1,6 +1,7 @@
 use super::state::GlobalStateBase;
 use crate::process_runner::cells::state::CellIndex;
 use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::parameters::Parameters;
 
 // A function that takes the cells and global state and returns an updated global state
 
@@ -11,7 +12,8 @@ pub struct GlobalUpdate<T: GlobalStateBase> {
     pub id: String,
     pub action: GlobalUpdateFn<T>,
 }
-type ProcessFuncT<C, G> = Box<dyn Fn(&Vec<&C>, &G) -> Vec<GlobalUpdate<G>>>;
+type ProcessFuncT<C, G> =
+    Box<dyn Fn(&Vec<&C>, &G, &Parameters) -> Vec<GlobalUpdate<G>> + Send + Sync>;
 
 impl<T: GlobalStateBase> std::fmt::Debug for GlobalUpdate<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -26,6 +28,12 @@ pub struct Process<C: CellStateBase, G: GlobalStateBase> {
     pub func: ProcessFuncT<C, G>,
 }
 
+impl<C: CellStateBase, G: GlobalStateBase> std::fmt::Debug for Process<C, G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Process").field("id", &self.id).finish()
+    }
+}
+
 #[allow(dead_code)]
 impl<C: CellStateBase, G: GlobalStateBase> Process<C, G> {
     pub fn new(id: u32, func: ProcessFuncT<C, G>) -> Process<C, G> {
@@ -51,11 +59,12 @@ pub fn run_processes<C: CellStateBase, G: GlobalStateBase>(
     _network: &Vec<Vec<CellIndex>>,
     processes: &Vec<&Process<C, G>>,
     global_state: &G,
+    parameters: &Parameters,
 ) -> Vec<GlobalUpdate<G>> {
     processes
         .iter()
         .fold(Vec::new(), move |mut updates, process| {
-            let mut new_updates = (process.func)(cells, global_state);
+            let mut new_updates = (process.func)(cells, global_state, parameters);
             updates.append(&mut new_updates);
             updates
         })
@@ -150,6 +159,7 @@ mod tests {
                 &network,
                 &processes.iter().collect(),
                 &global_state,
+                &Parameters::default(),
             )
         }
 
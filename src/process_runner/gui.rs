@@ -0,0 +1,302 @@
+/// GUI Module
+///
+/// The description of a model that can be driven by the web GUI.
+///
+/// A GUI model lists the map layers to show, the actions that can be applied
+/// to a selected cell and the controls for its parameters. It also creates the
+/// initial cells and the processes to run.
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::process_runner::cells::run::CellUpdate;
+use crate::process_runner::cells::run::Process as CellProcess;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::FieldDescriptor;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::parameters::ParameterSpec;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::recorder::numeric_field_reader;
+use crate::process_runner::recorder::CellFieldFn;
+use geo::Point;
+
+/// A function that creates the update an action makes to a cell
+pub type CellActionFn<C> = Box<dyn Fn(&C) -> CellUpdate<C> + Send + Sync>;
+
+/// A map layer showing a numeric cell field
+pub struct Layer<C: CellStateBase> {
+    pub uid: String,
+    pub label: String,
+    pub value: CellFieldFn<C>,
+}
+
+impl<C: CellStateBase> Layer<C> {
+    pub fn new(uid: &str, label: &str, value: CellFieldFn<C>) -> Layer<C> {
+        Layer {
+            uid: uid.to_owned(),
+            label: label.to_owned(),
+            value,
+        }
+    }
+
+    /// The layer value of each cell
+    pub fn values(&self, cells: &[C]) -> Vec<f64> {
+        cells.iter().map(|c| (self.value)(c)).collect()
+    }
+
+    pub fn total(&self, cells: &[C]) -> f64 {
+        cells.iter().map(|c| (self.value)(c)).sum()
+    }
+}
+
+impl<C: CellStateBase + Introspect> Layer<C> {
+    /// A layer showing a described cell field
+    pub fn from_field(field: &FieldDescriptor) -> Layer<C> {
+        Layer::new(&field.name, &field.label, numeric_field_reader(&field.name))
+    }
+}
+
+/// A layer for each numeric field described by the cell
+pub fn numeric_layers<C: CellStateBase + Introspect>(cell: &C) -> Vec<Layer<C>> {
+    cell.describe_fields()
+        .iter()
+        .filter(|field| field.kind.is_numeric())
+        .map(Layer::from_field)
+        .collect()
+}
+
+impl<C: CellStateBase> fmt::Debug for Layer<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Layer").field("uid", &self.uid).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionType {
+    Button,
+}
+
+impl fmt::Display for ActionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionType::Button => write!(f, "button"),
+        }
+    }
+}
+
+/// A named action that the user can apply to a cell
+pub struct CellAction<C: CellStateBase> {
+    pub uid: String,
+    pub label: String,
+    pub action_type: ActionType,
+    pub group: String,
+    pub action: CellActionFn<C>,
+}
+
+impl<C: CellStateBase> CellAction<C> {
+    pub fn new(uid: &str, label: &str, group: &str, action: CellActionFn<C>) -> CellAction<C> {
+        CellAction {
+            uid: uid.to_owned(),
+            label: label.to_owned(),
+            action_type: ActionType::Button,
+            group: group.to_owned(),
+            action,
+        }
+    }
+
+    /// Apply the action to the cell
+    pub fn apply(&self, cell: C) -> C {
+        let update = (self.action)(&cell);
+        (update.action)(cell)
+    }
+}
+
+impl<C: CellStateBase> fmt::Debug for CellAction<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CellAction")
+            .field("uid", &self.uid)
+            .field("group", &self.group)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlType {
+    Slider,
+    Number,
+    Checkbox,
+}
+
+impl fmt::Display for ControlType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlType::Slider => write!(f, "slider"),
+            ControlType::Number => write!(f, "number"),
+            ControlType::Checkbox => write!(f, "checkbox"),
+        }
+    }
+}
+
+/// A GUI control for a model parameter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Control {
+    pub uid: String,
+    pub label: String,
+    pub control_type: ControlType,
+    /// The type of value the control sets, "number" or "boolean"
+    pub value_type: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl From<&ParameterSpec> for Control {
+    /// Bool parameters use a checkbox and numbers with a range use a slider
+    fn from(spec: &ParameterSpec) -> Control {
+        let (control_type, value_type) = match spec.default {
+            ParameterValue::Bool(_) => (ControlType::Checkbox, "boolean"),
+            _ if spec.min.is_some() && spec.max.is_some() => (ControlType::Slider, "number"),
+            _ => (ControlType::Number, "number"),
+        };
+        Control {
+            uid: spec.name.clone(),
+            label: spec.label.clone(),
+            control_type,
+            value_type: value_type.to_owned(),
+            min: spec.min,
+            max: spec.max,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuiError {
+    UnknownAction(String),
+    UnknownLayer(String),
+}
+
+impl fmt::Display for GuiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuiError::UnknownAction(uid) => write!(f, "Unknown action: {}", uid),
+            GuiError::UnknownLayer(uid) => write!(f, "Unknown layer: {}", uid),
+        }
+    }
+}
+
+/// A model that can be driven by the web GUI
+pub trait GuiModel {
+    type Cell: CellStateBase;
+    type Global: GlobalStateBase;
+
+    fn layers(&self) -> Vec<Layer<Self::Cell>>;
+    fn cell_actions(&self) -> Vec<CellAction<Self::Cell>>;
+    fn parameters(&self) -> Parameters;
+    fn cell_processes(&self) -> Vec<CellProcess<Self::Cell, Self::Global>>;
+    fn global_processes(&self) -> Vec<GlobalProcess<Self::Cell, Self::Global>>;
+
+    /// Create a cell for a map feature
+    fn new_cell(&self, id: u32, position: Point<f64>) -> Self::Cell;
+
+    /// The parameter controls
+    fn controls(&self) -> Vec<Control> {
+        self.parameters()
+            .specs()
+            .iter()
+            .map(Control::from)
+            .collect()
+    }
+
+    /// Apply the action with the uid to a cell
+    fn apply_action(&self, cell: Self::Cell, uid: &str) -> Result<Self::Cell, GuiError> {
+        self.cell_actions()
+            .iter()
+            .find(|a| a.uid == uid)
+            .map(|a| a.apply(cell))
+            .ok_or_else(|| GuiError::UnknownAction(uid.to_owned()))
+    }
+
+    /// The total of a layer over all cells
+    fn layer_total(&self, cells: &[Self::Cell], uid: &str) -> Result<f64, GuiError> {
+        self.layers()
+            .iter()
+            .find(|l| l.uid == uid)
+            .map(|l| l.total(cells))
+            .ok_or_else(|| GuiError::UnknownLayer(uid.to_owned()))
+    }
+}
+
+/// Number of layer totals kept in the GUI global state
+pub const TOTALS_HISTORY_LENGTH: usize = 20;
+
+/// Add the current layer totals to the totals history
+///
+/// Only the last `history_length` totals of each layer are kept.
+pub fn update_layer_totals<M: GuiModel>(
+    model: &M,
+    totals: &mut HashMap<String, Vec<f64>>,
+    cells: &[M::Cell],
+    history_length: usize,
+) {
+    for layer in model.layers() {
+        let history = totals.entry(layer.uid.clone()).or_default();
+        history.push(layer.total(cells));
+        if history.len() > history_length {
+            history.drain(..history.len() - history_length);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_gui::ExampleGuiModel;
+    use crate::process_runner::examples::example_state::CellState;
+    use geo::point;
+
+    #[test]
+    fn should_build_controls_from_parameter_specs() {
+        let controls = ExampleGuiModel.controls();
+        assert_eq!(controls.len(), 1);
+        assert_eq!(controls[0].uid, "growth_rate");
+        assert_eq!(controls[0].control_type, ControlType::Slider);
+        assert_eq!(controls[0].value_type, "number");
+        assert_eq!(controls[0].max, Some(200.0));
+
+        let spec = ParameterSpec::new("migrate", "Migrate", ParameterValue::Bool(true));
+        assert_eq!(Control::from(&spec).control_type, ControlType::Checkbox);
+    }
+
+    #[test]
+    fn should_apply_cell_actions_by_uid() {
+        let cell = CellState::new(3, point!(x: 0.0, y: 0.0), 10);
+        let updated = ExampleGuiModel
+            .apply_action(cell.clone(), "setpop150")
+            .unwrap();
+        assert_eq!(updated.population, 150);
+        assert_eq!(updated.id, cell.id);
+        assert_eq!(
+            ExampleGuiModel.apply_action(cell, "missing").err(),
+            Some(GuiError::UnknownAction("missing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn should_total_layers_over_cells() {
+        let cells = vec![
+            ExampleGuiModel.new_cell(0, point!(x: 0.0, y: 0.0)),
+            CellState::new(1, point!(x: 1.0, y: 0.0), 25),
+        ];
+        assert_eq!(ExampleGuiModel.layer_total(&cells, "population"), Ok(25.0));
+        assert!(ExampleGuiModel.layer_total(&cells, "jobs").is_err());
+    }
+
+    #[test]
+    fn should_list_a_layer_for_each_numeric_field() {
+        let layers = numeric_layers(&CellState::default());
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].uid, "population");
+        assert_eq!(layers[0].label, "Population");
+    }
+}
@@ -1,10 +1,22 @@
 pub mod agents;
+pub mod cancel;
 pub mod cells;
+pub mod ensemble;
+pub mod events;
 pub mod examples;
+pub mod fields;
 pub mod global;
+pub mod gui;
 pub mod network;
+pub mod observer;
+pub mod parameters;
+pub mod random;
+pub mod recorder;
+pub mod registry;
 pub mod run;
+pub mod simulation;
 pub mod state;
+pub mod sweep;
 /* =============== TESTS =============== */
 
 #[cfg(test)]
@@ -16,11 +28,13 @@ mod tests {
     use cells::run::CellUpdate;
     use examples::example_processes::default_cell_processes;
     use examples::example_processes::default_global_processes;
+    use examples::example_processes::default_parameters;
     use examples::example_processes::CellProcessT;
     use examples::example_processes::GlobalProcessT;
     use examples::example_state::CellState;
     use examples::example_state::GlobalState;
     use global::run::GlobalUpdate;
+    use parameters::Parameters;
     use run::run_iteration;
     use state::IterationState;
 
@@ -70,8 +84,13 @@ mod tests {
         fn should_run_a_model_iteration_and_update_the_state() {
             let (initial_state, cell_processes, global_processes) = test_setup();
 
-            let final_state =
-                run_iteration(&cell_processes, &global_processes, initial_state, false);
+            let final_state = run_iteration(
+                &cell_processes,
+                &global_processes,
+                initial_state,
+                &default_parameters(),
+                false,
+            );
             assert_eq!(final_state.cells.len(), 3);
             assert_eq!(final_state.cells[0].population, 17); // initially 12
             assert_eq!(final_state.cells[1].population, 46); // initially 40
@@ -109,8 +128,13 @@ mod tests {
                 CellProcess::new(8, Box::new(conditional_pop_reset)),
             ];
             let global_processes = default_global_processes();
-            let final_state =
-                run_iteration(&cell_processes, &global_processes, initial_state, true);
+            let final_state = run_iteration(
+                &cell_processes,
+                &global_processes,
+                initial_state,
+                &Parameters::default(),
+                true,
+            );
             // When we run these
             assert_eq!(final_state.cells.len(), 3);
             assert_eq!(final_state.cells[0].population, 100); // initially 12
@@ -60,10 +60,10 @@ mod tests {
         let cell_b = CellState::new(1, point!(x:5.77, y:-0.02), 40);
         let cell_c = CellState::new(2, point!(x:5.94, y:0.42), 40);
         let are_neighbours = check_is_neighbour(&cell_a, &cell_b);
-        assert_eq!(are_neighbours, true);
+        assert!(are_neighbours);
         let are_neighbours = check_is_neighbour(&cell_a, &cell_c);
-        assert_eq!(are_neighbours, false);
+        assert!(!are_neighbours);
         let are_neighbours = check_is_neighbour(&cell_b, &cell_c);
-        assert_eq!(are_neighbours, true);
+        assert!(are_neighbours);
     }
 }
@@ -0,0 +1,11 @@
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::state::IterationState;
+
+/// Observer Base
+///
+/// Observers are notified of the model state at the end of every iteration.
+/// They must not modify the state; use processes for this.
+pub trait Observer<C: CellStateBase, G: GlobalStateBase> {
+    fn observe(&mut self, iteration: u32, state: &IterationState<C, G>);
+}
@@ -0,0 +1,387 @@
+/// Parameters Module
+///
+/// Model parameters are constant for the duration of a run and are passed to
+/// every process. Unlike the global state they are not modified by processes.
+/// Each parameter has a spec describing its name, label, range and default so
+/// that front-ends can build controls for it.
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParameterValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl ParameterValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ParameterValue::Int(_) => "int",
+            ParameterValue::Float(_) => "float",
+            ParameterValue::Bool(_) => "bool",
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            ParameterValue::Int(v) => v as f64,
+            ParameterValue::Float(v) => v,
+            ParameterValue::Bool(v) => v as u8 as f64,
+        }
+    }
+
+    pub fn as_i64(&self) -> i64 {
+        match *self {
+            ParameterValue::Int(v) => v,
+            ParameterValue::Float(v) => v as i64,
+            ParameterValue::Bool(v) => v as i64,
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match *self {
+            ParameterValue::Int(v) => v != 0,
+            ParameterValue::Float(v) => v != 0.0,
+            ParameterValue::Bool(v) => v,
+        }
+    }
+
+    /// Read a JSON number or bool
+    ///
+    /// Whole numbers are ints; `set` converts them for float parameters.
+    pub fn from_json(value: &JsonValue) -> Option<ParameterValue> {
+        match value {
+            JsonValue::Bool(v) => Some(ParameterValue::Bool(*v)),
+            JsonValue::Number(v) => match v.as_i64() {
+                Some(v) => Some(ParameterValue::Int(v)),
+                None => v.as_f64().map(ParameterValue::Float),
+            },
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        match *self {
+            ParameterValue::Int(v) => JsonValue::from(v),
+            ParameterValue::Float(v) => JsonValue::from(v),
+            ParameterValue::Bool(v) => JsonValue::from(v),
+        }
+    }
+
+    /// Convert the value to the same type as other
+    ///
+    /// Int values can be used for float parameters but not the reverse
+    fn coerce_to(&self, other: &ParameterValue) -> Option<ParameterValue> {
+        match (self, other) {
+            (ParameterValue::Int(_), ParameterValue::Int(_))
+            | (ParameterValue::Float(_), ParameterValue::Float(_))
+            | (ParameterValue::Bool(_), ParameterValue::Bool(_)) => Some(*self),
+            (ParameterValue::Int(v), ParameterValue::Float(_)) => {
+                Some(ParameterValue::Float(*v as f64))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParameterValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParameterValue::Int(v) => write!(f, "{}", v),
+            ParameterValue::Float(v) => write!(f, "{}", v),
+            ParameterValue::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Parameter metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParameterSpec {
+    pub name: String,
+    pub label: String,
+    pub default: ParameterValue,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl ParameterSpec {
+    pub fn new(name: &str, label: &str, default: ParameterValue) -> ParameterSpec {
+        ParameterSpec {
+            name: name.to_owned(),
+            label: label.to_owned(),
+            default,
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> ParameterSpec {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterError {
+    Unknown(String),
+    InvalidType {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    OutOfRange {
+        name: String,
+        value: f64,
+    },
+    /// The float value is NaN or infinite
+    NotFinite(String),
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParameterError::Unknown(name) => write!(f, "Unknown parameter: {}", name),
+            ParameterError::InvalidType {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Parameter {} expects a {} value but got {}",
+                name, expected, found
+            ),
+            ParameterError::OutOfRange { name, value } => {
+                write!(f, "Parameter {} value {} is out of range", name, value)
+            }
+            ParameterError::NotFinite(name) => {
+                write!(f, "Parameter {} must be a finite number", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParameterError {}
+
+/// A set of typed parameter values
+///
+/// Parameters that have not been set take the default from their spec.
+/// Deserialized values are checked as if they were set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ParametersData")]
+pub struct Parameters {
+    specs: Vec<ParameterSpec>,
+    values: HashMap<String, ParameterValue>,
+}
+
+impl Parameters {
+    pub fn new(specs: Vec<ParameterSpec>) -> Parameters {
+        Parameters {
+            specs,
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn specs(&self) -> &Vec<ParameterSpec> {
+        &self.specs
+    }
+
+    pub fn spec(&self, name: &str) -> Option<&ParameterSpec> {
+        self.specs.iter().find(|s| s.name == name)
+    }
+
+    /// Get the value of a parameter or its default if unset
+    pub fn get(&self, name: &str) -> Option<ParameterValue> {
+        match self.values.get(name) {
+            Some(value) => Some(*value),
+            None => self.spec(name).map(|s| s.default),
+        }
+    }
+
+    fn get_known(&self, name: &str) -> Result<ParameterValue, ParameterError> {
+        self.get(name)
+            .ok_or_else(|| ParameterError::Unknown(name.to_owned()))
+    }
+
+    /// Get a parameter as a float
+    pub fn get_f64(&self, name: &str) -> Result<f64, ParameterError> {
+        self.get_known(name).map(|v| v.as_f64())
+    }
+
+    /// Get a parameter as an integer
+    pub fn get_i64(&self, name: &str) -> Result<i64, ParameterError> {
+        self.get_known(name).map(|v| v.as_i64())
+    }
+
+    /// Get a parameter as a bool
+    pub fn get_bool(&self, name: &str) -> Result<bool, ParameterError> {
+        self.get_known(name).map(|v| v.as_bool())
+    }
+
+    /// Set the value of a parameter
+    ///
+    /// The value must match the type of the default, be finite and be within
+    /// the spec range.
+    pub fn set(&mut self, name: &str, value: ParameterValue) -> Result<(), ParameterError> {
+        let spec = self
+            .spec(name)
+            .ok_or_else(|| ParameterError::Unknown(name.to_owned()))?;
+        let value = value
+            .coerce_to(&spec.default)
+            .ok_or_else(|| ParameterError::InvalidType {
+                name: name.to_owned(),
+                expected: spec.default.type_name(),
+                found: value.type_name(),
+            })?;
+        let v = value.as_f64();
+        if !v.is_finite() {
+            return Err(ParameterError::NotFinite(name.to_owned()));
+        }
+        if matches!(spec.min, Some(min) if v < min) || matches!(spec.max, Some(max) if v > max) {
+            return Err(ParameterError::OutOfRange {
+                name: name.to_owned(),
+                value: v,
+            });
+        }
+        self.values.insert(name.to_owned(), value);
+        Ok(())
+    }
+
+    /// Builder version of `set`
+    pub fn with(mut self, name: &str, value: ParameterValue) -> Result<Self, ParameterError> {
+        self.set(name, value)?;
+        Ok(self)
+    }
+
+    /// All parameter values including defaults in spec order
+    pub fn values(&self) -> Vec<(String, ParameterValue)> {
+        self.specs
+            .iter()
+            .map(|s| (s.name.to_owned(), self.get(&s.name).unwrap()))
+            .collect()
+    }
+}
+
+/// The serialized form of a parameter set
+#[derive(Deserialize)]
+struct ParametersData {
+    specs: Vec<ParameterSpec>,
+    values: HashMap<String, ParameterValue>,
+}
+
+impl TryFrom<ParametersData> for Parameters {
+    type Error = ParameterError;
+
+    /// Check the defaults are finite and set each value so it is validated
+    fn try_from(data: ParametersData) -> Result<Parameters, ParameterError> {
+        if let Some(spec) = data.specs.iter().find(|s| !s.default.as_f64().is_finite()) {
+            return Err(ParameterError::NotFinite(spec.name.to_owned()));
+        }
+        let mut parameters = Parameters::new(data.specs);
+        for (name, value) in data.values.into_iter() {
+            parameters.set(&name, value)?;
+        }
+        Ok(parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demo_parameters() -> Parameters {
+        Parameters::new(vec![
+            ParameterSpec::new("growth_rate", "Growth Rate", ParameterValue::Float(10.0))
+                .with_range(-200.0, 200.0),
+            ParameterSpec::new("max_population", "Max Population", ParameterValue::Int(99)),
+            ParameterSpec::new("migration", "Migration", ParameterValue::Bool(true)),
+        ])
+    }
+
+    #[test]
+    fn should_return_defaults_for_unset_parameters() {
+        let parameters = demo_parameters();
+        assert_eq!(parameters.get_f64("growth_rate"), Ok(10.0));
+        assert_eq!(parameters.get_i64("max_population"), Ok(99));
+        assert_eq!(parameters.get_bool("migration"), Ok(true));
+        assert_eq!(parameters.get("missing"), None);
+        assert_eq!(
+            parameters.get_f64("missing"),
+            Err(ParameterError::Unknown("missing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn should_set_parameter_values() {
+        let parameters = demo_parameters()
+            .with("growth_rate", ParameterValue::Int(-5))
+            .unwrap();
+        assert_eq!(
+            parameters.get("growth_rate"),
+            Some(ParameterValue::Float(-5.0))
+        );
+        assert_eq!(
+            parameters.values(),
+            vec![
+                ("growth_rate".to_owned(), ParameterValue::Float(-5.0)),
+                ("max_population".to_owned(), ParameterValue::Int(99)),
+                ("migration".to_owned(), ParameterValue::Bool(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_validate_parameter_values() {
+        let mut parameters = demo_parameters();
+        assert_eq!(
+            parameters.set("missing", ParameterValue::Int(1)),
+            Err(ParameterError::Unknown("missing".to_owned()))
+        );
+        assert_eq!(
+            parameters.set("growth_rate", ParameterValue::Float(201.0)),
+            Err(ParameterError::OutOfRange {
+                name: "growth_rate".to_owned(),
+                value: 201.0
+            })
+        );
+        assert_eq!(
+            parameters.set("growth_rate", ParameterValue::Float(f64::NAN)),
+            Err(ParameterError::NotFinite("growth_rate".to_owned()))
+        );
+        assert_eq!(
+            parameters.set("max_population", ParameterValue::Float(1.5)),
+            Err(ParameterError::InvalidType {
+                name: "max_population".to_owned(),
+                expected: "int",
+                found: "float"
+            })
+        );
+    }
+
+    #[test]
+    fn should_validate_deserialized_values() {
+        let parameters = demo_parameters()
+            .with("growth_rate", ParameterValue::Float(-5.0))
+            .unwrap();
+        let encoded = bincode::serialize(&parameters).unwrap();
+        let decoded: Parameters = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, parameters);
+
+        let mut invalid = parameters.clone();
+        invalid
+            .values
+            .insert("growth_rate".to_owned(), ParameterValue::Float(f64::NAN));
+        let encoded = bincode::serialize(&invalid).unwrap();
+        assert!(bincode::deserialize::<Parameters>(&encoded).is_err());
+        invalid
+            .values
+            .insert("growth_rate".to_owned(), ParameterValue::Float(500.0));
+        let encoded = bincode::serialize(&invalid).unwrap();
+        assert!(bincode::deserialize::<Parameters>(&encoded).is_err());
+    }
+}
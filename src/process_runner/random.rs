@@ -0,0 +1,98 @@
+/// Random Module
+///
+/// Processes and cell randomization should use this module rather than
+/// `rand::thread_rng` so that runs can be reproduced from a seed.
+/// Each thread has its own generator; seeding one thread does not affect others.
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Seed the random generator for the current thread
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Run a function with the random generator for the current thread
+pub fn with_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// Replace the random generator for the current thread returning the previous one
+///
+/// Used to keep a separate random stream for work that moves between threads.
+pub fn replace_rng(rng: StdRng) -> StdRng {
+    RNG.with(|current| std::mem::replace(&mut *current.borrow_mut(), rng))
+}
+
+/// Run a function with the random generator for the current thread seeded
+///
+/// The previous generator is restored afterwards so the seed only applies to the call.
+pub fn with_seed<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    let previous = replace_rng(StdRng::seed_from_u64(seed));
+    let result = f();
+    replace_rng(previous);
+    result
+}
+
+/// Derive an independent seed for a numbered stream from a base seed
+///
+/// Uses the SplitMix64 finalizer so that neighbouring streams are uncorrelated.
+pub fn derive_seed(base_seed: u64, stream: u64) -> u64 {
+    let mut z = base_seed.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn should_repeat_sequence_after_seeding() {
+        seed(42);
+        let a: Vec<u32> = (0..5).map(|_| with_rng(|rng| rng.gen())).collect();
+        seed(42);
+        let b: Vec<u32> = (0..5).map(|_| with_rng(|rng| rng.gen())).collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn should_continue_replaced_sequence() {
+        seed(7);
+        let expected: Vec<u32> = (0..4).map(|_| with_rng(|rng| rng.gen())).collect();
+        seed(7);
+        let first: u32 = with_rng(|rng| rng.gen());
+        let stream = replace_rng(StdRng::seed_from_u64(0));
+        let _other: u32 = with_rng(|rng| rng.gen());
+        replace_rng(stream);
+        let rest: Vec<u32> = (0..3).map(|_| with_rng(|rng| rng.gen())).collect();
+        assert_eq!(expected[0], first);
+        assert_eq!(expected[1..].to_vec(), rest);
+    }
+
+    #[test]
+    fn should_restore_generator_after_seeded_call() {
+        seed(11);
+        let expected: Vec<u32> = (0..2).map(|_| with_rng(|rng| rng.gen())).collect();
+        seed(11);
+        let first: u32 = with_rng(|rng| rng.gen());
+        let a: u32 = with_seed(5, || with_rng(|rng| rng.gen()));
+        let b: u32 = with_seed(5, || with_rng(|rng| rng.gen()));
+        let second: u32 = with_rng(|rng| rng.gen());
+        assert_eq!(a, b);
+        assert_eq!(expected, vec![first, second]);
+    }
+
+    #[test]
+    fn should_derive_different_seeds_per_stream() {
+        assert_ne!(derive_seed(1, 0), derive_seed(1, 1));
+        assert_ne!(derive_seed(1, 0), derive_seed(2, 0));
+        assert_eq!(derive_seed(1, 5), derive_seed(1, 5));
+    }
+}
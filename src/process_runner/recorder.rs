@@ -0,0 +1,497 @@
+/// Recorder Module
+///
+/// A recorder is an observer that keeps a time series of the global state and
+/// summary statistics of selected cell fields.
+/// History can be limited to the most recent iterations.
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::state::IterationState;
+
+/// A function that reads a numeric value from a cell
+pub type CellFieldFn<C> = Box<dyn Fn(&C) -> f64 + Send + Sync>;
+
+/// A function that reads a numeric value from the global state
+pub type GlobalFieldFn<G> = Box<dyn Fn(&G) -> f64 + Send + Sync>;
+
+/// A reader for a numeric field of a cell or global state that describes its fields
+///
+/// Missing or non numeric values are read as NaN.
+pub fn numeric_field_reader<T: Introspect>(name: &str) -> Box<dyn Fn(&T) -> f64 + Send + Sync> {
+    let name = name.to_owned();
+    Box::new(move |state: &T| state.numeric_field_value(&name).unwrap_or(f64::NAN))
+}
+
+/// A summary statistic calculated over all cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Statistic {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    /// Percentile in the range 0-100
+    Percentile(f64),
+}
+
+impl Statistic {
+    /// Calculate the statistic for the values
+    ///
+    /// Returns NaN if there are no values
+    pub fn calculate(&self, values: &[f64]) -> f64 {
+        if values.is_empty() {
+            return f64::NAN;
+        }
+        match self {
+            Statistic::Sum => values.iter().sum(),
+            Statistic::Mean => values.iter().sum::<f64>() / values.len() as f64,
+            Statistic::Min => values.iter().cloned().fold(f64::INFINITY, f64::min),
+            Statistic::Max => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            Statistic::Percentile(p) => {
+                let mut sorted = values.to_vec();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                percentile_of_sorted(&sorted, *p)
+            }
+        }
+    }
+}
+
+/// Parse a statistic from its label. e.g. "sum", "mean" or "p90"
+impl std::str::FromStr for Statistic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Statistic::Sum),
+            "mean" => Ok(Statistic::Mean),
+            "min" => Ok(Statistic::Min),
+            "max" => Ok(Statistic::Max),
+            _ => match s.strip_prefix('p').map(|p| p.parse::<f64>()) {
+                Some(Ok(p)) if (0.0..=100.0).contains(&p) => Ok(Statistic::Percentile(p)),
+                _ => Err(format!("Invalid statistic: {}", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Statistic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statistic::Sum => write!(f, "sum"),
+            Statistic::Mean => write!(f, "mean"),
+            Statistic::Min => write!(f, "min"),
+            Statistic::Max => write!(f, "max"),
+            Statistic::Percentile(p) => write!(f, "p{}", p),
+        }
+    }
+}
+
+/// Linear interpolation between the closest ranks of sorted values
+///
+/// The percentile must be in the range 0-100.
+pub fn percentile_of_sorted(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = (percentile / 100.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecorderError {
+    /// The percentile is not in the range 0-100
+    InvalidPercentile(f64),
+}
+
+impl fmt::Display for RecorderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecorderError::InvalidPercentile(p) => {
+                write!(f, "Invalid percentile: {} is not in the range 0-100", p)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecorderError {}
+
+/// A single recorded iteration
+#[derive(Debug, Clone)]
+pub struct Record<G: GlobalStateBase> {
+    pub iteration: u32,
+    pub global_state: G,
+    /// Cell statistics ordered by field then statistic
+    pub cell_statistics: Vec<f64>,
+}
+
+pub struct Recorder<C: CellStateBase, G: GlobalStateBase> {
+    cell_fields: Vec<(String, CellFieldFn<C>)>,
+    global_fields: Vec<(String, GlobalFieldFn<G>)>,
+    statistics: Vec<Statistic>,
+    history_length: Option<usize>,
+    records: VecDeque<Record<G>>,
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> fmt::Debug for Recorder<C, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("columns", &self.columns())
+            .field("records", &self.records.len())
+            .finish()
+    }
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> Recorder<C, G> {
+    /// Create a recorder that calculates each statistic for every cell field
+    ///
+    /// If history_length is set only the most recent records are kept.
+    /// Percentiles must be in the range 0-100.
+    pub fn new(
+        statistics: Vec<Statistic>,
+        history_length: impl Into<Option<usize>>,
+    ) -> Result<Recorder<C, G>, RecorderError> {
+        for statistic in statistics.iter() {
+            if let Statistic::Percentile(p) = statistic {
+                if !(0.0..=100.0).contains(p) {
+                    return Err(RecorderError::InvalidPercentile(*p));
+                }
+            }
+        }
+        Ok(Recorder {
+            cell_fields: Vec::new(),
+            global_fields: Vec::new(),
+            statistics,
+            history_length: history_length.into(),
+            records: VecDeque::new(),
+        })
+    }
+
+    /// Add a cell field to calculate statistics for
+    pub fn with_cell_field(mut self, name: &str, field: CellFieldFn<C>) -> Self {
+        self.cell_fields.push((name.to_owned(), field));
+        self
+    }
+
+    /// Add a global state field to include in exported tables
+    pub fn with_global_field(mut self, name: &str, field: GlobalFieldFn<G>) -> Self {
+        self.global_fields.push((name.to_owned(), field));
+        self
+    }
+
+    /// Add every numeric field described by the cell and the global state
+    pub fn with_described_fields(mut self, cell: &C, global_state: &G) -> Self
+    where
+        C: Introspect,
+        G: Introspect,
+    {
+        for field in cell.describe_fields() {
+            if field.kind.is_numeric() {
+                self = self.with_cell_field(&field.name, numeric_field_reader(&field.name));
+            }
+        }
+        for field in global_state.describe_fields() {
+            if field.kind.is_numeric() {
+                self = self.with_global_field(&field.name, numeric_field_reader(&field.name));
+            }
+        }
+        self
+    }
+
+    /// Record the state of a single iteration
+    pub fn record(&mut self, iteration: u32, state: &IterationState<C, G>) {
+        let cell_statistics = self
+            .cell_fields
+            .iter()
+            .flat_map(|(_, field)| {
+                let values = state.cells.iter().map(field).collect::<Vec<f64>>();
+                self.statistics
+                    .iter()
+                    .map(|s| s.calculate(&values))
+                    .collect::<Vec<f64>>()
+            })
+            .collect();
+        self.records.push_back(Record {
+            iteration,
+            global_state: state.global_state.clone(),
+            cell_statistics,
+        });
+        if let Some(max_length) = self.history_length {
+            while self.records.len() > max_length {
+                self.records.pop_front();
+            }
+        }
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &Record<G>> {
+        self.records.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn iterations(&self) -> Vec<u32> {
+        self.records.iter().map(|r| r.iteration).collect()
+    }
+
+    pub fn global_states(&self) -> Vec<&G> {
+        self.records.iter().map(|r| &r.global_state).collect()
+    }
+
+    /// Get the time series of a statistic for a cell field
+    pub fn series(&self, field: &str, statistic: Statistic) -> Option<Vec<f64>> {
+        let field_index = self.cell_fields.iter().position(|(n, _)| n == field)?;
+        let stat_index = self.statistics.iter().position(|s| *s == statistic)?;
+        let column = field_index * self.statistics.len() + stat_index;
+        Some(
+            self.records
+                .iter()
+                .map(|r| r.cell_statistics[column])
+                .collect(),
+        )
+    }
+
+    /// Get the time series of a global state field
+    pub fn global_series(&self, field: &str) -> Option<Vec<f64>> {
+        let (_, getter) = self.global_fields.iter().find(|(n, _)| n == field)?;
+        Some(
+            self.records
+                .iter()
+                .map(|r| getter(&r.global_state))
+                .collect(),
+        )
+    }
+
+    /// The column names of the exported table
+    ///
+    /// Cell statistic columns are named "{field}_{statistic}"
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns = vec!["iteration".to_owned()];
+        columns.extend(self.global_fields.iter().map(|(n, _)| n.to_owned()));
+        for (name, _) in self.cell_fields.iter() {
+            columns.extend(self.statistics.iter().map(|s| format!("{}_{}", name, s)));
+        }
+        columns
+    }
+
+    /// Get the recorded history as a table with one row per iteration
+    pub fn to_table(&self) -> (Vec<String>, Vec<Vec<f64>>) {
+        let rows = self
+            .records
+            .iter()
+            .map(|r| {
+                let mut row = vec![r.iteration as f64];
+                row.extend(self.global_fields.iter().map(|(_, f)| f(&r.global_state)));
+                row.extend(r.cell_statistics.iter().cloned());
+                row
+            })
+            .collect();
+        (self.columns(), rows)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let (columns, rows) = self.to_table();
+        let mut out = columns.join(",");
+        out.push('\n');
+        for row in rows.iter() {
+            let line = row.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            out.push_str(&line.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Export the table as JSON in the form {"columns": [...], "rows": [[...], ...]}
+    ///
+    /// Non finite values are written as null
+    pub fn to_json(&self) -> String {
+        let (columns, rows) = self.to_table();
+        serde_json::json!({ "columns": columns, "rows": rows }).to_string()
+    }
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> Observer<C, G> for Recorder<C, G> {
+    fn observe(&mut self, iteration: u32, state: &IterationState<C, G>) {
+        self.record(iteration, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_processes::*;
+    use crate::process_runner::examples::example_state::*;
+    use crate::process_runner::run::run_iterations;
+    use geo::point;
+
+    fn demo_state() -> IterationState<CellState, GlobalState> {
+        IterationState {
+            global_state: GlobalState::default(),
+            cells: vec![
+                CellState::new(0, point!(x: 0.0, y: 0.0), 10),
+                CellState::new(1, point!(x: 0.0, y: 0.0), 20),
+                CellState::new(2, point!(x: 0.0, y: 0.0), 30),
+                CellState::new(3, point!(x: 0.0, y: 0.0), 40),
+            ],
+            network: vec![vec![]],
+        }
+    }
+
+    fn demo_recorder(history_length: Option<usize>) -> Recorder<CellState, GlobalState> {
+        Recorder::new(
+            vec![
+                Statistic::Sum,
+                Statistic::Mean,
+                Statistic::Min,
+                Statistic::Max,
+                Statistic::Percentile(50.0),
+            ],
+            history_length,
+        )
+        .unwrap()
+        .with_cell_field("population", Box::new(|c: &CellState| c.population as f64))
+        .with_global_field(
+            "iterations",
+            Box::new(|g: &GlobalState| g.iterations as f64),
+        )
+    }
+
+    mod test_statistic {
+        use super::*;
+
+        #[test]
+        fn should_calculate_statistics() {
+            let values = vec![4.0, 1.0, 3.0, 2.0];
+            assert_eq!(Statistic::Sum.calculate(&values), 10.0);
+            assert_eq!(Statistic::Mean.calculate(&values), 2.5);
+            assert_eq!(Statistic::Min.calculate(&values), 1.0);
+            assert_eq!(Statistic::Max.calculate(&values), 4.0);
+            assert_eq!(Statistic::Percentile(50.0).calculate(&values), 2.5);
+            assert_eq!(Statistic::Percentile(100.0).calculate(&values), 4.0);
+            assert!(Statistic::Mean.calculate(&[]).is_nan());
+        }
+
+        #[test]
+        fn should_parse_statistic_labels() {
+            assert_eq!("sum".parse::<Statistic>(), Ok(Statistic::Sum));
+            assert_eq!("p90".parse::<Statistic>(), Ok(Statistic::Percentile(90.0)));
+            assert!("p101".parse::<Statistic>().is_err());
+            assert!("median".parse::<Statistic>().is_err());
+            assert_eq!(Statistic::Percentile(12.5).to_string(), "p12.5");
+        }
+
+        #[test]
+        fn should_reject_invalid_percentiles() {
+            for p in [-1.0, 100.5, f64::NAN].iter() {
+                assert!(matches!(
+                    Recorder::<CellState, GlobalState>::new(vec![Statistic::Percentile(*p)], None),
+                    Err(RecorderError::InvalidPercentile(_))
+                ));
+            }
+            assert!(Recorder::<CellState, GlobalState>::new(
+                vec![Statistic::Percentile(0.0)],
+                None
+            )
+            .is_ok());
+        }
+    }
+
+    mod test_recorder {
+        use super::*;
+
+        #[test]
+        fn should_record_cell_statistics_and_global_state() {
+            let mut recorder = demo_recorder(None);
+            recorder.record(0, &demo_state());
+            assert_eq!(recorder.len(), 1);
+            assert_eq!(
+                recorder.series("population", Statistic::Sum),
+                Some(vec![100.0])
+            );
+            assert_eq!(
+                recorder.series("population", Statistic::Mean),
+                Some(vec![25.0])
+            );
+            assert_eq!(
+                recorder.series("population", Statistic::Max),
+                Some(vec![40.0])
+            );
+            assert_eq!(recorder.series("unknown", Statistic::Max), None);
+            assert_eq!(recorder.global_series("iterations"), Some(vec![0.0]));
+        }
+
+        #[test]
+        fn should_limit_history_length() {
+            let mut recorder = demo_recorder(Some(2));
+            let state = demo_state();
+            run_iterations(
+                &default_cell_processes(),
+                &default_global_processes(),
+                state,
+                &default_parameters(),
+                5,
+                true,
+                &mut [&mut recorder],
+            );
+            assert_eq!(recorder.iterations(), vec![4, 5]);
+            assert_eq!(recorder.global_series("iterations"), Some(vec![4.0, 5.0]));
+        }
+
+        #[test]
+        fn should_export_csv_and_json() {
+            let mut recorder = Recorder::new(vec![Statistic::Sum, Statistic::Mean], None)
+                .unwrap()
+                .with_cell_field("population", Box::new(|c: &CellState| c.population as f64))
+                .with_global_field(
+                    "iterations",
+                    Box::new(|g: &GlobalState| g.iterations as f64),
+                );
+            recorder.record(0, &demo_state());
+            let mut empty = demo_state();
+            empty.cells.clear();
+            recorder.record(1, &empty);
+            assert_eq!(
+                recorder.to_csv(),
+                "iteration,iterations,population_sum,population_mean\n0,0,100,25\n1,0,NaN,NaN\n"
+            );
+            let json: serde_json::Value = serde_json::from_str(&recorder.to_json()).unwrap();
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "columns": ["iteration", "iterations", "population_sum", "population_mean"],
+                    "rows": [[0.0, 0.0, 100.0, 25.0], [1.0, 0.0, null, null]]
+                })
+            );
+        }
+
+        #[test]
+        fn should_record_described_fields() {
+            let state = demo_state();
+            let mut recorder = Recorder::new(vec![Statistic::Sum], None)
+                .unwrap()
+                .with_described_fields(&state.cells[0], &state.global_state);
+            recorder.record(0, &state);
+            assert_eq!(
+                recorder.columns(),
+                vec!["iteration", "iterations", "population", "population_sum"]
+            );
+            assert_eq!(
+                recorder.series("population", Statistic::Sum),
+                Some(vec![100.0])
+            );
+        }
+    }
+}
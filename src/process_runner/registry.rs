@@ -0,0 +1,327 @@
+/// Registry Module
+///
+/// A registry of named process factories so that front-ends can list the
+/// available processes and build a run from an ordered selection of them.
+///
+/// Each process is registered with a unique id and name, a description and
+/// the specs of the parameters it reads.
+use std::fmt;
+
+use crate::process_runner::cells::run::Process as CellProcess;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::parameters::ParameterSpec;
+use crate::process_runner::parameters::Parameters;
+
+/// A function that creates a cell process with the given id
+pub type CellProcessFactory<C, G> = Box<dyn Fn(u32) -> CellProcess<C, G> + Send + Sync>;
+
+/// A function that creates a global process with the given id
+pub type GlobalProcessFactory<C, G> = Box<dyn Fn(u32) -> GlobalProcess<C, G> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessKind {
+    Cell,
+    Global,
+}
+
+impl fmt::Display for ProcessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessKind::Cell => write!(f, "cell"),
+            ProcessKind::Global => write!(f, "global"),
+        }
+    }
+}
+
+/// Process details for listing
+///
+/// The kind is set when the process is registered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    pub id: u32,
+    pub name: String,
+    pub kind: ProcessKind,
+    pub description: String,
+    pub parameters: Vec<ParameterSpec>,
+}
+
+impl ProcessInfo {
+    pub fn new(id: u32, name: &str, description: &str) -> ProcessInfo {
+        ProcessInfo {
+            id,
+            name: name.to_owned(),
+            kind: ProcessKind::Cell,
+            description: description.to_owned(),
+            parameters: vec![],
+        }
+    }
+
+    pub fn with_parameter(mut self, spec: ParameterSpec) -> Self {
+        self.parameters.push(spec);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryError {
+    /// A process with this name is already registered
+    DuplicateName(String),
+    /// A process of the same kind with this id is already registered
+    DuplicateId(u32),
+    /// No process of the requested kind has this name
+    Unknown(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::DuplicateName(name) => {
+                write!(f, "Process already registered: {}", name)
+            }
+            RegistryError::DuplicateId(id) => write!(f, "Process id already registered: {}", id),
+            RegistryError::Unknown(name) => write!(f, "Unknown process: {}", name),
+        }
+    }
+}
+
+enum Factory<C: CellStateBase, G: GlobalStateBase> {
+    Cell(CellProcessFactory<C, G>),
+    Global(GlobalProcessFactory<C, G>),
+}
+
+pub struct ProcessRegistry<C: CellStateBase, G: GlobalStateBase> {
+    entries: Vec<(ProcessInfo, Factory<C, G>)>,
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> fmt::Debug for ProcessRegistry<C, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcessRegistry")
+            .field("processes", &self.processes())
+            .finish()
+    }
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> Default for ProcessRegistry<C, G> {
+    fn default() -> Self {
+        ProcessRegistry { entries: vec![] }
+    }
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> ProcessRegistry<C, G> {
+    pub fn new() -> ProcessRegistry<C, G> {
+        ProcessRegistry::default()
+    }
+
+    fn check_unique(&self, info: &ProcessInfo) -> Result<(), RegistryError> {
+        for (existing, _) in self.entries.iter() {
+            if existing.name == info.name {
+                return Err(RegistryError::DuplicateName(info.name.clone()));
+            }
+            if existing.kind == info.kind && existing.id == info.id {
+                return Err(RegistryError::DuplicateId(info.id));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn register_cell_process(
+        &mut self,
+        info: ProcessInfo,
+        factory: CellProcessFactory<C, G>,
+    ) -> Result<(), RegistryError> {
+        let info = ProcessInfo {
+            kind: ProcessKind::Cell,
+            ..info
+        };
+        self.check_unique(&info)?;
+        self.entries.push((info, Factory::Cell(factory)));
+        Ok(())
+    }
+
+    pub fn register_global_process(
+        &mut self,
+        info: ProcessInfo,
+        factory: GlobalProcessFactory<C, G>,
+    ) -> Result<(), RegistryError> {
+        let info = ProcessInfo {
+            kind: ProcessKind::Global,
+            ..info
+        };
+        self.check_unique(&info)?;
+        self.entries.push((info, Factory::Global(factory)));
+        Ok(())
+    }
+
+    /// Register a cell process
+    ///
+    /// Panics if the name or id is already registered.
+    pub fn with_cell_process(
+        mut self,
+        info: ProcessInfo,
+        factory: CellProcessFactory<C, G>,
+    ) -> Self {
+        if let Err(e) = self.register_cell_process(info, factory) {
+            panic!("{}", e);
+        }
+        self
+    }
+
+    /// Register a global process
+    ///
+    /// Panics if the name or id is already registered.
+    pub fn with_global_process(
+        mut self,
+        info: ProcessInfo,
+        factory: GlobalProcessFactory<C, G>,
+    ) -> Self {
+        if let Err(e) = self.register_global_process(info, factory) {
+            panic!("{}", e);
+        }
+        self
+    }
+
+    /// Info of all registered processes in registration order
+    pub fn processes(&self) -> Vec<&ProcessInfo> {
+        self.entries.iter().map(|(info, _)| info).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProcessInfo> {
+        self.entries
+            .iter()
+            .map(|(info, _)| info)
+            .find(|info| info.name == name)
+    }
+
+    /// The highest registered id of the kind
+    pub fn max_id(&self, kind: ProcessKind) -> Option<u32> {
+        self.entries
+            .iter()
+            .filter(|(info, _)| info.kind == kind)
+            .map(|(info, _)| info.id)
+            .max()
+    }
+
+    /// Create a cell process by name
+    pub fn cell_process(&self, name: &str) -> Result<CellProcess<C, G>, RegistryError> {
+        self.entries
+            .iter()
+            .find_map(|(info, factory)| match factory {
+                Factory::Cell(factory) if info.name == name => Some(factory(info.id)),
+                _ => None,
+            })
+            .ok_or_else(|| RegistryError::Unknown(name.to_owned()))
+    }
+
+    /// Create a global process by name
+    pub fn global_process(&self, name: &str) -> Result<GlobalProcess<C, G>, RegistryError> {
+        self.entries
+            .iter()
+            .find_map(|(info, factory)| match factory {
+                Factory::Global(factory) if info.name == name => Some(factory(info.id)),
+                _ => None,
+            })
+            .ok_or_else(|| RegistryError::Unknown(name.to_owned()))
+    }
+
+    /// Create cell processes in the order of the names
+    pub fn cell_processes(&self, names: &[&str]) -> Result<Vec<CellProcess<C, G>>, RegistryError> {
+        names.iter().map(|name| self.cell_process(name)).collect()
+    }
+
+    /// Create global processes in the order of the names
+    pub fn global_processes(
+        &self,
+        names: &[&str],
+    ) -> Result<Vec<GlobalProcess<C, G>>, RegistryError> {
+        names.iter().map(|name| self.global_process(name)).collect()
+    }
+
+    /// Parameters with the specs of every registered process
+    ///
+    /// Specs shared by several processes are only included once.
+    pub fn parameters(&self) -> Parameters {
+        let mut specs: Vec<ParameterSpec> = vec![];
+        for spec in self
+            .entries
+            .iter()
+            .flat_map(|(info, _)| info.parameters.iter())
+        {
+            if !specs.iter().any(|s| s.name == spec.name) {
+                specs.push(spec.clone());
+            }
+        }
+        Parameters::new(specs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_processes::*;
+    use crate::process_runner::examples::example_state::*;
+
+    fn demo_registry() -> ProcessRegistry<CellState, GlobalState> {
+        ProcessRegistry::new()
+            .with_cell_process(
+                ProcessInfo::new(0, "reset", "Set population to 100"),
+                Box::new(|id| CellProcess::new(id, Box::new(set_population_to_100))),
+            )
+            .with_cell_process(
+                ProcessInfo::new(1, "growth", "Grow population")
+                    .with_parameter(default_parameters().specs()[0].clone()),
+                Box::new(|id| CellProcess::new(id, Box::new(population_growth))),
+            )
+            .with_global_process(
+                ProcessInfo::new(0, "count", "Count iterations"),
+                Box::new(|id| GlobalProcess::new(id, Box::new(example_global_process_iter))),
+            )
+    }
+
+    #[test]
+    fn should_build_processes_in_the_requested_order() {
+        let registry = demo_registry();
+        let processes = registry.cell_processes(&["growth", "reset"]).unwrap();
+        assert_eq!(
+            processes.iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![1, 0]
+        );
+        assert_eq!(registry.global_processes(&["count"]).unwrap().len(), 1);
+        assert_eq!(registry.max_id(ProcessKind::Cell), Some(1));
+    }
+
+    #[test]
+    fn should_not_build_processes_of_the_wrong_kind() {
+        let registry = demo_registry();
+        assert_eq!(
+            registry.cell_processes(&["count"]).err(),
+            Some(RegistryError::Unknown("count".to_owned()))
+        );
+    }
+
+    #[test]
+    fn should_reject_duplicate_names_and_ids() {
+        let mut registry = demo_registry();
+        let duplicate_name = registry.register_global_process(
+            ProcessInfo::new(5, "reset", ""),
+            Box::new(|id| GlobalProcess::new(id, Box::new(example_global_process))),
+        );
+        assert_eq!(
+            duplicate_name,
+            Err(RegistryError::DuplicateName("reset".to_owned()))
+        );
+        let duplicate_id = registry.register_cell_process(
+            ProcessInfo::new(1, "other", ""),
+            Box::new(|id| CellProcess::new(id, Box::new(set_population_to_100))),
+        );
+        assert_eq!(duplicate_id, Err(RegistryError::DuplicateId(1)));
+    }
+
+    #[test]
+    fn should_collect_process_parameters() {
+        let parameters = demo_registry().parameters();
+        assert_eq!(parameters.specs().len(), 1);
+        assert_eq!(parameters.get_f64("growth_rate"), Ok(10.0));
+    }
+}
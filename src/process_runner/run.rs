@@ -9,12 +9,15 @@ use super::global::run::apply_global_updates;
 use super::global::run::Process as GlobalProcess;
 use super::global::state::GlobalStateBase;
 use super::network::get_network_map;
+use super::observer::Observer;
+use super::parameters::Parameters;
 use super::state::IterationState;
 
 #[allow(dead_code)]
 pub fn setup_initial_state<C: CellStateBase, G: GlobalStateBase>(
     cell_setup_processes: Option<Vec<&CellProcess<C, G>>>,
     global_setup_processes: Option<Vec<&GlobalProcess<C, G>>>,
+    parameters: &Parameters,
     cells_data: impl Into<Option<Vec<C>>>,
     global_state: impl Into<Option<G>>,
     randomize: impl Into<Option<bool>>,
@@ -35,6 +38,7 @@ pub fn setup_initial_state<C: CellStateBase, G: GlobalStateBase>(
         &initial_network,
         &cell_setup_processes.unwrap_or_default(),
         &initial_global_state,
+        parameters,
     );
     let updated_cells = apply_cell_updates::<C>(initial_cells, cell_updates);
     let updated_global_state = apply_global_updates::<G>(initial_global_state, global_updates);
@@ -47,6 +51,7 @@ pub fn setup_initial_state<C: CellStateBase, G: GlobalStateBase>(
         &final_network,
         &global_setup_processes.unwrap_or_default(),
         &updated_global_state,
+        parameters,
     );
     let updated_global_state = apply_global_updates(updated_global_state, update_global_actions);
 
@@ -61,10 +66,33 @@ pub fn setup_initial_state<C: CellStateBase, G: GlobalStateBase>(
 /// Can run either in full parallel, or update per process
 /// In full parallel each process is run on each cell before performing any updates
 /// in update_per_process
+///
+/// The parameters are passed to every process and are not modified.
 pub fn run_iteration<C: CellStateBase, G: GlobalStateBase>(
     cell_processes: &Vec<CellProcess<C, G>>,
     global_processes: &Vec<GlobalProcess<C, G>>,
     input_state: IterationState<C, G>,
+    parameters: &Parameters,
+    update_per_process: bool,
+) -> IterationState<C, G> {
+    run_iteration_with_processes(
+        &cell_processes.iter().collect(),
+        &global_processes.iter().collect(),
+        input_state,
+        parameters,
+        update_per_process,
+    )
+}
+
+/// Run a single iteration of the model with a selection of processes
+///
+/// This allows processes to be enabled or disabled between iterations
+/// without rebuilding the process lists.
+pub fn run_iteration_with_processes<C: CellStateBase, G: GlobalStateBase>(
+    cell_processes: &Vec<&CellProcess<C, G>>,
+    global_processes: &Vec<&GlobalProcess<C, G>>,
+    input_state: IterationState<C, G>,
+    parameters: &Parameters,
     update_per_process: bool,
 ) -> IterationState<C, G> {
     let network: Vec<Vec<CellIndex>> = get_network_map::<C>(&input_state.cells);
@@ -80,6 +108,7 @@ pub fn run_iteration<C: CellStateBase, G: GlobalStateBase>(
                 &network,
                 process,
                 &updated_global_state,
+                parameters,
             );
             updated_cells = apply_cell_updates::<C>(updated_cells, cell_updates);
             updated_global_state = apply_global_updates::<G>(updated_global_state, global_updates)
@@ -88,8 +117,9 @@ pub fn run_iteration<C: CellStateBase, G: GlobalStateBase>(
         let (cell_updates, global_updates) = cells::run::run_processes::<C, G>(
             &updated_cells,
             &network,
-            &cell_processes.iter().collect(),
+            cell_processes,
             &updated_global_state,
+            parameters,
         );
         updated_cells = apply_cell_updates::<C>(updated_cells, cell_updates);
         updated_global_state = apply_global_updates::<G>(updated_global_state, global_updates)
@@ -101,8 +131,9 @@ pub fn run_iteration<C: CellStateBase, G: GlobalStateBase>(
             let update_global_actions = global::run::run_processes::<C, G>(
                 &updated_cells.iter().collect(),
                 &network,
-                &vec![process],
+                &vec![*process],
                 &updated_global_state,
+                parameters,
             );
             updated_global_state =
                 apply_global_updates(updated_global_state, update_global_actions);
@@ -111,8 +142,9 @@ pub fn run_iteration<C: CellStateBase, G: GlobalStateBase>(
         let update_global_actions = global::run::run_processes::<C, G>(
             &updated_cells.iter().collect(),
             &network,
-            &global_processes.iter().collect(),
+            global_processes,
             &updated_global_state,
+            parameters,
         );
         updated_global_state = apply_global_updates(updated_global_state, update_global_actions);
     }
@@ -124,6 +156,34 @@ pub fn run_iteration<C: CellStateBase, G: GlobalStateBase>(
     current_state
 }
 
+/// Run multiple iterations of the model
+///
+/// Each observer is notified of the state at the end of every iteration.
+/// Iterations are counted from 1.
+pub fn run_iterations<C: CellStateBase, G: GlobalStateBase>(
+    cell_processes: &Vec<CellProcess<C, G>>,
+    global_processes: &Vec<GlobalProcess<C, G>>,
+    input_state: IterationState<C, G>,
+    parameters: &Parameters,
+    iterations: u32,
+    update_per_process: bool,
+    observers: &mut [&mut dyn Observer<C, G>],
+) -> IterationState<C, G> {
+    (1..=iterations).fold(input_state, |state, iteration| {
+        let next_state = run_iteration(
+            cell_processes,
+            global_processes,
+            state,
+            parameters,
+            update_per_process,
+        );
+        for observer in observers.iter_mut() {
+            observer.observe(iteration, &next_state);
+        }
+        next_state
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,12 +231,50 @@ mod tests {
                 &cell_processes,
                 &global_processes,
                 initial_state.clone(),
+                &Parameters::default(),
                 false,
             );
             assert_ne!(initial_state.cells, state_out.cells);
         }
     }
 
+    mod test_run_iterations {
+        use super::*;
+
+        struct IterationCounter {
+            seen: Vec<u32>,
+        }
+
+        impl Observer<CellState, GlobalState> for IterationCounter {
+            fn observe(&mut self, iteration: u32, state: &IterationState<CellState, GlobalState>) {
+                assert_eq!(state.global_state.iterations, iteration);
+                self.seen.push(iteration);
+            }
+        }
+
+        #[test]
+        fn should_run_multiple_iterations_and_notify_observers() {
+            let (cells, global_state, network) = get_demo_data();
+            let initial_state = IterationState {
+                cells,
+                global_state,
+                network,
+            };
+            let mut counter = IterationCounter { seen: vec![] };
+            let state_out = run_iterations(
+                &default_cell_processes(),
+                &default_global_processes(),
+                initial_state,
+                &default_parameters(),
+                3,
+                true,
+                &mut [&mut counter],
+            );
+            assert_eq!(counter.seen, vec![1, 2, 3]);
+            assert_eq!(state_out.global_state.iterations, 3);
+        }
+    }
+
     mod test_setup_initial_state {
         use super::*;
 
@@ -194,6 +292,7 @@ mod tests {
             let initial_state = setup_initial_state(
                 Some(cell_setup_processes.iter().collect()),
                 Some(global_setup_processes.iter().collect()),
+                &Parameters::default(),
                 cells.clone(),
                 None,
                 false,
@@ -208,6 +307,7 @@ mod tests {
             let initial_state = setup_initial_state(
                 Some(cell_setup_processes.iter().collect()),
                 Some(global_setup_processes.iter().collect()),
+                &Parameters::default(),
                 cells.clone(),
                 None,
                 true,
@@ -0,0 +1,288 @@
+/// Simulation Module
+///
+/// A simulation holds the model state, processes, parameters and event
+/// schedule and keeps count of the iterations run.
+use std::mem;
+
+use crate::process_runner::cancel::CancellationToken;
+use crate::process_runner::cells::run::Process as CellProcess;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::events::EventSchedule;
+use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::run::run_iteration_with_processes;
+use crate::process_runner::state::IterationState;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The state of a simulation that can be saved and restored
+///
+/// Processes and events are code so they are not included.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationSnapshot<C: CellStateBase, G: GlobalStateBase> {
+    pub state: IterationState<C, G>,
+    pub parameters: Parameters,
+    pub update_per_process: bool,
+    pub iteration: u32,
+}
+
+#[derive(Debug)]
+pub struct Simulation<C: CellStateBase, G: GlobalStateBase> {
+    pub state: IterationState<C, G>,
+    pub parameters: Parameters,
+    pub cell_processes: Vec<CellProcess<C, G>>,
+    pub global_processes: Vec<GlobalProcess<C, G>>,
+    pub events: EventSchedule<C, G>,
+    pub update_per_process: bool,
+    iteration: u32,
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> Simulation<C, G> {
+    pub fn new(
+        state: IterationState<C, G>,
+        cell_processes: Vec<CellProcess<C, G>>,
+        global_processes: Vec<GlobalProcess<C, G>>,
+        parameters: Parameters,
+    ) -> Simulation<C, G> {
+        Simulation {
+            state,
+            parameters,
+            cell_processes,
+            global_processes,
+            events: EventSchedule::default(),
+            update_per_process: true,
+            iteration: 0,
+        }
+    }
+
+    pub fn with_events(mut self, events: EventSchedule<C, G>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn with_update_per_process(mut self, update_per_process: bool) -> Self {
+        self.update_per_process = update_per_process;
+        self
+    }
+
+    /// The number of iterations that have been run
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    pub fn snapshot(&self) -> SimulationSnapshot<C, G> {
+        SimulationSnapshot {
+            state: self.state.clone(),
+            parameters: self.parameters.clone(),
+            update_per_process: self.update_per_process,
+            iteration: self.iteration,
+        }
+    }
+
+    /// Replace the state, parameters and iteration count with those of a snapshot
+    pub fn restore(&mut self, snapshot: SimulationSnapshot<C, G>) {
+        self.state = snapshot.state;
+        self.parameters = snapshot.parameters;
+        self.update_per_process = snapshot.update_per_process;
+        self.iteration = snapshot.iteration;
+    }
+
+    /// Run a single iteration
+    ///
+    /// Events for the iteration are applied before the enabled processes are run.
+    pub fn step(&mut self, observers: &mut [&mut dyn Observer<C, G>]) {
+        let iteration = self.iteration + 1;
+        let global_state = &self.state.global_state;
+        let disabled_cell_processes = self.events.disabled_cell_processes(iteration, global_state);
+        let disabled_global_processes = self
+            .events
+            .disabled_global_processes(iteration, global_state);
+        let cell_processes = self
+            .cell_processes
+            .iter()
+            .filter(|p| !disabled_cell_processes.contains(&p.id))
+            .collect();
+        let global_processes = self
+            .global_processes
+            .iter()
+            .filter(|p| !disabled_global_processes.contains(&p.id))
+            .collect();
+
+        // The state is moved out rather than cloned as it is replaced below
+        let state = self.events.apply(iteration, mem::take(&mut self.state));
+        self.state = run_iteration_with_processes(
+            &cell_processes,
+            &global_processes,
+            state,
+            &self.parameters,
+            self.update_per_process,
+        );
+        self.iteration = iteration;
+        for observer in observers.iter_mut() {
+            observer.observe(iteration, &self.state);
+        }
+    }
+
+    /// Run a number of iterations
+    pub fn run(&mut self, iterations: u32, observers: &mut [&mut dyn Observer<C, G>]) {
+        for _ in 0..iterations {
+            self.step(observers);
+        }
+    }
+
+    /// Run a number of iterations or until the token is cancelled
+    ///
+    /// The token is checked before each iteration so the state is always that
+    /// of the last completed iteration. Returns the number of iterations run.
+    pub fn run_until_cancelled(
+        &mut self,
+        iterations: u32,
+        observers: &mut [&mut dyn Observer<C, G>],
+        token: &CancellationToken,
+    ) -> u32 {
+        let mut completed = 0;
+        while completed < iterations && !token.is_cancelled() {
+            self.step(observers);
+            completed += 1;
+        }
+        completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::events::Event;
+    use crate::process_runner::events::EventAction;
+    use crate::process_runner::events::Trigger;
+    use crate::process_runner::examples::example_processes::*;
+    use crate::process_runner::examples::example_state::*;
+    use crate::process_runner::recorder::numeric_field_reader;
+    use crate::process_runner::recorder::Recorder;
+    use crate::process_runner::recorder::Statistic;
+    use geo::point;
+
+    fn demo_simulation() -> Simulation<CellState, GlobalState> {
+        let state = IterationState {
+            global_state: GlobalState::default(),
+            cells: (0..3)
+                .map(|i| CellState::new(i, point!(x: i as f64 * 10.0, y: 0.0), 100))
+                .collect(),
+            network: vec![vec![]],
+        };
+        Simulation::new(
+            state,
+            vec![
+                CellProcess::new(0, Box::new(population_growth)),
+                CellProcess::new(1, Box::new(set_population_to_100)),
+            ],
+            default_global_processes(),
+            default_parameters(),
+        )
+    }
+
+    #[test]
+    fn should_run_iterations_and_count_them() {
+        let mut simulation = demo_simulation();
+        let mut recorder = Recorder::new(vec![Statistic::Sum], None)
+            .unwrap()
+            .with_cell_field("population", numeric_field_reader("population"));
+        simulation.run(3, &mut [&mut recorder]);
+        assert_eq!(simulation.iteration(), 3);
+        assert_eq!(simulation.state.global_state.iterations, 3);
+        assert_eq!(recorder.iterations(), vec![1, 2, 3]);
+    }
+
+    struct CancelAt {
+        iteration: u32,
+        token: CancellationToken,
+    }
+
+    impl Observer<CellState, GlobalState> for CancelAt {
+        fn observe(&mut self, iteration: u32, _state: &IterationState<CellState, GlobalState>) {
+            if iteration == self.iteration {
+                self.token.cancel();
+            }
+        }
+    }
+
+    #[test]
+    fn should_stop_at_the_end_of_the_iteration_when_cancelled() {
+        let mut simulation = demo_simulation();
+        let token = CancellationToken::new();
+        let mut cancel_at = CancelAt {
+            iteration: 2,
+            token: token.clone(),
+        };
+        let completed = simulation.run_until_cancelled(10, &mut [&mut cancel_at], &token);
+        assert_eq!(completed, 2);
+        assert_eq!(simulation.iteration(), 2);
+        assert_eq!(simulation.state.global_state.iterations, 2);
+        assert_eq!(simulation.run_until_cancelled(10, &mut [], &token), 0);
+    }
+
+    #[test]
+    fn should_restore_a_snapshot_from_bytes() {
+        let mut simulation = demo_simulation();
+        simulation.run(2, &mut []);
+        let bytes = bincode::serialize(&simulation.snapshot()).unwrap();
+        let snapshot: SimulationSnapshot<CellState, GlobalState> =
+            bincode::deserialize(&bytes).unwrap();
+        let mut restored = demo_simulation();
+        restored.restore(snapshot);
+        assert_eq!(restored.iteration(), 2);
+        assert_eq!(restored.state, simulation.state);
+        simulation.step(&mut []);
+        restored.step(&mut []);
+        assert_eq!(restored.state, simulation.state);
+    }
+
+    #[test]
+    fn should_disable_processes_from_an_iteration() {
+        let mut simulation = demo_simulation().with_events(EventSchedule::new(vec![Event::new(
+            "Stop resetting population",
+            Trigger::From(2),
+            EventAction::DisableCellProcess(1),
+        )]));
+        simulation.step(&mut []);
+        assert_eq!(simulation.state.cells[0].population, 100);
+        simulation.step(&mut []);
+        assert_eq!(simulation.state.cells[0].population, 110);
+        simulation.step(&mut []);
+        assert_eq!(simulation.state.cells[0].population, 121);
+    }
+
+    #[test]
+    fn should_apply_cell_events_before_processes() {
+        let mut simulation = demo_simulation().with_events(EventSchedule::new(vec![
+            Event::new(
+                "Disable reset",
+                Trigger::From(1),
+                EventAction::DisableCellProcess(1),
+            ),
+            Event::new(
+                "Set population in region",
+                Trigger::At(2),
+                EventAction::UpdateCells {
+                    predicate: Box::new(|cell: &CellState, _| cell.position.x() > 5.0),
+                    update: Box::new(|mut cell: CellState| {
+                        cell.population = 1000;
+                        cell
+                    }),
+                },
+            ),
+        ]));
+        simulation.run(2, &mut []);
+        assert_eq!(
+            simulation
+                .state
+                .cells
+                .iter()
+                .map(|c| c.population)
+                .collect::<Vec<_>>(),
+            vec![121, 1100, 1100]
+        );
+    }
+}
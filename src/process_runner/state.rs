@@ -1,11 +1,24 @@
 use crate::process_runner::cells::state::CellIndex;
 use crate::process_runner::cells::state::CellStateBase;
 use crate::process_runner::global::state::GlobalStateBase;
+use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct IterationState<C: CellStateBase, G: GlobalStateBase> {
     // pub: iteration_counter: u32,
     pub global_state: G,
     pub cells: Vec<C>,
     pub network: Vec<Vec<CellIndex>>,
 }
+
+/// An empty state with no cells and the default global state
+impl<C: CellStateBase, G: GlobalStateBase> Default for IterationState<C, G> {
+    fn default() -> Self {
+        IterationState {
+            global_state: G::default(),
+            cells: vec![],
+            network: vec![],
+        }
+    }
+}
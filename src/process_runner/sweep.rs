@@ -0,0 +1,762 @@
+/// Sweep Module
+///
+/// Run a model over a space of parameter configurations and seeds and collect
+/// the chosen output metrics into a single results table.
+///
+/// Runs are executed in parallel with an independent seed for each run.
+/// Completed runs can be appended to a checkpoint file so that a partially
+/// completed sweep can be resumed. The seed of a run only depends on the base
+/// seed, configuration and replicate so adding replicates keeps earlier seeds.
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand::SeedableRng;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::process_runner::cells::run::Process as CellProcess;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::parameters::ParameterError;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::random;
+use crate::process_runner::run::run_iterations;
+use crate::process_runner::state::IterationState;
+
+/// A function that calculates an output metric from the final state of a run
+pub type MetricFn<C, G> = Box<dyn Fn(&IterationState<C, G>) -> f64 + Send + Sync>;
+
+/// A set of parameter values that override the base parameters
+pub type Configuration = Vec<(String, ParameterValue)>;
+
+#[derive(Debug)]
+pub enum SweepError {
+    Parameter(ParameterError),
+    /// Latin hypercube sampling requires a parameter with a min and max
+    MissingRange(String),
+    InvalidCheckpoint(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SweepError::Parameter(e) => write!(f, "{}", e),
+            SweepError::MissingRange(name) => write!(f, "Parameter {} has no range", name),
+            SweepError::InvalidCheckpoint(msg) => write!(f, "Invalid checkpoint: {}", msg),
+            SweepError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SweepError {}
+
+impl From<ParameterError> for SweepError {
+    fn from(e: ParameterError) -> Self {
+        SweepError::Parameter(e)
+    }
+}
+
+impl From<std::io::Error> for SweepError {
+    fn from(e: std::io::Error) -> Self {
+        SweepError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterSpace {
+    /// Every combination of the listed values
+    Grid(Vec<(String, Vec<ParameterValue>)>),
+    /// An explicit list of configurations
+    List(Vec<Configuration>),
+    /// Latin hypercube samples within the spec range of each parameter
+    LatinHypercube {
+        parameters: Vec<String>,
+        samples: usize,
+        seed: u64,
+    },
+}
+
+impl ParameterSpace {
+    /// Get all configurations in the space
+    ///
+    /// The order is deterministic so configurations can be identified by index.
+    pub fn configurations(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<Vec<Configuration>, SweepError> {
+        match self {
+            ParameterSpace::Grid(axes) => {
+                Ok(axes
+                    .iter()
+                    .fold(vec![vec![]], |configurations, (name, values)| {
+                        configurations
+                            .iter()
+                            .flat_map(|c| {
+                                values.iter().map(move |v| {
+                                    let mut configuration = c.clone();
+                                    configuration.push((name.to_owned(), *v));
+                                    configuration
+                                })
+                            })
+                            .collect()
+                    }))
+            }
+            ParameterSpace::List(configurations) => Ok(configurations.clone()),
+            ParameterSpace::LatinHypercube {
+                parameters: names,
+                samples,
+                seed,
+            } => {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                let columns = names
+                    .iter()
+                    .map(|name| {
+                        let spec = parameters
+                            .spec(name)
+                            .ok_or_else(|| ParameterError::Unknown(name.to_owned()))?;
+                        let (min, max) = match (spec.min, spec.max) {
+                            (Some(min), Some(max)) => (min, max),
+                            _ => return Err(SweepError::MissingRange(name.to_owned())),
+                        };
+                        // Take one sample from each stratum in a random order
+                        let mut strata = (0..*samples).collect::<Vec<usize>>();
+                        strata.shuffle(&mut rng);
+                        Ok(strata
+                            .iter()
+                            .map(|stratum| {
+                                let u = (*stratum as f64 + rng.gen::<f64>()) / *samples as f64;
+                                let value = min + u * (max - min);
+                                match spec.default {
+                                    ParameterValue::Int(_) => {
+                                        ParameterValue::Int(value.round() as i64)
+                                    }
+                                    _ => ParameterValue::Float(value),
+                                }
+                            })
+                            .collect::<Vec<_>>())
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((0..*samples)
+                    .map(|i| {
+                        names
+                            .iter()
+                            .zip(columns.iter())
+                            .map(|(name, column)| (name.to_owned(), column[i]))
+                            .collect()
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// The outcome of a single run
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepRow {
+    pub configuration: usize,
+    pub replicate: usize,
+    pub seed: u64,
+    pub parameters: Vec<f64>,
+    pub metrics: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResults {
+    pub parameter_names: Vec<String>,
+    pub metric_names: Vec<String>,
+    pub rows: Vec<SweepRow>,
+}
+
+impl SweepResults {
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns = vec![
+            "configuration".to_owned(),
+            "replicate".to_owned(),
+            "seed".to_owned(),
+        ];
+        columns.extend(self.parameter_names.iter().cloned());
+        columns.extend(self.metric_names.iter().cloned());
+        columns
+    }
+
+    /// Get the values of a metric for every run
+    pub fn metric(&self, name: &str) -> Option<Vec<f64>> {
+        let index = self.metric_names.iter().position(|n| n == name)?;
+        Some(self.rows.iter().map(|r| r.metrics[index]).collect())
+    }
+
+    fn row_to_csv(row: &SweepRow) -> String {
+        let mut values = vec![
+            row.configuration.to_string(),
+            row.replicate.to_string(),
+            row.seed.to_string(),
+        ];
+        values.extend(row.parameters.iter().map(|v| v.to_string()));
+        values.extend(row.metrics.iter().map(|v| v.to_string()));
+        values.join(",")
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = self.columns().join(",");
+        out.push('\n');
+        for row in self.rows.iter() {
+            out.push_str(&SweepResults::row_to_csv(row));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Read results written by `to_csv`
+    ///
+    /// The parameter and metric names are required to split the columns.
+    pub fn from_csv(
+        csv: &str,
+        parameter_names: Vec<String>,
+        metric_names: Vec<String>,
+    ) -> Result<SweepResults, SweepError> {
+        let mut results = SweepResults {
+            parameter_names,
+            metric_names,
+            rows: vec![],
+        };
+        let mut lines = csv.lines().filter(|l| !l.trim().is_empty());
+        match lines.next() {
+            Some(header) if header == results.columns().join(",") => {}
+            Some(header) => {
+                return Err(SweepError::InvalidCheckpoint(format!(
+                    "Unexpected header: {}",
+                    header
+                )))
+            }
+            None => return Ok(results),
+        }
+        let parameter_count = results.parameter_names.len();
+        for (i, line) in lines.enumerate() {
+            let invalid = || SweepError::InvalidCheckpoint(format!("Invalid row {}", i + 1));
+            let values = line.split(',').collect::<Vec<_>>();
+            if values.len() != results.columns().len() {
+                return Err(invalid());
+            }
+            let numbers = values[3..]
+                .iter()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+            results.rows.push(SweepRow {
+                configuration: values[0].parse().map_err(|_| invalid())?,
+                replicate: values[1].parse().map_err(|_| invalid())?,
+                seed: values[2].parse().map_err(|_| invalid())?,
+                parameters: numbers[..parameter_count].to_vec(),
+                metrics: numbers[parameter_count..].to_vec(),
+            });
+        }
+        Ok(results)
+    }
+}
+
+/// Whether a checkpoint value matches, treating NaN as equal to NaN
+fn same_value(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// A single run waiting to be executed
+struct PendingRun {
+    configuration: usize,
+    replicate: usize,
+    seed: u64,
+    parameters: Parameters,
+}
+
+pub struct Sweep<C: CellStateBase, G: GlobalStateBase> {
+    space: ParameterSpace,
+    iterations: u32,
+    replicates: usize,
+    base_seed: u64,
+    threads: usize,
+    update_per_process: bool,
+    checkpoint: Option<PathBuf>,
+    metrics: Vec<(String, MetricFn<C, G>)>,
+}
+
+impl<C: CellStateBase, G: GlobalStateBase> Sweep<C, G> {
+    /// Create a sweep that runs each configuration for the number of iterations
+    ///
+    /// Defaults to a single replicate per configuration using all available threads.
+    pub fn new(space: ParameterSpace, iterations: u32) -> Sweep<C, G> {
+        Sweep {
+            space,
+            iterations,
+            replicates: 1,
+            base_seed: 0,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            update_per_process: true,
+            checkpoint: None,
+            metrics: vec![],
+        }
+    }
+
+    /// Set the number of replicate runs with different seeds for each configuration
+    pub fn with_replicates(mut self, replicates: usize) -> Self {
+        self.replicates = replicates;
+        self
+    }
+
+    /// Set the seed that the seed of each run is derived from
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.base_seed = seed;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_update_per_process(mut self, update_per_process: bool) -> Self {
+        self.update_per_process = update_per_process;
+        self
+    }
+
+    /// Append each completed run to a CSV file
+    ///
+    /// If the file already contains results those runs are skipped. Every
+    /// row must have the parameter values and seed of its run in this sweep.
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Add an output metric calculated from the final state of each run
+    pub fn with_metric(mut self, name: &str, metric: MetricFn<C, G>) -> Self {
+        self.metrics.push((name.to_owned(), metric));
+        self
+    }
+
+    fn empty_results(&self, configurations: &[Configuration]) -> SweepResults {
+        let mut parameter_names: Vec<String> = vec![];
+        for (name, _) in configurations.iter().flatten() {
+            if !parameter_names.contains(name) {
+                parameter_names.push(name.to_owned());
+            }
+        }
+        SweepResults {
+            parameter_names,
+            metric_names: self.metrics.iter().map(|(n, _)| n.to_owned()).collect(),
+            rows: vec![],
+        }
+    }
+
+    /// The seed of a run, independent of the number of replicates
+    fn run_seed(&self, configuration: usize, replicate: usize) -> u64 {
+        random::derive_seed(
+            random::derive_seed(self.base_seed, configuration as u64),
+            replicate as u64,
+        )
+    }
+
+    fn read_checkpoint(&self, empty_results: SweepResults) -> Result<SweepResults, SweepError> {
+        match &self.checkpoint {
+            Some(path) if path.exists() => SweepResults::from_csv(
+                &fs::read_to_string(path)?,
+                empty_results.parameter_names,
+                empty_results.metric_names,
+            ),
+            _ => Ok(empty_results),
+        }
+    }
+
+    /// Run every configuration and replicate
+    ///
+    /// The setup function is called on the run thread after it has been seeded
+    /// so randomized initial states are reproducible.
+    pub fn run<S>(
+        &self,
+        parameters: &Parameters,
+        cell_processes: &Vec<CellProcess<C, G>>,
+        global_processes: &Vec<GlobalProcess<C, G>>,
+        setup: S,
+    ) -> Result<SweepResults, SweepError>
+    where
+        S: Fn(&Parameters) -> IterationState<C, G> + Sync,
+    {
+        let configurations = self.space.configurations(parameters)?;
+        let mut results = self.read_checkpoint(self.empty_results(&configurations))?;
+        let parameter_values = |run_parameters: &Parameters| -> Vec<f64> {
+            results
+                .parameter_names
+                .iter()
+                .map(|n| run_parameters.get(n).map_or(f64::NAN, |v| v.as_f64()))
+                .collect()
+        };
+
+        let mut pending: Vec<PendingRun> = vec![];
+        let mut completed = 0;
+        for (configuration, values) in configurations.iter().enumerate() {
+            let run_parameters = values
+                .iter()
+                .try_fold(parameters.clone(), |p, (name, value)| p.with(name, *value))?;
+            let expected_values = parameter_values(&run_parameters);
+            for replicate in 0..self.replicates {
+                let seed = self.run_seed(configuration, replicate);
+                let row = results
+                    .rows
+                    .iter()
+                    .find(|r| r.configuration == configuration && r.replicate == replicate);
+                match row {
+                    Some(row)
+                        if row.seed == seed
+                            && row.parameters.len() == expected_values.len()
+                            && row
+                                .parameters
+                                .iter()
+                                .zip(expected_values.iter())
+                                .all(|(a, b)| same_value(*a, *b)) =>
+                    {
+                        completed += 1
+                    }
+                    Some(_) => {
+                        return Err(SweepError::InvalidCheckpoint(format!(
+                            "Configuration {} replicate {} has different parameters or seed",
+                            configuration, replicate
+                        )))
+                    }
+                    None => pending.push(PendingRun {
+                        configuration,
+                        replicate,
+                        seed,
+                        parameters: run_parameters.clone(),
+                    }),
+                }
+            }
+        }
+        if completed != results.rows.len() {
+            return Err(SweepError::InvalidCheckpoint(
+                "Rows for runs that are not in the sweep".to_owned(),
+            ));
+        }
+
+        let mut checkpoint_file = match &self.checkpoint {
+            Some(path) => {
+                let is_new = !path.exists() || fs::metadata(path)?.len() == 0;
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                if is_new {
+                    writeln!(file, "{}", results.columns().join(","))?;
+                }
+                Some(file)
+            }
+            None => None,
+        };
+
+        let parameter_values = &parameter_values;
+        let next_run = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel::<SweepRow>();
+        let new_rows = thread::scope(|scope| -> Result<Vec<SweepRow>, SweepError> {
+            for _ in 0..self.threads.min(pending.len()) {
+                let sender = sender.clone();
+                let (pending, next_run, setup) = (&pending, &next_run, &setup);
+                scope.spawn(move || {
+                    while let Some(run) = pending.get(next_run.fetch_add(1, Ordering::SeqCst)) {
+                        random::seed(run.seed);
+                        let final_state = run_iterations(
+                            cell_processes,
+                            global_processes,
+                            setup(&run.parameters),
+                            &run.parameters,
+                            self.iterations,
+                            self.update_per_process,
+                            &mut [],
+                        );
+                        let row = SweepRow {
+                            configuration: run.configuration,
+                            replicate: run.replicate,
+                            seed: run.seed,
+                            parameters: parameter_values(&run.parameters),
+                            metrics: self.metrics.iter().map(|(_, m)| m(&final_state)).collect(),
+                        };
+                        if sender.send(row).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            let mut rows = vec![];
+            for row in receiver.iter() {
+                if let Some(file) = checkpoint_file.as_mut() {
+                    writeln!(file, "{}", SweepResults::row_to_csv(&row))?;
+                }
+                rows.push(row);
+            }
+            Ok(rows)
+        })?;
+
+        results.rows.extend(new_rows);
+        results.rows.sort_by_key(|r| (r.configuration, r.replicate));
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_processes::*;
+    use crate::process_runner::examples::example_state::*;
+    use geo::point;
+
+    fn demo_setup(_parameters: &Parameters) -> IterationState<CellState, GlobalState> {
+        IterationState {
+            global_state: GlobalState::default(),
+            cells: (0..4)
+                .map(|i| CellState::new(i, point!(x: i as f64 * 10.0, y: 0.0), 100))
+                .collect(),
+            network: vec![vec![]],
+        }
+    }
+
+    fn demo_sweep(space: ParameterSpace) -> Sweep<CellState, GlobalState> {
+        Sweep::new(space, 2).with_metric(
+            "total_population",
+            Box::new(|state: &IterationState<CellState, GlobalState>| {
+                state.cells.iter().map(|c| c.population as f64).sum()
+            }),
+        )
+    }
+
+    fn growth_grid() -> ParameterSpace {
+        ParameterSpace::Grid(vec![(
+            "growth_rate".to_owned(),
+            vec![ParameterValue::Float(0.0), ParameterValue::Float(100.0)],
+        )])
+    }
+
+    mod test_parameter_space {
+        use super::*;
+
+        #[test]
+        fn should_get_all_grid_combinations() {
+            let space = ParameterSpace::Grid(vec![
+                (
+                    "a".to_owned(),
+                    vec![ParameterValue::Int(1), ParameterValue::Int(2)],
+                ),
+                (
+                    "b".to_owned(),
+                    vec![
+                        ParameterValue::Bool(true),
+                        ParameterValue::Bool(false),
+                        ParameterValue::Bool(true),
+                    ],
+                ),
+            ]);
+            let configurations = space.configurations(&Parameters::default()).unwrap();
+            assert_eq!(configurations.len(), 6);
+            assert_eq!(
+                configurations[1],
+                vec![
+                    ("a".to_owned(), ParameterValue::Int(1)),
+                    ("b".to_owned(), ParameterValue::Bool(false))
+                ]
+            );
+        }
+
+        #[test]
+        fn should_sample_one_value_per_stratum_in_latin_hypercube() {
+            let space = ParameterSpace::LatinHypercube {
+                parameters: vec!["growth_rate".to_owned()],
+                samples: 4,
+                seed: 1,
+            };
+            let configurations = space.configurations(&default_parameters()).unwrap();
+            let mut strata = configurations
+                .iter()
+                .map(|c| ((c[0].1.as_f64() + 200.0) / 100.0).floor() as usize)
+                .collect::<Vec<_>>();
+            strata.sort_unstable();
+            assert_eq!(strata, vec![0, 1, 2, 3]);
+            assert_eq!(
+                configurations,
+                space.configurations(&default_parameters()).unwrap()
+            );
+        }
+
+        #[test]
+        fn should_require_a_range_for_latin_hypercube() {
+            let space = ParameterSpace::LatinHypercube {
+                parameters: vec!["growth_rate".to_owned()],
+                samples: 4,
+                seed: 1,
+            };
+            let parameters =
+                Parameters::new(vec![crate::process_runner::parameters::ParameterSpec::new(
+                    "growth_rate",
+                    "Growth Rate",
+                    ParameterValue::Float(0.0),
+                )]);
+            assert!(matches!(
+                space.configurations(&parameters),
+                Err(SweepError::MissingRange(_))
+            ));
+        }
+    }
+
+    mod test_sweep {
+        use super::*;
+
+        #[test]
+        fn should_run_each_configuration_and_replicate() {
+            let sweep = demo_sweep(growth_grid()).with_replicates(3).with_threads(2);
+            let results = sweep
+                .run(
+                    &default_parameters(),
+                    &vec![CellProcess::new(0, Box::new(population_growth))],
+                    &default_global_processes(),
+                    demo_setup,
+                )
+                .unwrap();
+            assert_eq!(results.rows.len(), 6);
+            assert_eq!(results.parameter_names, vec!["growth_rate".to_owned()]);
+            assert_eq!(
+                results.metric("total_population"),
+                Some(vec![400.0, 400.0, 400.0, 1600.0, 1600.0, 1600.0])
+            );
+            let seeds = results
+                .rows
+                .iter()
+                .map(|r| r.seed)
+                .collect::<std::collections::HashSet<_>>();
+            assert_eq!(seeds.len(), 6);
+        }
+
+        #[test]
+        fn should_resume_from_checkpoint() {
+            let path = std::env::temp_dir().join(format!(
+                "cellular_automata_sweep_checkpoint_{}.csv",
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            let cell_processes = vec![CellProcess::new(0, Box::new(population_growth))];
+
+            // Run part of the sweep by only including the first configuration
+            let partial_space = ParameterSpace::List(vec![vec![(
+                "growth_rate".to_owned(),
+                ParameterValue::Float(0.0),
+            )]]);
+            demo_sweep(partial_space)
+                .with_checkpoint(&path)
+                .run(
+                    &default_parameters(),
+                    &cell_processes,
+                    &default_global_processes(),
+                    demo_setup,
+                )
+                .unwrap();
+
+            // The previous run is loaded and only the second configuration is run
+            let results = demo_sweep(growth_grid())
+                .with_checkpoint(&path)
+                .run(
+                    &default_parameters(),
+                    &cell_processes,
+                    &default_global_processes(),
+                    |p: &Parameters| {
+                        assert_eq!(p.get_f64("growth_rate"), Ok(100.0));
+                        demo_setup(p)
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                results.metric("total_population"),
+                Some(vec![400.0, 1600.0])
+            );
+            let checkpoint = fs::read_to_string(&path).unwrap();
+
+            // Adding replicates keeps the seeds of the completed runs
+            let results = demo_sweep(growth_grid())
+                .with_replicates(2)
+                .with_checkpoint(&path)
+                .run(
+                    &default_parameters(),
+                    &cell_processes,
+                    &default_global_processes(),
+                    demo_setup,
+                )
+                .unwrap();
+            assert_eq!(results.rows.len(), 4);
+            fs::remove_file(&path).unwrap();
+            assert_eq!(checkpoint, demo_results_csv(&results, 0));
+        }
+
+        /// The CSV of the rows of a single replicate
+        fn demo_results_csv(results: &SweepResults, replicate: usize) -> String {
+            SweepResults {
+                rows: results
+                    .rows
+                    .iter()
+                    .filter(|r| r.replicate == replicate)
+                    .cloned()
+                    .collect(),
+                ..results.clone()
+            }
+            .to_csv()
+        }
+
+        #[test]
+        fn should_reject_a_checkpoint_from_a_different_sweep() {
+            let path = std::env::temp_dir().join(format!(
+                "cellular_automata_sweep_stale_checkpoint_{}.csv",
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            let cell_processes = vec![CellProcess::new(0, Box::new(population_growth))];
+            let run = |space: ParameterSpace, seed: u64| {
+                demo_sweep(space)
+                    .with_seed(seed)
+                    .with_checkpoint(&path)
+                    .run(
+                        &default_parameters(),
+                        &cell_processes,
+                        &default_global_processes(),
+                        demo_setup,
+                    )
+            };
+            run(growth_grid(), 0).unwrap();
+
+            let changed_space = ParameterSpace::Grid(vec![(
+                "growth_rate".to_owned(),
+                vec![ParameterValue::Float(0.0), ParameterValue::Float(50.0)],
+            )]);
+            let smaller_space = ParameterSpace::List(vec![vec![(
+                "growth_rate".to_owned(),
+                ParameterValue::Float(0.0),
+            )]]);
+            let changed_space = run(changed_space, 0);
+            let changed_seed = run(growth_grid(), 1);
+            let smaller_space = run(smaller_space, 0);
+            fs::remove_file(&path).unwrap();
+            assert!(matches!(
+                changed_space,
+                Err(SweepError::InvalidCheckpoint(_))
+            ));
+            assert!(matches!(
+                changed_seed,
+                Err(SweepError::InvalidCheckpoint(_))
+            ));
+            assert!(matches!(
+                smaller_space,
+                Err(SweepError::InvalidCheckpoint(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,36 @@
+/* Cancellation Interface
+
+A cancellation token that python code can share with a running model.
+Call `cancel` from another thread, e.g. a GUI request handler, to stop the
+run after the current iteration.
+*/
+extern crate pyo3;
+use crate::process_runner::cancel::CancellationToken;
+use pyo3::prelude::*;
+
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct CancellationTokenPy {
+    pub inner: CancellationToken,
+}
+
+#[pymethods]
+impl CancellationTokenPy {
+    #[new]
+    pub fn new() -> Self {
+        CancellationTokenPy::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn reset(&self) {
+        self.inner.reset();
+    }
+
+    #[getter]
+    pub fn cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
@@ -0,0 +1,209 @@
+/* GUI Model Interface
+
+The python side of the web GUI contract for the example model.
+
+`GuiModelPy` provides the functions the GUI calls: get_model_layers,
+get_cell_actions, action_runner, get_config_fields, setup_state and
+run_iteration. Layers, actions and controls come from the rust `GuiModel`.
+*/
+extern crate pyo3;
+use std::collections::HashMap;
+
+use super::gui::cell_action_to_py;
+use super::gui::control_to_py;
+use super::gui::gui_global_state_to_py;
+use super::gui::layer_to_py;
+use super::parameters::parameters_from_py;
+use crate::io::geojson::GeoJsonCells;
+use crate::io::geojson::GeoJsonLoader;
+use crate::process_runner::examples::example_gui::ExampleGuiModel;
+use crate::process_runner::examples::example_state::CellState;
+use crate::process_runner::examples::example_state::GlobalState;
+use crate::process_runner::gui::update_layer_totals;
+use crate::process_runner::gui::GuiModel;
+use crate::process_runner::gui::TOTALS_HISTORY_LENGTH;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::random;
+use crate::process_runner::run::run_iteration;
+use crate::process_runner::run::setup_initial_state;
+use crate::process_runner::state::IterationState;
+use crate::py_interface::examples::CellStatePy;
+use crate::py_interface::examples::GlobalStatePy;
+use crate::py_interface::PyWrapperBase;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Python wrapper for the example model GUI interface
+#[pyclass]
+#[derive(Debug, Default, Clone)]
+pub struct GuiModelPy {
+    pub inner: ExampleGuiModel,
+}
+
+/// Read the layer totals and model state from a GUI global state dict
+///
+/// Missing keys use empty totals and the default global state.
+fn gui_global_state_from_py(
+    global_state: &PyDict,
+) -> PyResult<(HashMap<String, Vec<f64>>, GlobalState)> {
+    let totals = match global_state.get_item("totals") {
+        Some(totals) => totals.extract()?,
+        None => HashMap::new(),
+    };
+    let model_state = match global_state.get_item("model_state") {
+        Some(model_state) => model_state.extract::<GlobalStatePy>()?.get_inner(),
+        None => GlobalState::default(),
+    };
+    Ok((totals, model_state))
+}
+
+#[pymethods]
+impl GuiModelPy {
+    #[new]
+    pub fn new() -> Self {
+        GuiModelPy::default()
+    }
+
+    /// The map layers as dicts of uid and label
+    pub fn get_model_layers(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        self.inner
+            .layers()
+            .iter()
+            .map(|layer| layer_to_py(py, layer))
+            .collect()
+    }
+
+    /// The cell actions as dicts of uid, label, type and group
+    pub fn get_cell_actions(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        self.inner
+            .cell_actions()
+            .iter()
+            .map(|action| cell_action_to_py(py, action))
+            .collect()
+    }
+
+    /// Apply the action to the cell and return the updated cell
+    ///
+    /// Extra keyword arguments are accepted for the GUI but are not used.
+    /// Raises a ValueError if the action does not exist.
+    #[args(_kwargs = "**")]
+    pub fn action_runner(
+        &self,
+        cell: CellStatePy,
+        action_id: &str,
+        _kwargs: Option<&PyDict>,
+    ) -> PyResult<CellStatePy> {
+        self.inner
+            .apply_action(cell.get_inner(), action_id)
+            .map(|c| CellStatePy::from_inner(&c))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// The parameter controls as GUI control dicts
+    pub fn get_config_fields(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        self.inner
+            .controls()
+            .iter()
+            .map(|control| control_to_py(py, control))
+            .collect()
+    }
+
+    /// Create the randomized initial cells from a GeoJSON FeatureCollection string
+    ///
+    /// Each feature becomes a cell at the centroid of its geometry.
+    /// Returns the cells and the GUI global state dict with the keys totals,
+    /// network_map, model_state and labels. Labels are read from the label
+    /// property and are None if it is not set.
+    #[args(seed = "None", label_property = "None")]
+    pub fn setup_state(
+        &self,
+        py: Python,
+        geojson: &str,
+        seed: Option<u64>,
+        label_property: Option<&str>,
+    ) -> PyResult<(Vec<CellStatePy>, PyObject)> {
+        if let Some(seed) = seed {
+            random::seed(seed);
+        }
+        let mut loader = GeoJsonLoader::new();
+        if let Some(property) = label_property {
+            loader = loader.with_label_property(property);
+        }
+        let loaded: GeoJsonCells<CellState> = loader
+            .load_str(geojson)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let state: IterationState<CellState, GlobalState> = setup_initial_state(
+            None,
+            None,
+            &self.inner.parameters(),
+            loaded.cells,
+            None,
+            true,
+        );
+        let mut totals = HashMap::new();
+        update_layer_totals(
+            &self.inner,
+            &mut totals,
+            &state.cells,
+            TOTALS_HISTORY_LENGTH,
+        );
+        let global_state = gui_global_state_to_py(
+            py,
+            totals,
+            &state.network,
+            GlobalStatePy::from_inner(&state.global_state),
+        )?;
+        global_state.as_ref(py).set_item("labels", loaded.labels)?;
+        Ok((
+            state.cells.iter().map(CellStatePy::from_inner).collect(),
+            global_state,
+        ))
+    }
+
+    /// Run an iteration with the GUI config as parameter values
+    ///
+    /// Returns the cells and the GUI global state dict with the new totals
+    /// added to the history.
+    /// The GIL is released while the iteration runs.
+    pub fn run_iteration(
+        &self,
+        py: Python,
+        config: Option<HashMap<String, ParameterValue>>,
+        cells: Vec<CellStatePy>,
+        global_state: &PyDict,
+    ) -> PyResult<(Vec<CellStatePy>, PyObject)> {
+        let parameters = parameters_from_py(self.inner.parameters(), config)?;
+        let (mut totals, model_state) = gui_global_state_from_py(global_state)?;
+        let initial_state = IterationState {
+            global_state: model_state,
+            cells: cells.iter().map(|c| c.get_inner()).collect(),
+            network: vec![vec![]],
+        };
+        let model = self.inner;
+        let state = py.allow_threads(move || {
+            run_iteration(
+                &model.cell_processes(),
+                &model.global_processes(),
+                initial_state,
+                &parameters,
+                true,
+            )
+        });
+        update_layer_totals(
+            &self.inner,
+            &mut totals,
+            &state.cells,
+            TOTALS_HISTORY_LENGTH,
+        );
+        Ok((
+            state.cells.iter().map(CellStatePy::from_inner).collect(),
+            gui_global_state_to_py(
+                py,
+                totals,
+                &state.network,
+                GlobalStatePy::from_inner(&state.global_state),
+            )?,
+        ))
+    }
+}
@@ -0,0 +1,115 @@
+extern crate pyo3;
+use super::example_simulation::check_numeric_cell_field;
+use crate::process_runner::examples::example_state::CellState;
+use crate::process_runner::examples::example_state::GlobalState;
+use crate::process_runner::recorder::numeric_field_reader;
+use crate::process_runner::recorder::Recorder;
+use crate::process_runner::recorder::Statistic;
+use crate::process_runner::state::IterationState;
+use crate::py_interface::examples::CellStatePy;
+use crate::py_interface::examples::GlobalStatePy;
+use crate::py_interface::PyWrapperBase;
+use pyo3::exceptions::PyKeyError;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Python wrapper for the example model recorder
+///
+/// Records the global state and the chosen statistics of each cell field.
+/// Statistics are named "sum", "mean", "min", "max" or "p{percentile}" e.g. "p90".
+#[pyclass]
+pub struct RecorderPy {
+    pub inner: Recorder<CellState, GlobalState>,
+}
+
+#[pymethods]
+impl RecorderPy {
+    #[new]
+    #[args(history_length = "None")]
+    pub fn new(
+        fields: Vec<String>,
+        statistics: Vec<String>,
+        history_length: Option<usize>,
+    ) -> PyResult<Self> {
+        let statistics = statistics
+            .iter()
+            .map(|s| s.parse::<Statistic>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(PyValueError::new_err)?;
+        let recorder = Recorder::new(statistics, history_length)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let recorder = ["iterations", "population"]
+            .iter()
+            .fold(recorder, |recorder, name| {
+                recorder.with_global_field(name, numeric_field_reader(name))
+            });
+        let recorder = fields.iter().try_fold(recorder, |recorder, name| {
+            check_numeric_cell_field(name)?;
+            Ok::<_, PyErr>(recorder.with_cell_field(name, numeric_field_reader(name)))
+        })?;
+        Ok(RecorderPy { inner: recorder })
+    }
+
+    /// Record the state of a single iteration
+    pub fn record(
+        &mut self,
+        iteration: u32,
+        cell_data: Vec<CellStatePy>,
+        global_state: GlobalStatePy,
+    ) {
+        let state = IterationState {
+            global_state: global_state.get_inner(),
+            cells: cell_data.iter().map(|c| c.get_inner()).collect(),
+            network: vec![vec![]],
+        };
+        self.inner.record(iteration, &state);
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    #[getter]
+    pub fn iterations(&self) -> Vec<u32> {
+        self.inner.iterations()
+    }
+
+    #[getter]
+    pub fn global_states(&self) -> Vec<GlobalStatePy> {
+        self.inner
+            .global_states()
+            .iter()
+            .map(|g| GlobalStatePy::from_inner(g))
+            .collect()
+    }
+
+    #[getter]
+    pub fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    /// Get the time series of a statistic for a cell field
+    pub fn series(&self, field: &str, statistic: &str) -> PyResult<Vec<f64>> {
+        let statistic = statistic
+            .parse::<Statistic>()
+            .map_err(PyValueError::new_err)?;
+        self.inner
+            .series(field, statistic)
+            .ok_or_else(|| PyKeyError::new_err(format!("Not recorded: {}_{}", field, statistic)))
+    }
+
+    /// Get the time series of a global state field
+    pub fn global_series(&self, field: &str) -> PyResult<Vec<f64>> {
+        self.inner
+            .global_series(field)
+            .ok_or_else(|| PyKeyError::new_err(format!("Not recorded: {}", field)))
+    }
+
+    pub fn to_csv(&self) -> String {
+        self.inner.to_csv()
+    }
+
+    pub fn to_json(&self) -> String {
+        self.inner.to_json()
+    }
+}
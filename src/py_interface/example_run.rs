@@ -1,11 +1,56 @@
 extern crate pyo3;
+use std::collections::HashMap;
+
+use super::cancel::CancellationTokenPy;
+use super::parameters::parameter_spec_to_py;
+use super::parameters::parameters_from_py;
+use super::py_process::cell_processes_from_py;
+use super::py_process::global_processes_from_py;
+use super::py_process::has_python_processes;
+use super::py_process::process_info_to_py;
 use super::run::run_iteration_py_wrap;
-use crate::process_runner::examples::example_processes::default_cell_processes;
+use super::run::run_simulation_py_wrap;
+use super::run::setup_initial_state_py_wrap;
+use crate::io::ascii_grid::cells_to_ascii_grid;
+use crate::io::ascii_grid::AsciiGrid;
+use crate::io::ascii_grid::AsciiGridLoader;
+use crate::io::ascii_grid::GridCells;
+use crate::io::csv::cells_to_csv;
+use crate::io::csv::CsvCellReader;
+use crate::io::geojson::state_to_geojson;
+use crate::io::geojson::GeoJsonCells;
+use crate::io::geojson::GeoJsonLoader;
+use crate::io::render::ColourMap;
+use crate::io::render::ColourScale;
+use crate::io::render::SvgRenderer;
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::examples::example_processes::default_parameters;
+use crate::process_runner::examples::example_processes::default_registry;
+use crate::process_runner::examples::example_processes::CellProcessT;
+use crate::process_runner::examples::example_processes::GlobalProcessT;
+use crate::process_runner::examples::example_processes::DEFAULT_CELL_PROCESS_NAMES;
+use crate::process_runner::examples::example_state::CellState;
+use crate::process_runner::examples::example_state::GlobalState;
+use crate::process_runner::fields::FieldDescriptor;
+use crate::process_runner::fields::FieldKind;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::network::check_is_neighbour;
+use crate::process_runner::network::get_network_map;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::random;
+use crate::process_runner::simulation::Simulation;
+use crate::process_runner::state::IterationState;
 use crate::py_interface::examples::CellStatePy;
 use crate::py_interface::examples::GlobalStatePy;
+use crate::py_interface::PyWrapperBase;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use pyo3::wrap_pyfunction;
 
+/// The cells, global state, network and number of iterations run returned to python
+type IterationsPy = (Vec<CellStatePy>, GlobalStatePy, Vec<Vec<u32>>, u32);
+
 // TODO: Move these to an example library
 /// The python facing run_iteration wrapper function
 ///
@@ -15,25 +60,453 @@ use pyo3::wrap_pyfunction;
 /// This wraps the run_iteration function for python.
 /// It can only take python arguments and must return a PyResult object.
 ///
-/// To create a new model with different processes we need to make a copy of this function.
-#[pyfunction]
+/// Parameters are passed as a dict of name to value. Unset parameters use their defaults.
+///
+/// Processes are chosen by registered name or written in python, see example_processes.
+/// If none are chosen the default processes run.
+/// See py_process for the python callable signatures and their cost.
+///
+/// The GIL is released while the iteration runs.
+#[pyfunction(
+    parameters = "None",
+    cell_processes = "None",
+    global_processes = "None"
+)]
 pub fn run_iteration_py(
+    py: Python,
     cell_data: Vec<CellStatePy>,
     global_state: GlobalStatePy,
+    parameters: Option<HashMap<String, ParameterValue>>,
+    cell_processes: Option<Vec<PyObject>>,
+    global_processes: Option<Vec<PyObject>>,
 ) -> PyResult<(Vec<CellStatePy>, GlobalStatePy, Vec<Vec<u32>>)> {
-    // TODO: Add global processes
-    let global_processes = vec![];
-    run_iteration_py_wrap(
-        cell_data,
-        global_state,
-        default_cell_processes(),
-        global_processes,
-        true,
-    )
-}
-
-pub fn run_submodule(py: Python) -> PyResult<&PyModule> {
+    let parameters = parameters_from_py(default_parameters(), parameters)?;
+    let (all_cell_processes, all_global_processes) =
+        example_processes(py, cell_processes, global_processes)?;
+    py.allow_threads(move || {
+        run_iteration_py_wrap(
+            cell_data,
+            global_state,
+            all_cell_processes,
+            all_global_processes,
+            &parameters,
+            true,
+        )
+    })
+}
+
+/// Run a number of iterations and return the final state
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// The GIL is released while the iterations run.
+/// Cancelling the token stops the run after the current iteration and the state
+/// at that iteration is returned with the number of iterations run.
+/// Ctrl-C cancels the token and raises KeyboardInterrupt after the current iteration.
+#[allow(clippy::too_many_arguments)]
+#[pyfunction(
+    parameters = "None",
+    cell_processes = "None",
+    global_processes = "None",
+    token = "None"
+)]
+pub fn run_iterations_py(
+    py: Python,
+    cell_data: Vec<CellStatePy>,
+    global_state: GlobalStatePy,
+    iterations: u32,
+    parameters: Option<HashMap<String, ParameterValue>>,
+    cell_processes: Option<Vec<PyObject>>,
+    global_processes: Option<Vec<PyObject>>,
+    token: Option<CancellationTokenPy>,
+) -> PyResult<IterationsPy> {
+    let parameters = parameters_from_py(default_parameters(), parameters)?;
+    let python_processes = has_python_processes(py, cell_processes.as_deref().unwrap_or_default())
+        || has_python_processes(py, global_processes.as_deref().unwrap_or_default());
+    let (all_cell_processes, all_global_processes) =
+        example_processes(py, cell_processes, global_processes)?;
+    let cells: Vec<CellState> = cell_data.iter().map(|c| c.get_inner()).collect();
+    let state = IterationState {
+        global_state: global_state.get_inner(),
+        network: get_network_map(&cells),
+        cells,
+    };
+    let mut simulation =
+        Simulation::new(state, all_cell_processes, all_global_processes, parameters);
+    let token = token.unwrap_or_default().inner;
+    let completed = run_simulation_py_wrap(
+        py,
+        &mut simulation,
+        iterations,
+        None,
+        &token,
+        python_processes,
+    )?;
+    let state = simulation.state;
+    Ok((
+        state.cells.iter().map(CellStatePy::from_inner).collect(),
+        GlobalStatePy::from_inner(&state.global_state),
+        state
+            .network
+            .iter()
+            .map(|c| c.iter().map(|ci: &CellIndex| u32::from(*ci)).collect())
+            .collect(),
+        completed,
+    ))
+}
+
+/// The python facing setup_initial_state wrapper function
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// Setup processes are registered process names or python callables as for run_iteration.
+/// No setup processes run unless they are given.
+/// If randomize is true each cell is randomized before the setup processes run.
+/// Set a seed to make the randomization reproducible.
+#[allow(clippy::too_many_arguments)]
+#[pyfunction(
+    global_state = "None",
+    parameters = "None",
+    setup_processes = "None",
+    global_setup_processes = "None",
+    randomize = "None",
+    seed = "None"
+)]
+pub fn setup_initial_state_py(
+    py: Python,
+    cell_data: Vec<CellStatePy>,
+    global_state: Option<GlobalStatePy>,
+    parameters: Option<HashMap<String, ParameterValue>>,
+    setup_processes: Option<Vec<PyObject>>,
+    global_setup_processes: Option<Vec<PyObject>>,
+    randomize: Option<bool>,
+    seed: Option<u64>,
+) -> PyResult<(Vec<CellStatePy>, GlobalStatePy, Vec<Vec<u32>>)> {
+    let parameters = parameters_from_py(default_parameters(), parameters)?;
+    let registry = default_registry();
+    let cell_setup_processes = cell_processes_from_py::<_, _, CellStatePy, GlobalStatePy>(
+        py,
+        &registry,
+        setup_processes.unwrap_or_default(),
+    )?;
+    let global_setup_processes = global_processes_from_py::<_, _, CellStatePy, GlobalStatePy>(
+        py,
+        &registry,
+        global_setup_processes.unwrap_or_default(),
+    )?;
+    let setup = || {
+        setup_initial_state_py_wrap(
+            Some(cell_setup_processes),
+            Some(global_setup_processes),
+            &parameters,
+            cell_data,
+            global_state,
+            randomize,
+        )
+    };
+    match seed {
+        Some(seed) => random::with_seed(seed, setup),
+        None => setup(),
+    }
+}
+
+/// Get the ids of the neighbours of each cell in cell order
+#[pyfunction]
+pub fn get_network_map_py(cell_data: Vec<CellStatePy>) -> Vec<Vec<u32>> {
+    let cells: Vec<CellState> = cell_data.iter().map(|c| c.get_inner()).collect();
+    get_network_map(&cells)
+        .iter()
+        .map(|c| c.iter().map(|ci: &CellIndex| u32::from(*ci)).collect())
+        .collect()
+}
+
+/// Check if two cells are neighbours
+#[pyfunction]
+pub fn check_is_neighbour_py(cell_a: CellStatePy, cell_b: CellStatePy) -> bool {
+    check_is_neighbour(&cell_a.inner, &cell_b.inner)
+}
+
+/// Load cells from a GeoJSON FeatureCollection string
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// Each feature becomes a cell at the centroid of its geometry.
+/// fields maps feature property names to the cell fields they set.
+/// Returns the cells and the label property of each cell.
+#[pyfunction(label_property = "None", fields = "None")]
+pub fn load_geojson_py(
+    geojson: &str,
+    label_property: Option<&str>,
+    fields: Option<HashMap<String, String>>,
+) -> PyResult<(Vec<CellStatePy>, Vec<Option<String>>)> {
+    let mut loader = GeoJsonLoader::new();
+    if let Some(property) = label_property {
+        loader = loader.with_label_property(property);
+    }
+    for (property, field) in fields.unwrap_or_default().iter() {
+        loader = loader.with_field(property, field);
+    }
+    let loaded: GeoJsonCells<CellState> = loader
+        .load_str(geojson)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok((
+        loaded.cells.iter().map(CellStatePy::from_inner).collect(),
+        loaded.labels,
+    ))
+}
+
+/// Write cells and the global state as a GeoJSON FeatureCollection string
+///
+/// EXAMPLE IMPLEMENTATION
+#[pyfunction(global_state = "None")]
+pub fn to_geojson_py(
+    cell_data: Vec<CellStatePy>,
+    global_state: Option<GlobalStatePy>,
+) -> PyResult<String> {
+    let state = IterationState {
+        global_state: global_state.unwrap_or_default().get_inner(),
+        network: vec![],
+        cells: cell_data.iter().map(|c| c.get_inner()).collect(),
+    };
+    state_to_geojson(&state, None).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Load cells from a CSV table string with id, x and y columns
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// fields maps column names to the cell fields they set.
+/// Cell ids must match the row order so the network can be built from the cells.
+#[pyfunction(fields = "None")]
+pub fn load_csv_py(
+    csv: &str,
+    fields: Option<HashMap<String, String>>,
+) -> PyResult<Vec<CellStatePy>> {
+    let mut reader = CsvCellReader::new();
+    for (column, field) in fields.unwrap_or_default().iter() {
+        reader = reader.with_field(column, field);
+    }
+    let cells: Vec<CellState> = reader
+        .read_str(csv)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(cells.iter().map(CellStatePy::from_inner).collect())
+}
+
+/// Write cells as a CSV table string with a row per cell
+///
+/// EXAMPLE IMPLEMENTATION
+#[pyfunction]
+pub fn to_csv_py(cell_data: Vec<CellStatePy>) -> PyResult<String> {
+    let cells: Vec<CellState> = cell_data.iter().map(|c| c.get_inner()).collect();
+    cells_to_csv(&cells).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Load lattice cells from ESRI ASCII grid strings
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// grids maps cell fields to the grid that sets them. Grid cells with no data
+/// in any grid are masked and have no cell.
+#[pyfunction]
+pub fn load_ascii_grids_py(grids: HashMap<String, String>) -> PyResult<Vec<CellStatePy>> {
+    let mut loader = AsciiGridLoader::new();
+    for (field, grid) in grids.iter() {
+        let grid = grid
+            .parse::<AsciiGrid>()
+            .map_err(|e| PyValueError::new_err(format!("{}: {}", field, e)))?;
+        loader = loader.with_field(grid, field);
+    }
+    let loaded: GridCells<CellState> = loader
+        .load()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(loaded.cells.iter().map(CellStatePy::from_inner).collect())
+}
+
+/// Write a numeric cell field as an ESRI ASCII grid string
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// The extent and georeferencing are taken from the template grid, e.g. one of
+/// the grids the cells were loaded from.
+#[pyfunction]
+pub fn to_ascii_grid_py(
+    cell_data: Vec<CellStatePy>,
+    field: &str,
+    template: &str,
+) -> PyResult<String> {
+    let template = template
+        .parse::<AsciiGrid>()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let cells: Vec<CellState> = cell_data.iter().map(|c| c.get_inner()).collect();
+    cells_to_ascii_grid(&cells, &template.header, field)
+        .map(|grid| grid.to_string())
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Draw a numeric cell field as an SVG string
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// colour_map is "viridis" (the default) or "greys". If a network is given a
+/// line is drawn between each cell and its neighbours.
+#[pyfunction(network = "None", colour_map = "None", width = "800.0")]
+pub fn render_svg_py(
+    cell_data: Vec<CellStatePy>,
+    field: &str,
+    network: Option<Vec<Vec<u32>>>,
+    colour_map: Option<&str>,
+    width: f64,
+) -> PyResult<String> {
+    let colour_map = match colour_map.unwrap_or("viridis") {
+        "viridis" => ColourMap::Viridis,
+        "greys" => ColourMap::Greys,
+        other => {
+            return Err(PyValueError::new_err(format!(
+                "Unknown colour map: {}",
+                other
+            )))
+        }
+    };
+    let cells: Vec<CellState> = cell_data.iter().map(|c| c.get_inner()).collect();
+    let network: Option<Vec<Vec<CellIndex>>> = network.map(|network| {
+        network
+            .into_iter()
+            .map(|n| n.into_iter().map(CellIndex).collect())
+            .collect()
+    });
+    let mut renderer = SvgRenderer::new()
+        .with_colour_scale(ColourScale::new(colour_map))
+        .with_width(width);
+    if let Some(network) = network.as_deref() {
+        renderer = renderer.with_network(network, [128, 128, 128]);
+    }
+    renderer
+        .render(&cells, field)
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn field_descriptor_to_py(py: Python, field: &FieldDescriptor) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("name", &field.name)?;
+    dict.set_item("label", &field.label)?;
+    dict.set_item("type", field.kind.type_name())?;
+    dict.set_item("unit", &field.unit)?;
+    dict.set_item("range", field.range)?;
+    if let FieldKind::Category(categories) = &field.kind {
+        dict.set_item("categories", categories)?;
+    }
+    Ok(dict.into())
+}
+
+/// Describe the fields of the example cell and global states
+///
+/// EXAMPLE IMPLEMENTATION
+///
+/// Returns a dict with "cell" and "global" lists of dicts with the name, label,
+/// type, unit and range of each field.
+#[pyfunction]
+pub fn describe_fields(py: Python) -> PyResult<PyObject> {
+    let describe = |fields: Vec<FieldDescriptor>| -> PyResult<Vec<PyObject>> {
+        fields
+            .iter()
+            .map(|field| field_descriptor_to_py(py, field))
+            .collect()
+    };
+    let dict = PyDict::new(py);
+    dict.set_item("cell", describe(CellState::default().describe_fields())?)?;
+    dict.set_item(
+        "global",
+        describe(GlobalState::default().describe_fields())?,
+    )?;
+    Ok(dict.into())
+}
+
+/// Get the example model processes
+///
+/// Each list item is the name of a process in the example registry or a python callable.
+/// Processes run in the order of the lists. If no cell processes are given the
+/// default cell processes are used.
+pub fn example_processes(
+    py: Python,
+    cell_processes: Option<Vec<PyObject>>,
+    global_processes: Option<Vec<PyObject>>,
+) -> PyResult<(Vec<CellProcessT>, Vec<GlobalProcessT>)> {
+    let registry = default_registry();
+    let cell_processes = match cell_processes {
+        Some(items) => {
+            cell_processes_from_py::<_, _, CellStatePy, GlobalStatePy>(py, &registry, items)?
+        }
+        None => registry
+            .cell_processes(&DEFAULT_CELL_PROCESS_NAMES)
+            .expect("Default processes are registered"),
+    };
+    let global_processes = global_processes_from_py::<_, _, CellStatePy, GlobalStatePy>(
+        py,
+        &registry,
+        global_processes.unwrap_or_default(),
+    )?;
+    Ok((cell_processes, global_processes))
+}
+
+/// List the processes in the example registry
+///
+/// Each process is a dict with the keys id, name, kind, description and parameters.
+#[pyfunction]
+pub fn list_processes(py: Python) -> PyResult<Vec<PyObject>> {
+    default_registry()
+        .processes()
+        .iter()
+        .map(|info| process_info_to_py(py, info))
+        .collect()
+}
+
+/// Get the model parameter specs so front-ends can build controls
+#[pyfunction]
+pub fn get_parameter_specs(py: Python) -> PyResult<Vec<PyObject>> {
+    default_parameters()
+        .specs()
+        .iter()
+        .map(|spec| parameter_spec_to_py(py, spec))
+        .collect()
+}
+
+pub fn run_submodule(py: Python<'_>) -> PyResult<&PyModule> {
     let submod = PyModule::new(py, "run")?;
     submod.add("run_iteration", wrap_pyfunction!(run_iteration_py, submod)?)?;
+    submod.add(
+        "run_iterations",
+        wrap_pyfunction!(run_iterations_py, submod)?,
+    )?;
+    submod.add(
+        "setup_initial_state",
+        wrap_pyfunction!(setup_initial_state_py, submod)?,
+    )?;
+    submod.add(
+        "get_network_map",
+        wrap_pyfunction!(get_network_map_py, submod)?,
+    )?;
+    submod.add(
+        "check_is_neighbour",
+        wrap_pyfunction!(check_is_neighbour_py, submod)?,
+    )?;
+    submod.add("load_geojson", wrap_pyfunction!(load_geojson_py, submod)?)?;
+    submod.add("to_geojson", wrap_pyfunction!(to_geojson_py, submod)?)?;
+    submod.add("load_csv", wrap_pyfunction!(load_csv_py, submod)?)?;
+    submod.add("to_csv", wrap_pyfunction!(to_csv_py, submod)?)?;
+    submod.add(
+        "load_ascii_grids",
+        wrap_pyfunction!(load_ascii_grids_py, submod)?,
+    )?;
+    submod.add("to_ascii_grid", wrap_pyfunction!(to_ascii_grid_py, submod)?)?;
+    submod.add("render_svg", wrap_pyfunction!(render_svg_py, submod)?)?;
+    submod.add("list_processes", wrap_pyfunction!(list_processes, submod)?)?;
+    submod.add(
+        "describe_fields",
+        wrap_pyfunction!(describe_fields, submod)?,
+    )?;
+    submod.add(
+        "get_parameter_specs",
+        wrap_pyfunction!(get_parameter_specs, submod)?,
+    )?;
     Ok(submod)
 }
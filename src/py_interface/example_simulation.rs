@@ -0,0 +1,346 @@
+/* Simulation Interface
+
+A python simulation object that keeps the model state in rust between steps.
+
+Unlike `run.run_iteration` the cells are only converted to python objects when
+they are read, so a python loop calling `step` does not pay the conversion cost
+on each iteration.
+*/
+extern crate pyo3;
+use std::collections::HashMap;
+
+use super::cancel::CancellationTokenPy;
+use super::example_recorder::RecorderPy;
+use super::example_run::example_processes;
+use super::parameters::parameters_from_py;
+use super::parameters::parameters_to_py;
+use super::pickle::state_from_bytes;
+use super::pickle::state_to_bytes;
+use super::py_process::has_python_processes;
+use super::run::run_simulation_py_wrap;
+use crate::io::geojson::state_to_geojson;
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::examples::example_processes::default_parameters;
+use crate::process_runner::examples::example_state::CellState;
+use crate::process_runner::examples::example_state::GlobalState;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::network::get_network_map;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::recorder::numeric_field_reader;
+use crate::process_runner::simulation::Simulation;
+use crate::process_runner::simulation::SimulationSnapshot;
+use crate::process_runner::state::IterationState;
+use crate::py_interface::examples::CellStatePy;
+use crate::py_interface::examples::GlobalStatePy;
+use crate::py_interface::PyWrapperBase;
+use numpy::IntoPyArray;
+use numpy::PyArray1;
+use numpy::PyArray2;
+use numpy::PyReadonlyArray1;
+use pyo3::exceptions::PyIndexError;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// The process names or python callables a simulation was created with
+type ProcessesPy = Option<Vec<PyObject>>;
+
+/// Check that the example cell has a numeric field with the name
+pub fn check_numeric_cell_field(name: &str) -> PyResult<()> {
+    match CellState::default().numeric_field_value(name) {
+        Some(_) => Ok(()),
+        None => Err(PyValueError::new_err(format!(
+            "Invalid cell field: {}",
+            name
+        ))),
+    }
+}
+
+/// Python wrapper for a simulation of the example model
+///
+/// Processes are chosen as for run_iteration by registered name or python callable.
+/// The chosen process lists are kept so that a pickled simulation can rebuild them.
+#[pyclass(module = "cellular_automata")]
+pub struct SimulationPy {
+    pub inner: Simulation<CellState, GlobalState>,
+    cell_processes_py: Option<Vec<PyObject>>,
+    global_processes_py: Option<Vec<PyObject>>,
+}
+
+impl SimulationPy {
+    /// Create a simulation with the chosen processes from a snapshot
+    fn from_snapshot(
+        py: Python,
+        snapshot: SimulationSnapshot<CellState, GlobalState>,
+        cell_processes_py: Option<Vec<PyObject>>,
+        global_processes_py: Option<Vec<PyObject>>,
+    ) -> PyResult<Self> {
+        let (cell_processes, global_processes) =
+            example_processes(py, cell_processes_py.clone(), global_processes_py.clone())?;
+        let mut inner = Simulation::new(
+            snapshot.state.clone(),
+            cell_processes,
+            global_processes,
+            snapshot.parameters.clone(),
+        );
+        inner.restore(snapshot);
+        Ok(SimulationPy {
+            inner,
+            cell_processes_py,
+            global_processes_py,
+        })
+    }
+
+    /// Check if any of the chosen processes is a python callable
+    fn has_python_processes(&self, py: Python) -> bool {
+        [&self.cell_processes_py, &self.global_processes_py]
+            .iter()
+            .any(|items| has_python_processes(py, items.as_deref().unwrap_or_default()))
+    }
+}
+
+#[pymethods]
+impl SimulationPy {
+    #[new]
+    #[args(
+        global_state = "None",
+        parameters = "None",
+        cell_processes = "None",
+        global_processes = "None"
+    )]
+    pub fn new(
+        py: Python,
+        cell_data: Vec<CellStatePy>,
+        global_state: Option<GlobalStatePy>,
+        parameters: Option<HashMap<String, ParameterValue>>,
+        cell_processes: Option<Vec<PyObject>>,
+        global_processes: Option<Vec<PyObject>>,
+    ) -> PyResult<Self> {
+        let parameters = parameters_from_py(default_parameters(), parameters)?;
+        let cells: Vec<CellState> = cell_data.iter().map(|c| c.get_inner()).collect();
+        let state = IterationState {
+            global_state: global_state.unwrap_or_default().get_inner(),
+            network: get_network_map(&cells),
+            cells,
+        };
+        SimulationPy::from_snapshot(
+            py,
+            SimulationSnapshot {
+                state,
+                parameters,
+                update_per_process: true,
+                iteration: 0,
+            },
+            cell_processes,
+            global_processes,
+        )
+    }
+
+    /// Get the encoded simulation state and the chosen processes
+    ///
+    /// Python processes are pickled by python so they must be picklable,
+    /// e.g. module level functions.
+    pub fn __getstate__(&self, py: Python) -> PyResult<(PyObject, ProcessesPy, ProcessesPy)> {
+        Ok((
+            state_to_bytes(py, &self.inner.snapshot())?,
+            self.cell_processes_py.clone(),
+            self.global_processes_py.clone(),
+        ))
+    }
+
+    pub fn __setstate__(
+        &mut self,
+        py: Python,
+        state: (&PyBytes, ProcessesPy, ProcessesPy),
+    ) -> PyResult<()> {
+        let (bytes, cell_processes_py, global_processes_py) = state;
+        let snapshot = state_from_bytes(bytes.as_bytes())?;
+        *self = SimulationPy::from_snapshot(py, snapshot, cell_processes_py, global_processes_py)?;
+        Ok(())
+    }
+
+    /// Placeholder constructor arguments used by pickle before the state is set
+    pub fn __getnewargs__(&self) -> (Vec<CellStatePy>,) {
+        (vec![],)
+    }
+
+    pub fn __copy__(&self, py: Python) -> PyResult<Self> {
+        SimulationPy::from_snapshot(
+            py,
+            self.inner.snapshot(),
+            self.cell_processes_py.clone(),
+            self.global_processes_py.clone(),
+        )
+    }
+
+    /// Copy the state; python processes are shared with the copy
+    pub fn __deepcopy__(&self, py: Python, _memo: &PyAny) -> PyResult<Self> {
+        self.__copy__(py)
+    }
+
+    /// Run a number of iterations and return the number of iterations run
+    ///
+    /// If a recorder is given it records every iteration.
+    /// The GIL is released while iterations run. Cancelling the token stops the run
+    /// after the current iteration and keeps the state at that iteration.
+    /// Ctrl-C also cancels the token and raises KeyboardInterrupt.
+    /// An exception raised by a python process is raised here and the state is
+    /// kept at the last completed iteration.
+    #[args(iterations = "1", recorder = "None", token = "None")]
+    pub fn step(
+        &mut self,
+        py: Python,
+        iterations: u32,
+        recorder: Option<&PyCell<RecorderPy>>,
+        token: Option<CancellationTokenPy>,
+    ) -> PyResult<u32> {
+        let token = token.unwrap_or_default().inner;
+        let python_processes = self.has_python_processes(py);
+        match recorder {
+            Some(recorder) => {
+                let mut recorder = recorder.borrow_mut();
+                run_simulation_py_wrap(
+                    py,
+                    &mut self.inner,
+                    iterations,
+                    Some(&mut recorder.inner),
+                    &token,
+                    python_processes,
+                )
+            }
+            None => run_simulation_py_wrap(
+                py,
+                &mut self.inner,
+                iterations,
+                None,
+                &token,
+                python_processes,
+            ),
+        }
+    }
+
+    #[getter]
+    pub fn iteration(&self) -> u32 {
+        self.inner.iteration()
+    }
+
+    /// A copy of the cells
+    #[getter]
+    pub fn cells(&self) -> Vec<CellStatePy> {
+        self.inner
+            .state
+            .cells
+            .iter()
+            .map(CellStatePy::from_inner)
+            .collect()
+    }
+
+    #[getter]
+    pub fn global_state(&self) -> GlobalStatePy {
+        GlobalStatePy::from_inner(&self.inner.state.global_state)
+    }
+
+    #[getter]
+    pub fn network(&self) -> Vec<Vec<u32>> {
+        self.inner
+            .state
+            .network
+            .iter()
+            .map(|c| c.iter().map(|ci: &CellIndex| u32::from(*ci)).collect())
+            .collect()
+    }
+
+    /// A copy of the cells, global state and network in the same form as run_iteration
+    #[getter]
+    pub fn state(&self) -> (Vec<CellStatePy>, GlobalStatePy, Vec<Vec<u32>>) {
+        (self.cells(), self.global_state(), self.network())
+    }
+
+    #[getter]
+    pub fn parameters(&self, py: Python) -> PyResult<PyObject> {
+        parameters_to_py(py, &self.inner.parameters)
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: ParameterValue) -> PyResult<()> {
+        self.inner
+            .parameters
+            .set(name, value)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Get a single cell by index
+    pub fn cell(&self, index: usize) -> PyResult<CellStatePy> {
+        self.inner
+            .state
+            .cells
+            .get(index)
+            .map(CellStatePy::from_inner)
+            .ok_or_else(|| PyIndexError::new_err(format!("Invalid cell index: {}", index)))
+    }
+
+    /// Get a numpy array of a numeric cell field in cell order
+    ///
+    /// Cells are stored as structs so the field is copied once into a new array.
+    /// Use positions for the cell positions as they are not fields.
+    pub fn field<'py>(&self, py: Python<'py>, name: &str) -> PyResult<&'py PyArray1<f64>> {
+        check_numeric_cell_field(name)?;
+        let field = numeric_field_reader::<CellState>(name);
+        let values: Vec<f64> = self.inner.state.cells.iter().map(field).collect();
+        Ok(values.into_pyarray(py))
+    }
+
+    /// Set a numeric cell field from an array with a value for each cell
+    ///
+    /// Values are converted to the field type as for set_numeric_field_value.
+    pub fn set_field(&mut self, name: &str, values: PyReadonlyArray1<f64>) -> PyResult<()> {
+        let values = values.as_array();
+        let cells = &mut self.inner.state.cells;
+        if values.len() != cells.len() {
+            return Err(PyValueError::new_err(format!(
+                "Expected {} values but got {}",
+                cells.len(),
+                values.len()
+            )));
+        }
+        check_numeric_cell_field(name)?;
+        for (cell, value) in cells.iter_mut().zip(values.iter()) {
+            cell.set_numeric_field_value(name, *value)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Get the cell positions as an array with a row of x, y for each cell
+    #[getter]
+    pub fn positions<'py>(&self, py: Python<'py>) -> &'py PyArray2<f64> {
+        let values: Vec<f64> = self
+            .inner
+            .state
+            .cells
+            .iter()
+            .flat_map(|c| vec![c.position.x(), c.position.y()])
+            .collect();
+        let rows = self.inner.state.cells.len();
+        values
+            .into_pyarray(py)
+            .reshape([rows, 2])
+            .expect("positions have two columns")
+    }
+
+    /// Get the current state as a GeoJSON FeatureCollection string
+    ///
+    /// Each cell is a point feature with its fields as properties and the
+    /// global state is in the global_state member of the collection.
+    pub fn to_geojson(&self) -> PyResult<String> {
+        state_to_geojson(&self.inner.state, None).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Get the value of a numeric global state field
+    pub fn global_field(&self, name: &str) -> PyResult<f64> {
+        self.inner
+            .state
+            .global_state
+            .numeric_field_value(name)
+            .ok_or_else(|| PyValueError::new_err(format!("Invalid global field: {}", name)))
+    }
+}
@@ -4,13 +4,22 @@ use crate::process_runner::examples::example_state::CellState;
 use crate::process_runner::examples::example_state::GlobalState;
 use crate::py_interface::cell_state::CellStatePyBase;
 use crate::py_interface::global_state::GlobalStatePyBase;
+use crate::py_interface::hash::HashField;
+use crate::py_interface::pickle::state_from_bytes;
+use crate::py_interface::pickle::state_to_bytes;
 use crate::py_interface::PyWrapperBase;
 use geo::point;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
 
+use pyo3::basic::CompareOp;
+use pyo3::exceptions::PyTypeError;
 use pyo3::prelude::*;
+use pyo3::types::PyBytes;
 use pyo3::PyObjectProtocol;
 
-#[pyclass]
+#[pyclass(module = "cellular_automata")]
 #[derive(Clone)]
 pub struct CellStatePy {
     pub inner: CellState,
@@ -29,6 +38,68 @@ impl CellStatePy {
             },
         }
     }
+
+    #[getter]
+    pub fn id(&self) -> u32 {
+        self.inner.id.into()
+    }
+
+    #[setter]
+    pub fn set_id(&mut self, id: u32) {
+        self.inner.id = CellIndex(id);
+    }
+
+    #[getter]
+    pub fn position(&self) -> (f64, f64) {
+        (self.inner.position.x(), self.inner.position.y())
+    }
+
+    #[setter]
+    pub fn set_position(&mut self, pos: (f64, f64)) {
+        self.inner.position = point!(x: pos.0, y: pos.1);
+    }
+
+    #[getter]
+    pub fn population(&self) -> u32 {
+        self.inner.population
+    }
+
+    #[setter]
+    pub fn set_population(&mut self, population: u32) {
+        self.inner.population = population;
+    }
+
+    #[getter]
+    pub fn peep_ids(&self) -> Vec<u32> {
+        self.inner.peep_ids.clone()
+    }
+
+    #[setter]
+    pub fn set_peep_ids(&mut self, peep_ids: Vec<u32>) {
+        self.inner.peep_ids = peep_ids;
+    }
+
+    pub fn __getstate__(&self, py: Python) -> PyResult<PyObject> {
+        state_to_bytes(py, &self.inner)
+    }
+
+    pub fn __setstate__(&mut self, state: &PyBytes) -> PyResult<()> {
+        self.inner = state_from_bytes(state.as_bytes())?;
+        Ok(())
+    }
+
+    /// Placeholder constructor arguments used by pickle before the state is set
+    pub fn __getnewargs__(&self) -> (u32, (f64, f64), u32) {
+        (0, (0.0, 0.0), 0)
+    }
+
+    pub fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        self.clone()
+    }
 }
 impl CellStatePyBase<CellState> for CellStatePy {}
 // Note: This now includes cloning so we could in theory end up with duplicate clones
@@ -44,32 +115,56 @@ impl PyWrapperBase<CellState> for CellStatePy {
     }
 }
 
+/// Hash of the current field values
+///
+/// Positions are hashed with `HashField` so that equal cells hash equally.
+fn hash_cell_state(cell: &CellState) -> isize {
+    let mut hasher = DefaultHasher::new();
+    cell.id.hash_field(&mut hasher);
+    cell.position.hash_field(&mut hasher);
+    cell.population.hash_field(&mut hasher);
+    cell.peep_ids.hash_field(&mut hasher);
+    hasher.finish() as isize
+}
+
+/// Compare wrapped values for equality
+///
+/// Python only allows == and != on the state wrappers.
+fn compare_eq<T: PartialEq>(a: &T, b: &T, op: CompareOp) -> PyResult<bool> {
+    match op {
+        CompareOp::Eq => Ok(a == b),
+        CompareOp::Ne => Ok(a != b),
+        _ => Err(PyTypeError::new_err(
+            "States can only be compared for equality",
+        )),
+    }
+}
+
 #[pyproto]
 impl PyObjectProtocol for CellStatePy {
-    fn __str__(&self) -> PyResult<&'static str> {
-        Ok("CellStatePy")
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "CellStatePy(id={}, position=({:?}, {:?}), population={}, peep_ids={:?})",
+            self.inner.id,
+            self.inner.position.x(),
+            self.inner.position.y(),
+            self.inner.population,
+            self.inner.peep_ids
+        ))
     }
 
-    fn __repr__<'a>(&'a self) -> PyResult<String> {
-        Ok(format!("CellStateObj id: {:?}", self.inner.id))
+    fn __richcmp__(&self, other: CellStatePy, op: CompareOp) -> PyResult<bool> {
+        compare_eq(&self.inner, &other.inner, op)
     }
 
-    fn __getattr__(&'a self, name: &str) -> PyResult<String> {
-        // TODO: Work out how to return value other than string here
-        let out: String = match name {
-            "id" => self.inner.id.into(),
-            "position" => format!("{},{}", self.inner.position.x(), self.inner.position.y()),
-            "population" => format!("{}", self.inner.population),
-            // TODO: Should return missing attribute error here
-            &_ => "INVALID FIELD".to_owned(),
-        };
-        Ok(out)
+    fn __hash__(&self) -> PyResult<isize> {
+        Ok(hash_cell_state(&self.inner))
     }
 }
 
 // Global State
 
-#[pyclass]
+#[pyclass(module = "cellular_automata")]
 #[derive(Clone, Default)]
 pub struct GlobalStatePy {
     pub inner: GlobalState,
@@ -86,6 +181,48 @@ impl GlobalStatePy {
             },
         }
     }
+
+    #[getter]
+    pub fn iterations(&self) -> u32 {
+        self.inner.iterations
+    }
+
+    #[setter]
+    pub fn set_iterations(&mut self, iterations: u32) {
+        self.inner.iterations = iterations;
+    }
+
+    #[getter]
+    pub fn population(&self) -> u32 {
+        self.inner.population
+    }
+
+    #[setter]
+    pub fn set_population(&mut self, population: u32) {
+        self.inner.population = population;
+    }
+
+    pub fn __getstate__(&self, py: Python) -> PyResult<PyObject> {
+        state_to_bytes(py, &self.inner)
+    }
+
+    pub fn __setstate__(&mut self, state: &PyBytes) -> PyResult<()> {
+        self.inner = state_from_bytes(state.as_bytes())?;
+        Ok(())
+    }
+
+    /// Placeholder constructor arguments used by pickle before the state is set
+    pub fn __getnewargs__(&self) -> (u32,) {
+        (0,)
+    }
+
+    pub fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        self.clone()
+    }
 }
 
 impl GlobalStatePy {
@@ -96,21 +233,21 @@ impl GlobalStatePy {
 
 #[pyproto]
 impl PyObjectProtocol for GlobalStatePy {
-    fn __str__(&self) -> PyResult<&'static str> {
-        Ok("GlobalState")
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "GlobalStatePy(iterations={}, population={})",
+            self.inner.iterations, self.inner.population
+        ))
     }
 
-    fn __repr__<'a>(&'a self) -> PyResult<String> {
-        Ok("GlobalStateObj".to_owned())
+    fn __richcmp__(&self, other: GlobalStatePy, op: CompareOp) -> PyResult<bool> {
+        compare_eq(&self.inner, &other.inner, op)
     }
 
-    fn __getattr__(&'a self, name: &str) -> PyResult<String> {
-        let out: String = match name {
-            "iterations" => format!("{}", self.inner.iterations),
-            // TODO: Should return missing attribute error here
-            &_ => "INVALID".to_owned(),
-        };
-        Ok(out)
+    fn __hash__(&self) -> PyResult<isize> {
+        let mut hasher = DefaultHasher::new();
+        self.inner.hash(&mut hasher);
+        Ok(hasher.finish() as isize)
     }
 }
 
@@ -0,0 +1,68 @@
+/* GUI Interface
+
+Conversions from a rust GUI model description to the dicts used by the web GUI.
+
+Layers are `{uid, label}`, cell actions are `{uid, label, type, group}` and
+controls are `{uid, label, controlType, type, min, max}`.
+*/
+extern crate pyo3;
+use std::collections::HashMap;
+
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::gui::CellAction;
+use crate::process_runner::gui::Control;
+use crate::process_runner::gui::Layer;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+pub fn layer_to_py<C: CellStateBase>(py: Python, layer: &Layer<C>) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("uid", &layer.uid)?;
+    dict.set_item("label", &layer.label)?;
+    Ok(dict.into())
+}
+
+pub fn cell_action_to_py<C: CellStateBase>(
+    py: Python,
+    action: &CellAction<C>,
+) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("uid", &action.uid)?;
+    dict.set_item("label", &action.label)?;
+    dict.set_item("type", action.action_type.to_string())?;
+    dict.set_item("group", &action.group)?;
+    Ok(dict.into())
+}
+
+pub fn control_to_py(py: Python, control: &Control) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("uid", &control.uid)?;
+    dict.set_item("label", &control.label)?;
+    dict.set_item("controlType", control.control_type.to_string())?;
+    dict.set_item("type", &control.value_type)?;
+    dict.set_item("min", control.min)?;
+    dict.set_item("max", control.max)?;
+    Ok(dict.into())
+}
+
+/// Build the GUI global state dict
+///
+/// The dict has the layer totals history, the network map and the model
+/// global state.
+pub fn gui_global_state_to_py<GW: IntoPy<PyObject>>(
+    py: Python,
+    totals: HashMap<String, Vec<f64>>,
+    network: &[Vec<CellIndex>],
+    global_state: GW,
+) -> PyResult<PyObject> {
+    let network_map: Vec<Vec<u32>> = network
+        .iter()
+        .map(|c| c.iter().map(|ci: &CellIndex| u32::from(*ci)).collect())
+        .collect();
+    let dict = PyDict::new(py);
+    dict.set_item("totals", totals)?;
+    dict.set_item("network_map", network_map)?;
+    dict.set_item("model_state", global_state.into_py(py))?;
+    Ok(dict.into())
+}
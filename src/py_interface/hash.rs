@@ -0,0 +1,94 @@
+/* State Hash
+
+Hash the fields of python state wrappers.
+
+Wrappers compare equal when all of their fields are equal so they hash all of
+their fields. Floats do not implement `Hash` so they are hashed by their bits
+with -0.0 hashed as 0.0, which keeps equal values hashing equally.
+*/
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use crate::process_runner::cells::state::CellIndex;
+use geo::Point;
+
+/// A field that can be hashed by a state wrapper
+pub trait HashField {
+    fn hash_field<H: Hasher>(&self, state: &mut H);
+}
+
+macro_rules! hash_field_with_hash {
+    ($($t:ty),*) => {$(
+        impl HashField for $t {
+            fn hash_field<H: Hasher>(&self, state: &mut H) {
+                self.hash(state);
+            }
+        }
+    )*};
+}
+
+hash_field_with_hash!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, bool, char, String, CellIndex
+);
+
+impl HashField for f64 {
+    fn hash_field<H: Hasher>(&self, state: &mut H) {
+        (self + 0.0).to_bits().hash(state);
+    }
+}
+
+impl HashField for f32 {
+    fn hash_field<H: Hasher>(&self, state: &mut H) {
+        (self + 0.0).to_bits().hash(state);
+    }
+}
+
+impl HashField for Point<f64> {
+    fn hash_field<H: Hasher>(&self, state: &mut H) {
+        self.x().hash_field(state);
+        self.y().hash_field(state);
+    }
+}
+
+impl<T: HashField> HashField for Vec<T> {
+    fn hash_field<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for value in self.iter() {
+            value.hash_field(state);
+        }
+    }
+}
+
+impl<T: HashField> HashField for Option<T> {
+    fn hash_field<H: Hasher>(&self, state: &mut H) {
+        self.is_some().hash(state);
+        if let Some(value) = self {
+            value.hash_field(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::point;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash_of<T: HashField>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash_field(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn should_hash_equal_floats_equally() {
+        assert_eq!(hash_of(&0.0_f64), hash_of(&-0.0_f64));
+        assert_eq!(
+            hash_of(&point!(x: -0.0, y: 1.5)),
+            hash_of(&point!(x: 0.0, y: 1.5))
+        );
+        assert_ne!(hash_of(&1.0_f64), hash_of(&2.0_f64));
+        assert_ne!(hash_of(&vec![1_u32]), hash_of(&vec![1_u32, 1]));
+        assert_ne!(hash_of(&Some(0_u32)), hash_of(&None::<u32>));
+    }
+}
@@ -4,10 +4,19 @@ Each struct requires a wrapper that is readable by python.
 For the wrapper we implement various methods that enable read and coonstruct
 access from python.
 */
+pub mod cancel;
 pub mod cell_state;
+pub mod example_gui;
+pub mod example_recorder;
 pub mod example_run;
+pub mod example_simulation;
 pub mod examples;
 pub mod global_state;
+pub mod gui;
+pub mod hash;
+pub mod parameters;
+pub mod pickle;
+pub mod py_process;
 pub mod run;
 
 /// Generic Type wrapper base
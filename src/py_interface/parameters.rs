@@ -0,0 +1,78 @@
+/* Parameters Interface
+
+Conversions between python values and model parameters.
+*/
+extern crate pyo3;
+use std::collections::HashMap;
+
+use crate::process_runner::parameters::ParameterSpec;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::parameters::Parameters;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBool;
+use pyo3::types::PyDict;
+use pyo3::types::PyLong;
+
+impl<'source> FromPyObject<'source> for ParameterValue {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        // Note: bool must be checked first as python bools are also ints
+        if ob.downcast::<PyBool>().is_ok() {
+            Ok(ParameterValue::Bool(ob.extract()?))
+        } else if ob.downcast::<PyLong>().is_ok() {
+            Ok(ParameterValue::Int(ob.extract()?))
+        } else {
+            Ok(ParameterValue::Float(ob.extract()?))
+        }
+    }
+}
+
+impl IntoPy<PyObject> for ParameterValue {
+    fn into_py(self, py: Python) -> PyObject {
+        match self {
+            ParameterValue::Int(v) => v.into_py(py),
+            ParameterValue::Float(v) => v.into_py(py),
+            ParameterValue::Bool(v) => v.into_py(py),
+        }
+    }
+}
+
+/// Apply the python parameter values to the model parameters
+///
+/// Raises a ValueError if a value is unknown, the wrong type or out of range
+pub fn parameters_from_py(
+    parameters: Parameters,
+    values: Option<HashMap<String, ParameterValue>>,
+) -> PyResult<Parameters> {
+    values
+        .unwrap_or_default()
+        .into_iter()
+        .try_fold(parameters, |parameters, (name, value)| {
+            parameters
+                .with(&name, value)
+                .map_err(|e| PyValueError::new_err(e.to_string()))
+        })
+}
+
+/// Convert a parameter spec to a python dict
+///
+/// The dict has the keys name, label, type, default, min and max
+pub fn parameter_spec_to_py(py: Python, spec: &ParameterSpec) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("name", &spec.name)?;
+    dict.set_item("label", &spec.label)?;
+    dict.set_item("type", spec.default.type_name())?;
+    dict.set_item("default", spec.default.into_py(py))?;
+    dict.set_item("min", spec.min)?;
+    dict.set_item("max", spec.max)?;
+    Ok(dict.into())
+}
+
+/// Convert the parameter values to a python dict of name to value
+pub fn parameters_to_py(py: Python, parameters: &Parameters) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    for (name, value) in parameters.values() {
+        dict.set_item(name, value.into_py(py))?;
+    }
+    Ok(dict.into())
+}
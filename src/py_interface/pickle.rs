@@ -0,0 +1,26 @@
+/* Pickle Interface
+
+Encode the inner rust state of python wrappers as compact bytes.
+
+Wrappers use these in `__getstate__` and `__setstate__` so that they can be
+pickled, copied and sent between processes with standard python tooling.
+*/
+extern crate pyo3;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Encode a value as python bytes
+pub fn state_to_bytes<T: Serialize>(py: Python, value: &T) -> PyResult<PyObject> {
+    let bytes = bincode::serialize(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(PyBytes::new(py, &bytes).into())
+}
+
+/// Decode a value from bytes created by state_to_bytes
+///
+/// Raises a ValueError if the bytes are not a valid encoding of the type.
+pub fn state_from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> PyResult<T> {
+    bincode::deserialize(bytes).map_err(|e| PyValueError::new_err(e.to_string()))
+}
@@ -0,0 +1,291 @@
+/* Python Processes
+
+Build cell and global processes from python callables so that they can be
+mixed with native rust processes in the same run.
+
+A python cell process is called for each cell as
+`process(cell, neighbours, global_state, parameters)` and returns either `None`
+for no update or a new cell state that replaces the cell.
+
+A python global process is called once per iteration as
+`process(cells, global_state, parameters)` and returns either `None` or a new
+global state.
+
+Lists of processes from python can mix the names of processes in a rust
+process registry with python callables.
+
+PERFORMANCE
+Python processes are much slower than native processes.
+For every call the GIL is acquired and the cell, its neighbours and the global
+state are cloned into new python wrapper objects. The parameters are converted
+to a dict. A python cell process therefore costs a few microseconds per cell
+per iteration before any python code runs, and python processes cannot run in
+parallel. Use them for prototyping or infrequent processes and port hot
+processes to rust.
+
+ERRORS
+Processes cannot return errors so an exception raised by a python process is
+kept and the remaining python processes of the run are skipped. The runners
+call `take_process_error` after each iteration and raise the original
+exception in python.
+*/
+extern crate pyo3;
+use pyo3::prelude::*;
+use std::cell::RefCell;
+
+use crate::process_runner::cells::run::CellUpdate;
+use crate::process_runner::cells::run::Process as CellProcess;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::global::run::GlobalUpdate;
+use crate::process_runner::global::run::Process as GlobalProcess;
+use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::registry::ProcessInfo;
+use crate::process_runner::registry::ProcessKind;
+use crate::process_runner::registry::ProcessRegistry;
+use crate::py_interface::cell_state::CellStatePyBase;
+use crate::py_interface::global_state::GlobalStatePyBase;
+use crate::py_interface::parameters::parameter_spec_to_py;
+use crate::py_interface::parameters::parameters_to_py;
+use pyo3::exceptions::PyTypeError;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+
+thread_local! {
+    /// The first exception raised by a python process on this thread
+    ///
+    /// Processes run on the thread that runs the iteration so each run sees
+    /// only its own errors.
+    static PROCESS_ERROR: RefCell<Option<PyErr>> = const { RefCell::new(None) };
+}
+
+/// Keep the first exception raised by a python process
+fn store_process_error(err: PyErr) {
+    PROCESS_ERROR.with(|error| {
+        error.borrow_mut().get_or_insert(err);
+    });
+}
+
+/// Check if a python process has raised an exception that has not been taken
+fn process_error_raised() -> bool {
+    PROCESS_ERROR.with(|error| error.borrow().is_some())
+}
+
+/// Take the exception raised by a python process since the last call
+///
+/// Runners call this after running processes to raise the exception in python.
+pub fn take_process_error() -> PyResult<()> {
+    match PROCESS_ERROR.with(|error| error.borrow_mut().take()) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Create a cell process from a python callable
+pub fn cell_process_from_py<C, G, S, GW>(id: u32, callable: PyObject) -> CellProcess<C, G>
+where
+    C: CellStateBase + 'static,
+    G: GlobalStateBase + 'static,
+    S: CellStatePyBase<C> + IntoPy<PyObject> + for<'a> FromPyObject<'a>,
+    GW: GlobalStatePyBase<G> + IntoPy<PyObject>,
+{
+    CellProcess::new(
+        id,
+        Box::new(
+            move |cell: &C, neighbours: &Vec<&C>, global_state: &G, parameters: &Parameters| {
+                if process_error_raised() {
+                    return (vec![], vec![]);
+                }
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                let result = parameters_to_py(py, parameters).and_then(|parameters_py| {
+                    let neighbours_py: Vec<PyObject> = neighbours
+                        .iter()
+                        .map(|n| S::from_inner(n).into_py(py))
+                        .collect();
+                    callable
+                        .call1(
+                            py,
+                            (
+                                S::from_inner(cell).into_py(py),
+                                neighbours_py,
+                                GW::from_inner(global_state).into_py(py),
+                                parameters_py,
+                            ),
+                        )?
+                        .extract::<Option<S>>(py)
+                });
+                let cell_updates = match result {
+                    Ok(Some(new_cell)) => {
+                        let new_cell = new_cell.get_inner();
+                        vec![CellUpdate::new(
+                            cell.id(),
+                            Box::new(move |_: C| new_cell.clone()),
+                        )]
+                    }
+                    Ok(None) => vec![],
+                    Err(err) => {
+                        store_process_error(err);
+                        vec![]
+                    }
+                };
+                (cell_updates, vec![])
+            },
+        ),
+    )
+}
+
+/// Create a global process from a python callable
+pub fn global_process_from_py<C, G, S, GW>(id: u32, callable: PyObject) -> GlobalProcess<C, G>
+where
+    C: CellStateBase + 'static,
+    G: GlobalStateBase + 'static,
+    S: CellStatePyBase<C> + IntoPy<PyObject>,
+    GW: GlobalStatePyBase<G> + IntoPy<PyObject> + for<'a> FromPyObject<'a>,
+{
+    GlobalProcess::new(
+        id,
+        Box::new(
+            move |cells: &Vec<&C>, global_state: &G, parameters: &Parameters| {
+                if process_error_raised() {
+                    return vec![];
+                }
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                let result = parameters_to_py(py, parameters).and_then(|parameters_py| {
+                    let cells_py: Vec<PyObject> =
+                        cells.iter().map(|c| S::from_inner(c).into_py(py)).collect();
+                    callable
+                        .call1(
+                            py,
+                            (
+                                cells_py,
+                                GW::from_inner(global_state).into_py(py),
+                                parameters_py,
+                            ),
+                        )?
+                        .extract::<Option<GW>>(py)
+                });
+                match result {
+                    Ok(Some(new_global_state)) => {
+                        let new_global_state = new_global_state.get_inner();
+                        vec![GlobalUpdate {
+                            id: format!("Python process {}", id),
+                            action: Box::new(move |_: G| new_global_state.clone()),
+                        }]
+                    }
+                    Ok(None) => vec![],
+                    Err(err) => {
+                        store_process_error(err);
+                        vec![]
+                    }
+                }
+            },
+        ),
+    )
+}
+
+/// A registered process name or a python callable
+enum ProcessItem {
+    Name(String),
+    Callable(PyObject),
+}
+
+fn process_item_from_py(py: Python, item: PyObject) -> PyResult<ProcessItem> {
+    if let Ok(name) = item.extract::<String>(py) {
+        Ok(ProcessItem::Name(name))
+    } else if item.as_ref(py).is_callable() {
+        Ok(ProcessItem::Callable(item))
+    } else {
+        Err(PyTypeError::new_err(
+            "Processes must be registered process names or callables",
+        ))
+    }
+}
+
+/// Check if a list of processes has python callables as well as registered names
+pub fn has_python_processes(py: Python, items: &[PyObject]) -> bool {
+    items.iter().any(|item| item.extract::<String>(py).is_err())
+}
+
+/// The id of the first python process of the kind
+///
+/// Python processes are numbered after the registered processes.
+fn first_python_id<C: CellStateBase, G: GlobalStateBase>(
+    registry: &ProcessRegistry<C, G>,
+    kind: ProcessKind,
+) -> u32 {
+    registry.max_id(kind).map_or(0, |id| id + 1)
+}
+
+/// Create cell processes in order from registered process names and python callables
+pub fn cell_processes_from_py<C, G, S, GW>(
+    py: Python,
+    registry: &ProcessRegistry<C, G>,
+    items: Vec<PyObject>,
+) -> PyResult<Vec<CellProcess<C, G>>>
+where
+    C: CellStateBase + 'static,
+    G: GlobalStateBase + 'static,
+    S: CellStatePyBase<C> + IntoPy<PyObject> + for<'a> FromPyObject<'a>,
+    GW: GlobalStatePyBase<G> + IntoPy<PyObject>,
+{
+    let mut next_id = first_python_id(registry, ProcessKind::Cell);
+    items
+        .into_iter()
+        .map(|item| match process_item_from_py(py, item)? {
+            ProcessItem::Name(name) => registry
+                .cell_process(&name)
+                .map_err(|e| PyValueError::new_err(e.to_string())),
+            ProcessItem::Callable(callable) => {
+                next_id += 1;
+                Ok(cell_process_from_py::<C, G, S, GW>(next_id - 1, callable))
+            }
+        })
+        .collect()
+}
+
+/// Create global processes in order from registered process names and python callables
+pub fn global_processes_from_py<C, G, S, GW>(
+    py: Python,
+    registry: &ProcessRegistry<C, G>,
+    items: Vec<PyObject>,
+) -> PyResult<Vec<GlobalProcess<C, G>>>
+where
+    C: CellStateBase + 'static,
+    G: GlobalStateBase + 'static,
+    S: CellStatePyBase<C> + IntoPy<PyObject>,
+    GW: GlobalStatePyBase<G> + IntoPy<PyObject> + for<'a> FromPyObject<'a>,
+{
+    let mut next_id = first_python_id(registry, ProcessKind::Global);
+    items
+        .into_iter()
+        .map(|item| match process_item_from_py(py, item)? {
+            ProcessItem::Name(name) => registry
+                .global_process(&name)
+                .map_err(|e| PyValueError::new_err(e.to_string())),
+            ProcessItem::Callable(callable) => {
+                next_id += 1;
+                Ok(global_process_from_py::<C, G, S, GW>(next_id - 1, callable))
+            }
+        })
+        .collect()
+}
+
+/// Convert process info to a python dict
+///
+/// The dict has the keys id, name, kind, description and parameters
+pub fn process_info_to_py(py: Python, info: &ProcessInfo) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("id", info.id)?;
+    dict.set_item("name", &info.name)?;
+    dict.set_item("kind", info.kind.to_string())?;
+    dict.set_item("description", &info.description)?;
+    let parameters = info
+        .parameters
+        .iter()
+        .map(|spec| parameter_spec_to_py(py, spec))
+        .collect::<PyResult<Vec<_>>>()?;
+    dict.set_item("parameters", parameters)?;
+    Ok(dict.into())
+}
@@ -1,16 +1,22 @@
 extern crate pyo3;
 use pyo3::prelude::*;
 
+use crate::process_runner::cancel::CancellationToken;
 use crate::process_runner::cells::run::Process as CellProcess;
 use crate::process_runner::cells::state::CellIndex;
 use crate::process_runner::cells::state::CellStateBase;
 use crate::process_runner::global::run::Process as GlobalProcess;
 use crate::process_runner::global::state::GlobalStateBase;
+use crate::process_runner::observer::Observer;
+use crate::process_runner::parameters::Parameters;
+use crate::process_runner::recorder::Recorder;
 use crate::process_runner::run::run_iteration;
 use crate::process_runner::run::setup_initial_state;
+use crate::process_runner::simulation::Simulation;
 use crate::process_runner::state::IterationState;
 use crate::py_interface::cell_state::CellStatePyBase;
 use crate::py_interface::global_state::GlobalStatePyBase;
+use crate::py_interface::py_process::take_process_error;
 
 /// Run model setup and return initial state.
 pub fn setup_initial_state_py_wrap<
@@ -21,6 +27,7 @@ pub fn setup_initial_state_py_wrap<
 >(
     cell_setup_processes: Option<Vec<CellProcess<T, G>>>,
     global_setup_processes: Option<Vec<GlobalProcess<T, G>>>,
+    parameters: &Parameters,
     cells_data: Vec<S>,
     global_state: Option<GW>,
     randomize: Option<bool>,
@@ -28,10 +35,12 @@ pub fn setup_initial_state_py_wrap<
     let initial_state = setup_initial_state(
         Some(cell_setup_processes.unwrap_or_default().iter().collect()),
         Some(global_setup_processes.unwrap_or_default().iter().collect()),
+        parameters,
         cells_data.iter().map(|c| c.get_inner()).collect::<Vec<T>>(),
         global_state.unwrap_or_default().get_inner(),
         randomize,
     );
+    take_process_error()?;
 
     // 5. Wrap the cells state back up in the CellStatePy wrapper
     let cell_data_outer: Vec<S> = initial_state
@@ -69,6 +78,7 @@ pub fn run_iteration_py_wrap<
     global_state: GW,
     cell_processes: Vec<CellProcess<T, G>>,
     global_processes: Vec<GlobalProcess<T, G>>,
+    parameters: &Parameters,
     update_per_process: bool,
 ) -> PyResult<(Vec<S>, GW, Vec<Vec<u32>>)> {
     // 1. Get the processes that are to be used.
@@ -90,8 +100,10 @@ pub fn run_iteration_py_wrap<
         &cell_processes,
         &global_processes,
         initial_state,
+        parameters,
         update_per_process,
     );
+    take_process_error()?;
 
     // 5. Wrap the cells state back up in the CellStatePy wrapper
     let cell_data_outer: Vec<S> = out_state.cells.iter().map(|c| S::from_inner(c)).collect();
@@ -106,3 +118,51 @@ pub fn run_iteration_py_wrap<
     let global_state_output = GW::from_inner(&out_state.global_state);
     Ok((cell_data_outer, global_state_output, network_converted))
 }
+
+/// Run simulation iterations with the GIL released
+///
+/// The GIL is taken back between iterations to check for signals so that
+/// Ctrl-C stops the run. A signal or a cancelled token stops the run at the end
+/// of the current iteration. A signal cancels the token and its exception,
+/// e.g. KeyboardInterrupt, is returned.
+/// Python processes take the GIL while they run.
+/// If a python process raises an exception the simulation is restored to the
+/// last completed iteration, which is not recorded, and the exception is returned.
+/// The simulation is only copied before each iteration when `has_python_processes`
+/// is set, so runs of registered processes do not pay for the copy.
+///
+/// Returns the number of iterations run.
+pub fn run_simulation_py_wrap<C: CellStateBase + Send, G: GlobalStateBase + Send>(
+    py: Python,
+    simulation: &mut Simulation<C, G>,
+    iterations: u32,
+    mut recorder: Option<&mut Recorder<C, G>>,
+    token: &CancellationToken,
+    has_python_processes: bool,
+) -> PyResult<u32> {
+    let mut completed = 0;
+    while completed < iterations && !token.is_cancelled() {
+        let previous = if has_python_processes {
+            Some(simulation.snapshot())
+        } else {
+            None
+        };
+        py.allow_threads(|| simulation.step(&mut []));
+        if let Err(err) = take_process_error() {
+            if let Some(previous) = previous {
+                simulation.restore(previous);
+            }
+            return Err(err);
+        }
+        if let Some(recorder) = recorder.as_deref_mut() {
+            recorder.observe(simulation.iteration(), &simulation.state);
+        }
+        completed += 1;
+        // The token is cancelled so other users of it stop as well
+        if let Err(err) = py.check_signals() {
+            token.cancel();
+            return Err(err);
+        }
+    }
+    Ok(completed)
+}
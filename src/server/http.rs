@@ -0,0 +1,302 @@
+/// HTTP Module
+///
+/// A minimal HTTP/1.1 request reader and response writer for the local GUI
+/// server. Each connection carries a single request; bodies are read by
+/// content length and query values are not percent decoded.
+use std::fmt;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+
+use serde_json::Value as JsonValue;
+
+/// Requests with larger bodies are rejected
+///
+/// The largest body is a GeoJSON upload of the model cells.
+pub const MAX_BODY_LENGTH: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum HttpError {
+    Io(std::io::Error),
+    /// The connection closed before a request line was read
+    Closed,
+    InvalidRequestLine(String),
+    InvalidHeader(String),
+    BodyTooLarge(usize),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Io(e) => write!(f, "{}", e),
+            HttpError::Closed => write!(f, "Connection closed"),
+            HttpError::InvalidRequestLine(line) => write!(f, "Invalid request line: {}", line),
+            HttpError::InvalidHeader(line) => write!(f, "Invalid header: {}", line),
+            HttpError::BodyTooLarge(length) => write!(f, "Body of {} bytes is too large", length),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<std::io::Error> for HttpError {
+    fn from(e: std::io::Error) -> Self {
+        HttpError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    /// Header names are lower case
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn read(reader: &mut impl BufRead) -> Result<Request, HttpError> {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(HttpError::Closed);
+        }
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(_version)) => (method, target),
+            _ => return Err(HttpError::InvalidRequestLine(line.trim_end().to_owned())),
+        };
+        let (path, query) = match target.find('?') {
+            Some(i) => (&target[..i], &target[i + 1..]),
+            None => (target, ""),
+        };
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.find('=') {
+                Some(i) => (pair[..i].to_owned(), pair[i + 1..].to_owned()),
+                None => (pair.to_owned(), String::new()),
+            })
+            .collect();
+        let mut request = Request {
+            method: method.to_uppercase(),
+            path: path.to_owned(),
+            query,
+            headers: vec![],
+            body: vec![],
+        };
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(HttpError::Closed);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let i = line
+                .find(':')
+                .ok_or_else(|| HttpError::InvalidHeader(line.to_owned()))?;
+            request.headers.push((
+                line[..i].trim().to_lowercase(),
+                line[i + 1..].trim().to_owned(),
+            ));
+        }
+
+        let length = match request.header("content-length") {
+            Some(length) => length
+                .parse::<usize>()
+                .map_err(|_| HttpError::InvalidHeader(format!("content-length: {}", length)))?,
+            None => 0,
+        };
+        if length > MAX_BODY_LENGTH {
+            return Err(HttpError::BodyTooLarge(length));
+        }
+        // Read the body as it arrives rather than allocating the claimed length
+        reader.take(length as u64).read_to_end(&mut request.body)?;
+        if request.body.len() < length {
+            return Err(HttpError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(request)
+    }
+
+    /// The value of the header, matched case insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The path split into its non empty segments
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.split('/').filter(|s| !s.is_empty()).collect()
+    }
+
+    /// Whether the request asks to upgrade the connection to a WebSocket
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.header("upgrade")
+            .map(|v| v.eq_ignore_ascii_case("websocket"))
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+    /// The origin allowed to read the response from another site
+    pub allow_origin: Option<String>,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &JsonValue) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+            allow_origin: None,
+        }
+    }
+
+    /// A JSON body of the form `{"error": message}`
+    pub fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &serde_json::json!({ "error": message }))
+    }
+
+    pub fn no_content() -> Response {
+        Response {
+            status: 204,
+            content_type: "text/plain",
+            body: String::new(),
+            allow_origin: None,
+        }
+    }
+
+    pub fn with_allow_origin(mut self, origin: &str) -> Self {
+        self.allow_origin = Some(origin.to_owned());
+        self
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// Write the response and close the connection
+    ///
+    /// Browsers only let another site read the response if it has an allowed
+    /// origin, e.g. a front-end served from another local port.
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len()
+        )?;
+        if let Some(origin) = &self.allow_origin {
+            write!(
+                writer,
+                "Access-Control-Allow-Origin: {}\r\n\
+                 Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+                 Access-Control-Allow-Headers: Content-Type\r\n\
+                 Vary: Origin\r\n",
+                origin
+            )?;
+        }
+        write!(writer, "Connection: close\r\n\r\n")?;
+        writer.write_all(self.body.as_bytes())?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_a_request_with_a_query_and_body() {
+        let data = "POST /run?iterations=5&fast HTTP/1.1\r\n\
+                    Host: localhost\r\n\
+                    Content-Length: 4\r\n\r\n\
+                    {}\r\n";
+        let request = Request::read(&mut data.as_bytes()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.segments(), vec!["run"]);
+        assert_eq!(request.query("iterations"), Some("5"));
+        assert_eq!(request.query("fast"), Some(""));
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(request.body, b"{}\r\n");
+        assert!(!request.is_websocket_upgrade());
+    }
+
+    #[test]
+    fn should_reject_invalid_requests() {
+        assert!(matches!(
+            Request::read(&mut "GET\r\n\r\n".as_bytes()),
+            Err(HttpError::InvalidRequestLine(_))
+        ));
+        assert!(matches!(
+            Request::read(&mut "GET / HTTP/1.1\r\nHost\r\n\r\n".as_bytes()),
+            Err(HttpError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            Request::read(&mut "".as_bytes()),
+            Err(HttpError::Closed)
+        ));
+        assert!(matches!(
+            Request::read(&mut "POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\n{}".as_bytes()),
+            Err(HttpError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+        let too_large = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LENGTH + 1
+        );
+        assert!(matches!(
+            Request::read(&mut too_large.as_bytes()),
+            Err(HttpError::BodyTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn should_write_a_json_response() {
+        let mut out = vec![];
+        Response::error(404, "Not found: /x")
+            .write_to(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(out.contains("Content-Length: 25\r\n"));
+        assert!(out.ends_with("\r\n\r\n{\"error\":\"Not found: /x\"}"));
+        assert!(!out.contains("Access-Control-Allow-Origin"));
+
+        let mut out = vec![];
+        Response::no_content()
+            .with_allow_origin("http://localhost:3000")
+            .write_to(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Access-Control-Allow-Origin: http://localhost:3000\r\n"));
+    }
+}
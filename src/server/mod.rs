@@ -0,0 +1,515 @@
+/// Server Module
+///
+/// A local server that hosts a simulation of a GUI model so the web GUI can
+/// drive the rust engine directly instead of through the python interface.
+///
+/// Commands are sent over HTTP and return a JSON message:
+///
+/// - `GET /model`: the layers, cell actions and parameter controls
+/// - `GET /state`: the full state
+/// - `GET /parameters`, `POST /parameters`: read or set parameter values from a
+///   JSON object of names and values
+/// - `POST /setup?seed=<n>&label_property=<name>&randomize=<bool>`: create the
+///   cells from the GeoJSON FeatureCollection in the body, only randomizing
+///   them if randomize is true
+/// - `POST /step`: run a single iteration
+/// - `POST /run?iterations=<n>`: run iterations at the step interval until
+///   paused or until n iterations have run
+/// - `POST /pause`, `POST /reset`
+/// - `POST /cells/<id>/actions/<uid>`: apply a cell action
+///
+/// Clients connected to `/ws` with a WebSocket receive the full state when they
+/// connect and then every message that changes the state, including each
+/// iteration run in the background. If a background iteration fails the
+/// simulation is paused and they receive an error message. See the session
+/// module for the messages.
+///
+/// Browsers send the origin of the page with cross site requests and WebSocket
+/// handshakes. Requests from an origin other than the server's own or one
+/// added with `with_allowed_origin` are rejected so that other sites cannot
+/// drive the simulation. Requests without an origin, e.g. from scripts, are
+/// allowed.
+///
+/// Only available with the `server` feature.
+pub mod http;
+pub mod session;
+
+use std::io;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::thread;
+use std::time::Duration;
+
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::gui::GuiModel;
+use http::Request;
+use http::Response;
+use serde_json::Value as JsonValue;
+use session::Session;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::Message;
+use tungstenite::WebSocket;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+/// Time between iterations while the simulation is running
+pub const DEFAULT_STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often WebSocket connections check for messages to send
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Connections that do not send a request in this time are closed
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Clients = Arc<Mutex<Vec<mpsc::Sender<String>>>>;
+
+/// Lock the mutex even if a thread panicked while holding it
+///
+/// Session commands leave the session consistent when they fail so one failed
+/// command should not stop the server.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Send a message to every connected WebSocket client
+///
+/// Clients that have disconnected are removed.
+fn broadcast(clients: &Clients, message: &JsonValue) {
+    let message = message.to_string();
+    lock(clients).retain(|client| client.send(message.clone()).is_ok());
+}
+
+fn parse_query<T: std::str::FromStr>(request: &Request, name: &str) -> Result<Option<T>, Response> {
+    match request.query(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Response::error(400, &format!("Invalid {}: {}", name, value))),
+        None => Ok(None),
+    }
+}
+
+/// Run a command on the session
+///
+/// Returns the response message and whether it should be sent to the
+/// WebSocket clients.
+pub fn route<M>(session: &mut Session<M>, request: &Request) -> Result<(JsonValue, bool), Response>
+where
+    M: GuiModel,
+    M::Cell: CellFields + PartialEq,
+    M::Global: Introspect,
+{
+    let bad_request = |e: session::SessionError| Response::error(400, &e.to_string());
+    match (request.method.as_str(), request.segments().as_slice()) {
+        ("GET", ["model"]) => Ok((session.model_description(), false)),
+        ("GET", ["state"]) => Ok((session.state_message(), false)),
+        ("GET", ["parameters"]) => Ok((session.parameters(), false)),
+        ("POST", ["parameters"]) => Ok((
+            session.set_parameters(&request.body).map_err(bad_request)?,
+            false,
+        )),
+        ("POST", ["setup"]) => {
+            let seed = parse_query(request, "seed")?;
+            let randomize = parse_query(request, "randomize")?.unwrap_or(false);
+            let geojson = String::from_utf8_lossy(&request.body);
+            let message = session
+                .setup(&geojson, seed, request.query("label_property"), randomize)
+                .map_err(bad_request)?;
+            Ok((message, true))
+        }
+        ("POST", ["step"]) => match session.step() {
+            Ok(message) => Ok((message, true)),
+            Err(e) => Err(Response::error(500, &e.to_string())),
+        },
+        ("POST", ["run"]) => Ok((session.run(parse_query(request, "iterations")?), true)),
+        ("POST", ["pause"]) => Ok((session.pause(), true)),
+        ("POST", ["reset"]) => Ok((session.reset(), true)),
+        ("POST", ["cells", id, "actions", uid]) => {
+            let id = id
+                .parse()
+                .map_err(|_| Response::error(400, &format!("Invalid cell id: {}", id)))?;
+            Ok((session.apply_action(id, uid).map_err(bad_request)?, true))
+        }
+        (method, _) => Err(Response::error(
+            404,
+            &format!("Not found: {} {}", method, request.path),
+        )),
+    }
+}
+
+fn websocket_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+/// Complete the WebSocket handshake and send messages until the client leaves
+fn serve_websocket<M>(
+    mut stream: TcpStream,
+    request: &Request,
+    session: &Mutex<Session<M>>,
+    clients: &Clients,
+) -> io::Result<()>
+where
+    M: GuiModel,
+    M::Cell: CellFields + PartialEq,
+    M::Global: Introspect,
+{
+    let key = match request.header("sec-websocket-key") {
+        Some(key) => key,
+        None => {
+            Response::error(400, "Missing Sec-WebSocket-Key").write_to(&mut stream)?;
+            return Ok(());
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    )?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    // Register before reading the state so that no later message is missed
+    let (sender, receiver) = mpsc::channel();
+    lock(clients).push(sender);
+    let state = lock(session).state_message();
+    socket
+        .write_message(Message::Text(state.to_string()))
+        .map_err(websocket_error)?;
+    loop {
+        for message in receiver.try_iter() {
+            socket
+                .write_message(Message::Text(message))
+                .map_err(websocket_error)?;
+        }
+        match socket.read_message() {
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(websocket_error(e)),
+        }
+    }
+}
+
+/// The origin of the request if it is one of the allowed origins
+///
+/// Requests without an origin are not from a browser page and are allowed.
+fn check_origin<'a>(request: &'a Request, origins: &[String]) -> Result<Option<&'a str>, Response> {
+    match request.header("origin") {
+        Some(origin) if origins.iter().any(|o| o == origin) => Ok(Some(origin)),
+        Some(origin) => Err(Response::error(
+            403,
+            &format!("Origin not allowed: {}", origin),
+        )),
+        None => Ok(None),
+    }
+}
+
+fn handle_connection<M>(
+    stream: TcpStream,
+    session: &Mutex<Session<M>>,
+    clients: &Clients,
+    origins: &[String],
+) -> io::Result<()>
+where
+    M: GuiModel,
+    M::Cell: CellFields + PartialEq,
+    M::Global: Introspect,
+{
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = match Request::read(&mut BufReader::new(stream.try_clone()?)) {
+        Ok(request) => request,
+        Err(http::HttpError::Closed) => return Ok(()),
+        Err(e) => return Response::error(400, &e.to_string()).write_to(&mut &stream),
+    };
+    let origin = match check_origin(&request, origins) {
+        Ok(origin) => origin,
+        Err(response) => return response.write_to(&mut &stream),
+    };
+    if request.path == "/ws" && request.is_websocket_upgrade() {
+        return serve_websocket(stream, &request, session, clients);
+    }
+    let allow_origin = |response: Response| match origin {
+        Some(origin) => response.with_allow_origin(origin),
+        None => response,
+    };
+    if request.method == "OPTIONS" {
+        return allow_origin(Response::no_content()).write_to(&mut &stream);
+    }
+    // Messages are broadcast while the session is locked so clients receive
+    // them in the order the commands ran
+    let response = {
+        let mut session = lock(session);
+        match route(&mut session, &request) {
+            Ok((message, send)) => {
+                if send {
+                    broadcast(clients, &message);
+                }
+                Response::json(200, &message)
+            }
+            Err(response) => response,
+        }
+    };
+    allow_origin(response).write_to(&mut &stream)
+}
+
+/// A local server hosting a simulation of a GUI model
+///
+/// Each connection is handled on its own thread and the simulation is shared
+/// between them.
+#[derive(Debug)]
+pub struct Server<M: GuiModel> {
+    listener: TcpListener,
+    session: Arc<Mutex<Session<M>>>,
+    clients: Clients,
+    step_interval: Duration,
+    allowed_origins: Vec<String>,
+}
+
+impl<M> Server<M>
+where
+    M: GuiModel + Send + 'static,
+    M::Cell: CellFields + PartialEq + Send,
+    M::Global: Introspect + Send,
+{
+    pub fn bind(model: M, address: impl ToSocketAddrs) -> io::Result<Server<M>> {
+        Ok(Server {
+            listener: TcpListener::bind(address)?,
+            session: Arc::new(Mutex::new(Session::new(model))),
+            clients: Arc::new(Mutex::new(vec![])),
+            step_interval: DEFAULT_STEP_INTERVAL,
+            allowed_origins: vec![],
+        })
+    }
+
+    pub fn with_step_interval(mut self, interval: Duration) -> Self {
+        self.step_interval = interval;
+        self
+    }
+
+    /// Allow a front-end served from another origin, e.g. `http://localhost:3000`
+    pub fn with_allowed_origin(mut self, origin: &str) -> Self {
+        self.allowed_origins.push(origin.to_owned());
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The server's own origins and the allowed origins
+    ///
+    /// A server on a loopback address can also be reached as localhost.
+    fn origins(&self) -> io::Result<Vec<String>> {
+        let address = self.local_addr()?;
+        let mut origins = vec![format!("http://{}", address)];
+        if address.ip().is_loopback() {
+            origins.push(format!("http://localhost:{}", address.port()));
+        }
+        origins.extend(self.allowed_origins.iter().cloned());
+        Ok(origins)
+    }
+
+    /// The hosted session, e.g. to set up cells before serving
+    pub fn session(&self) -> Arc<Mutex<Session<M>>> {
+        self.session.clone()
+    }
+
+    /// Serve connections until the process exits
+    ///
+    /// An error on a connection only closes that connection and is passed to
+    /// on_error. An error accepting connections stops the server and is returned.
+    pub fn serve(self, on_error: impl Fn(io::Error) + Send + Sync + 'static) -> io::Result<()> {
+        let origins = Arc::new(self.origins()?);
+        let on_error = Arc::new(on_error);
+        let session = self.session.clone();
+        let clients = self.clients.clone();
+        let step_interval = self.step_interval;
+        thread::spawn(move || loop {
+            thread::sleep(step_interval);
+            let mut session = lock(&session);
+            if let Some(message) = session.tick() {
+                broadcast(&clients, &message);
+            }
+        });
+
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let session = self.session.clone();
+            let clients = self.clients.clone();
+            let origins = origins.clone();
+            let on_error = on_error.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &session, &clients, &origins) {
+                    on_error(e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::examples::example_gui::ExampleGuiModel;
+    use std::io::Read;
+    use tungstenite::client::client;
+
+    const POINTS: &str = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {},
+         "geometry": {"type": "Point", "coordinates": [0.0, 0.0]}},
+        {"type": "Feature", "properties": {},
+         "geometry": {"type": "Point", "coordinates": [0.0, 0.1]}}
+    ]}"#;
+
+    /// Reads fail rather than hang if the server stops responding
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn start_server() -> SocketAddr {
+        let server = Server::bind(ExampleGuiModel, "127.0.0.1:0")
+            .unwrap()
+            .with_step_interval(Duration::from_millis(5));
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.serve(|_| {}));
+        address
+    }
+
+    /// Send a request with extra header lines and return the whole response
+    fn send_raw(
+        address: SocketAddr,
+        method: &str,
+        target: &str,
+        headers: &str,
+        body: &str,
+    ) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(TEST_TIMEOUT)).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            target,
+            headers,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    /// Send a request and return the status and JSON body of the response
+    fn send(address: SocketAddr, method: &str, target: &str, body: &str) -> (u16, JsonValue) {
+        let response = send_raw(address, method, target, "", body);
+        let status = response[9..12].parse().unwrap();
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn read_text(socket: &mut WebSocket<TcpStream>) -> JsonValue {
+        match socket.read_message().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("Unexpected message: {:?}", message),
+        }
+    }
+
+    #[test]
+    fn should_run_commands_over_http() {
+        let address = start_server();
+        let (status, model) = send(address, "GET", "/model", "");
+        assert_eq!(status, 200);
+        assert_eq!(model["controls"][0]["uid"], "growth_rate");
+
+        let (status, state) = send(address, "POST", "/setup?seed=3", POINTS);
+        assert_eq!(status, 200);
+        assert_eq!(state["cells"].as_array().unwrap().len(), 2);
+
+        let (_, message) = send(address, "POST", "/cells/0/actions/setpop99", "");
+        assert_eq!(message["cells"][0]["population"], 99);
+        let (_, message) = send(address, "POST", "/step", "");
+        assert_eq!(message["type"], "iteration");
+        assert_eq!(message["iteration"], 1);
+
+        let (status, error) = send(address, "POST", "/cells/9/actions/setpop99", "");
+        assert_eq!(status, 400);
+        assert_eq!(error["error"], "Unknown cell: 9");
+        let (status, _) = send(address, "DELETE", "/state", "");
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn should_stream_iterations_over_a_websocket() {
+        let address = start_server();
+        send(address, "POST", "/setup", POINTS);
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(TEST_TIMEOUT)).unwrap();
+        let (mut socket, _) = client(format!("ws://{}/ws", address), stream).unwrap();
+        assert_eq!(read_text(&mut socket)["type"], "state");
+
+        let (_, status) = send(address, "POST", "/run?iterations=2", "");
+        assert_eq!(status["running"], true);
+        assert_eq!(read_text(&mut socket)["type"], "status");
+        let first = read_text(&mut socket);
+        let second = read_text(&mut socket);
+        assert_eq!(first["iteration"], 1);
+        assert_eq!(second["iteration"], 2);
+        assert_eq!(second["running"], false);
+    }
+
+    #[test]
+    fn should_reject_requests_from_other_origins() {
+        let server = Server::bind(ExampleGuiModel, "127.0.0.1:0")
+            .unwrap()
+            .with_allowed_origin("http://localhost:3000");
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.serve(|_| {}));
+
+        let response = send_raw(address, "POST", "/step", "Origin: http://evil.test\r\n", "");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        let response = send_raw(
+            address,
+            "GET",
+            "/state",
+            "Origin: http://localhost:3000\r\n",
+            "",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Access-Control-Allow-Origin: http://localhost:3000\r\n"));
+        let own_origin = format!("Origin: http://{}\r\n", address);
+        let response = send_raw(address, "GET", "/state", &own_origin, "");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let response = send_raw(address, "GET", "/state", "", "");
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+
+        let response = send_raw(
+            address,
+            "GET",
+            "/ws",
+            "Upgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\nOrigin: http://evil.test\r\n",
+            "",
+        );
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    }
+}
@@ -0,0 +1,592 @@
+/// Session Module
+///
+/// The simulation of a GUI model hosted by the server and the JSON messages
+/// sent to the web GUI.
+///
+/// Every message has a `type`:
+///
+/// - `state`: every cell, the network map, labels and the global summary, sent
+///   after setup and reset and to newly connected clients
+/// - `iteration`: the cells that changed in an iteration and the global summary
+/// - `cells`: cells changed by a cell action and the global summary
+/// - `status`: whether the simulation is running
+/// - `error`: an iteration failed and the simulation was paused
+///
+/// The global summary is the iteration, whether the simulation is running, the
+/// layer totals history and the fields of the model global state. Cells are
+/// objects with their id, position and fields.
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+use crate::io::geojson::field_value_to_json;
+use crate::io::geojson::GeoJsonError;
+use crate::io::geojson::GeoJsonLoader;
+use crate::process_runner::cells::state::CellIndex;
+use crate::process_runner::cells::state::CellStateBase;
+use crate::process_runner::fields::CellFields;
+use crate::process_runner::fields::Introspect;
+use crate::process_runner::gui::update_layer_totals;
+use crate::process_runner::gui::GuiError;
+use crate::process_runner::gui::GuiModel;
+use crate::process_runner::gui::TOTALS_HISTORY_LENGTH;
+use crate::process_runner::network::CellNetwork;
+use crate::process_runner::parameters::ParameterError;
+use crate::process_runner::parameters::ParameterValue;
+use crate::process_runner::random;
+use crate::process_runner::run::setup_initial_state;
+use crate::process_runner::simulation::Simulation;
+use crate::process_runner::simulation::SimulationSnapshot;
+use crate::process_runner::state::IterationState;
+use serde_json::json;
+use serde_json::Map as JsonMap;
+use serde_json::Value as JsonValue;
+
+#[derive(Debug)]
+pub enum SessionError {
+    Setup(GeoJsonError),
+    Gui(GuiError),
+    Parameter(ParameterError),
+    /// The parameter value is not a number or bool
+    InvalidParameterValue {
+        name: String,
+        value: String,
+    },
+    UnknownCell(u32),
+    /// The request body is not valid JSON
+    InvalidBody(serde_json::Error),
+    /// A process panicked during the iteration
+    StepFailed(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Setup(e) => write!(f, "{}", e),
+            SessionError::Gui(e) => write!(f, "{}", e),
+            SessionError::Parameter(e) => write!(f, "{}", e),
+            SessionError::InvalidParameterValue { name, value } => write!(
+                f,
+                "Parameter {} must be a number or bool but got {}",
+                name, value
+            ),
+            SessionError::UnknownCell(id) => write!(f, "Unknown cell: {}", id),
+            SessionError::InvalidBody(e) => write!(f, "Invalid JSON body: {}", e),
+            SessionError::StepFailed(msg) => write!(f, "Iteration failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<GeoJsonError> for SessionError {
+    fn from(e: GeoJsonError) -> Self {
+        SessionError::Setup(e)
+    }
+}
+
+impl From<GuiError> for SessionError {
+    fn from(e: GuiError) -> Self {
+        SessionError::Gui(e)
+    }
+}
+
+impl From<ParameterError> for SessionError {
+    fn from(e: ParameterError) -> Self {
+        SessionError::Parameter(e)
+    }
+}
+
+impl From<serde_json::Error> for SessionError {
+    fn from(e: serde_json::Error) -> Self {
+        SessionError::InvalidBody(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunState {
+    Paused,
+    /// Running until paused or until the remaining iterations have run
+    Running {
+        remaining: Option<u32>,
+    },
+}
+
+/// A cell as a JSON object of its id, position and fields
+pub fn cell_to_json<C: CellStateBase + Introspect>(cell: &C) -> JsonValue {
+    let position = cell.position();
+    let mut object = JsonMap::new();
+    object.insert("id".to_owned(), JsonValue::from(u32::from(cell.id())));
+    object.insert("position".to_owned(), json!([position.x(), position.y()]));
+    for (name, value) in cell.field_values() {
+        object.insert(name, field_value_to_json(value));
+    }
+    JsonValue::Object(object)
+}
+
+/// The message of a panic payload
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+fn network_to_json(network: &CellNetwork) -> JsonValue {
+    let network_map: Vec<Vec<u32>> = network
+        .iter()
+        .map(|c| c.iter().map(|ci: &CellIndex| u32::from(*ci)).collect())
+        .collect();
+    json!(network_map)
+}
+
+/// A simulation of a GUI model driven by server commands
+#[derive(Debug)]
+pub struct Session<M: GuiModel> {
+    model: M,
+    simulation: Simulation<M::Cell, M::Global>,
+    /// The state after setup that reset returns to
+    initial: SimulationSnapshot<M::Cell, M::Global>,
+    labels: Vec<Option<String>>,
+    totals: HashMap<String, Vec<f64>>,
+    run_state: RunState,
+}
+
+impl<M> Session<M>
+where
+    M: GuiModel,
+    M::Cell: CellFields + PartialEq,
+    M::Global: Introspect,
+{
+    /// A session with no cells until setup
+    pub fn new(model: M) -> Session<M> {
+        let simulation = Simulation::new(
+            IterationState {
+                global_state: M::Global::default(),
+                cells: vec![],
+                network: vec![],
+            },
+            model.cell_processes(),
+            model.global_processes(),
+            model.parameters(),
+        );
+        Session {
+            initial: simulation.snapshot(),
+            model,
+            simulation,
+            labels: vec![],
+            totals: HashMap::new(),
+            run_state: RunState::Paused,
+        }
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn simulation(&self) -> &Simulation<M::Cell, M::Global> {
+        &self.simulation
+    }
+
+    pub fn run_state(&self) -> RunState {
+        self.run_state
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.run_state != RunState::Paused
+    }
+
+    /// The layers, cell actions and parameter controls of the model
+    pub fn model_description(&self) -> JsonValue {
+        let layers: Vec<JsonValue> = self
+            .model
+            .layers()
+            .iter()
+            .map(|layer| json!({ "uid": layer.uid, "label": layer.label }))
+            .collect();
+        let actions: Vec<JsonValue> = self
+            .model
+            .cell_actions()
+            .iter()
+            .map(|action| {
+                json!({
+                    "uid": action.uid,
+                    "label": action.label,
+                    "type": action.action_type.to_string(),
+                    "group": action.group,
+                })
+            })
+            .collect();
+        let controls: Vec<JsonValue> = self
+            .model
+            .controls()
+            .iter()
+            .map(|control| {
+                json!({
+                    "uid": control.uid,
+                    "label": control.label,
+                    "controlType": control.control_type.to_string(),
+                    "type": control.value_type,
+                    "min": control.min,
+                    "max": control.max,
+                })
+            })
+            .collect();
+        json!({ "layers": layers, "actions": actions, "controls": controls })
+    }
+
+    /// The current parameter values by name
+    pub fn parameters(&self) -> JsonValue {
+        let values: JsonMap<String, JsonValue> = self
+            .simulation
+            .parameters
+            .values()
+            .into_iter()
+            .map(|(name, value)| (name, value.to_json()))
+            .collect();
+        JsonValue::Object(values)
+    }
+
+    fn message(&self, message_type: &str) -> JsonMap<String, JsonValue> {
+        let mut message = JsonMap::new();
+        message.insert("type".to_owned(), JsonValue::from(message_type));
+        message.insert(
+            "iteration".to_owned(),
+            JsonValue::from(self.simulation.iteration()),
+        );
+        message.insert("running".to_owned(), JsonValue::from(self.is_running()));
+        message
+    }
+
+    fn summary_message(&self, message_type: &str) -> JsonMap<String, JsonValue> {
+        let mut message = self.message(message_type);
+        let model_state: JsonMap<String, JsonValue> = self
+            .simulation
+            .state
+            .global_state
+            .field_values()
+            .into_iter()
+            .map(|(name, value)| (name, field_value_to_json(value)))
+            .collect();
+        message.insert("totals".to_owned(), json!(self.totals));
+        message.insert("model_state".to_owned(), JsonValue::Object(model_state));
+        message
+    }
+
+    /// The full state message
+    pub fn state_message(&self) -> JsonValue {
+        let state = &self.simulation.state;
+        let mut message = self.summary_message("state");
+        message.insert(
+            "cells".to_owned(),
+            state.cells.iter().map(cell_to_json).collect(),
+        );
+        message.insert("network_map".to_owned(), network_to_json(&state.network));
+        message.insert("labels".to_owned(), json!(self.labels));
+        JsonValue::Object(message)
+    }
+
+    pub fn status_message(&self) -> JsonValue {
+        JsonValue::Object(self.message("status"))
+    }
+
+    pub fn error_message(&self, error: &SessionError) -> JsonValue {
+        let mut message = self.message("error");
+        message.insert("error".to_owned(), JsonValue::from(error.to_string()));
+        JsonValue::Object(message)
+    }
+
+    fn update_totals(&mut self) {
+        update_layer_totals(
+            &self.model,
+            &mut self.totals,
+            &self.simulation.state.cells,
+            TOTALS_HISTORY_LENGTH,
+        );
+    }
+
+    /// Create cells from a GeoJSON FeatureCollection
+    ///
+    /// The cells are at the centroids of the features and are only randomized
+    /// if randomize is set. The simulation is paused and the parameters are
+    /// kept.
+    pub fn setup(
+        &mut self,
+        geojson: &str,
+        seed: Option<u64>,
+        label_property: Option<&str>,
+        randomize: bool,
+    ) -> Result<JsonValue, SessionError> {
+        if let Some(seed) = seed {
+            random::seed(seed);
+        }
+        let mut loader = GeoJsonLoader::new();
+        if let Some(property) = label_property {
+            loader = loader.with_label_property(property);
+        }
+        let loaded = loader.load_str::<M::Cell>(geojson)?;
+        let parameters = self.simulation.parameters.clone();
+        let state = setup_initial_state(
+            None,
+            None,
+            &parameters,
+            loaded.cells,
+            M::Global::default(),
+            randomize,
+        );
+        self.initial = SimulationSnapshot {
+            state,
+            parameters,
+            update_per_process: self.simulation.update_per_process,
+            iteration: 0,
+        };
+        self.labels = loaded.labels;
+        Ok(self.reset())
+    }
+
+    /// Return to the state after setup and pause, keeping the parameters
+    pub fn reset(&mut self) -> JsonValue {
+        let parameters = self.simulation.parameters.clone();
+        self.simulation.restore(SimulationSnapshot {
+            parameters,
+            ..self.initial.clone()
+        });
+        self.run_state = RunState::Paused;
+        self.totals.clear();
+        self.update_totals();
+        self.state_message()
+    }
+
+    /// Run a single iteration and return the cells that changed
+    ///
+    /// The network map is included if it changed. If a process panics the
+    /// state is kept from before the iteration and the simulation is paused.
+    pub fn step(&mut self) -> Result<JsonValue, SessionError> {
+        let previous = self.simulation.state.clone();
+        let simulation = &mut self.simulation;
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| simulation.step(&mut []))) {
+            self.simulation.state = previous;
+            self.run_state = RunState::Paused;
+            return Err(SessionError::StepFailed(panic_message(panic.as_ref())));
+        }
+        self.update_totals();
+
+        let state = &self.simulation.state;
+        let changed: Vec<JsonValue> = state
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(i, cell)| previous.cells.get(*i) != Some(*cell))
+            .map(|(_, cell)| cell_to_json(cell))
+            .collect();
+        let mut message = self.summary_message("iteration");
+        message.insert("cells".to_owned(), JsonValue::from(changed));
+        if previous.network != state.network {
+            message.insert("network_map".to_owned(), network_to_json(&state.network));
+        }
+        Ok(JsonValue::Object(message))
+    }
+
+    /// Keep running iterations, or only the given number, until paused
+    pub fn run(&mut self, iterations: Option<u32>) -> JsonValue {
+        self.run_state = match iterations {
+            Some(0) => RunState::Paused,
+            remaining => RunState::Running { remaining },
+        };
+        self.status_message()
+    }
+
+    pub fn pause(&mut self) -> JsonValue {
+        self.run_state = RunState::Paused;
+        self.status_message()
+    }
+
+    /// Run an iteration if the simulation is running
+    ///
+    /// Called by the server at its step interval.
+    pub fn tick(&mut self) -> Option<JsonValue> {
+        let remaining = match self.run_state {
+            RunState::Paused => return None,
+            RunState::Running { remaining } => remaining,
+        };
+        self.run_state = match remaining {
+            Some(1) => RunState::Paused,
+            Some(n) => RunState::Running {
+                remaining: Some(n - 1),
+            },
+            None => RunState::Running { remaining: None },
+        };
+        Some(self.step().unwrap_or_else(|e| self.error_message(&e)))
+    }
+
+    /// Apply a cell action and return the updated cell
+    pub fn apply_action(&mut self, cell_id: u32, uid: &str) -> Result<JsonValue, SessionError> {
+        let index = cell_id as usize;
+        let cell = self
+            .simulation
+            .state
+            .cells
+            .get(index)
+            .cloned()
+            .ok_or(SessionError::UnknownCell(cell_id))?;
+        let cell = self.model.apply_action(cell, uid)?;
+        let cell_json = cell_to_json(&cell);
+        self.simulation.state.cells[index] = cell;
+        self.update_totals();
+
+        let mut message = self.summary_message("cells");
+        message.insert("cells".to_owned(), json!([cell_json]));
+        Ok(JsonValue::Object(message))
+    }
+
+    /// Set parameters from a JSON object of names and values
+    ///
+    /// No values are set if any of them are invalid.
+    pub fn set_parameters(&mut self, body: &[u8]) -> Result<JsonValue, SessionError> {
+        let values: JsonMap<String, JsonValue> = serde_json::from_slice(body)?;
+        let mut parameters = self.simulation.parameters.clone();
+        for (name, value) in values.iter() {
+            let parameter_value = ParameterValue::from_json(value).ok_or_else(|| {
+                SessionError::InvalidParameterValue {
+                    name: name.clone(),
+                    value: value.to_string(),
+                }
+            })?;
+            parameters.set(name, parameter_value)?;
+        }
+        self.simulation.parameters = parameters;
+        Ok(self.parameters())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_runner::cells::run::Process as CellProcess;
+    use crate::process_runner::examples::example_gui::ExampleGuiModel;
+
+    const POINTS: &str = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"name": "a"},
+         "geometry": {"type": "Point", "coordinates": [0.0, 0.0]}},
+        {"type": "Feature", "properties": {"name": "b"},
+         "geometry": {"type": "Point", "coordinates": [0.0, 0.1]}}
+    ]}"#;
+
+    fn demo_session() -> Session<ExampleGuiModel> {
+        let mut session = Session::new(ExampleGuiModel);
+        session.setup(POINTS, Some(1), Some("name"), false).unwrap();
+        session
+    }
+
+    #[test]
+    fn should_send_the_full_state_after_setup() {
+        let mut session = Session::new(ExampleGuiModel);
+        let message = session.setup(POINTS, Some(1), Some("name"), false).unwrap();
+        assert_eq!(message["type"], "state");
+        assert_eq!(message["iteration"], 0);
+        assert_eq!(message["cells"].as_array().unwrap().len(), 2);
+        assert_eq!(message["cells"][1]["id"], 1);
+        assert_eq!(message["cells"][1]["position"], json!([0.0, 0.1]));
+        assert_eq!(message["network_map"], json!([[1], [0]]));
+        assert_eq!(message["labels"], json!(["a", "b"]));
+        assert_eq!(message["totals"]["population"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn should_send_only_changed_cells_after_a_step() {
+        let mut session = demo_session();
+        session.apply_action(0, "setpop50").unwrap();
+        let message = session.step().unwrap();
+        assert_eq!(message["type"], "iteration");
+        assert_eq!(message["iteration"], 1);
+        assert_eq!(message["model_state"]["iterations"], 1);
+        assert!(message.get("network_map").is_none());
+        assert_eq!(message["totals"]["population"].as_array().unwrap().len(), 3);
+
+        let cells = session.simulation().state.cells.clone();
+        let message = session.step().unwrap();
+        let changed = message["cells"].as_array().unwrap();
+        let expected = session
+            .simulation()
+            .state
+            .cells
+            .iter()
+            .zip(cells.iter())
+            .filter(|(after, before)| after != before)
+            .count();
+        assert_eq!(changed.len(), expected);
+    }
+
+    #[test]
+    fn should_only_randomize_cells_when_asked() {
+        let mut session = Session::new(ExampleGuiModel);
+        session.setup(POINTS, Some(1), None, false).unwrap();
+        let cells = session.simulation().state.cells.clone();
+        session.setup(POINTS, Some(1), None, true).unwrap();
+        assert_ne!(session.simulation().state.cells, cells);
+        session.step().unwrap();
+        assert_eq!(session.simulation().iteration(), 1);
+    }
+
+    #[test]
+    fn should_run_a_number_of_iterations_and_reset() {
+        let mut session = demo_session();
+        session.run(Some(2));
+        assert!(session.tick().is_some());
+        assert!(session.is_running());
+        assert!(session.tick().is_some());
+        assert!(!session.is_running());
+        assert!(session.tick().is_none());
+        assert_eq!(session.simulation().iteration(), 2);
+
+        let message = session.reset();
+        assert_eq!(message["iteration"], 0);
+        assert_eq!(message["model_state"]["iterations"], 0);
+    }
+
+    #[test]
+    fn should_pause_and_keep_the_state_when_a_process_panics() {
+        let mut session = demo_session();
+        session.simulation.cell_processes.push(CellProcess::new(
+            9,
+            Box::new(|_, _, _, _| panic!("bad process")),
+        ));
+        let cells = session.simulation().state.cells.clone();
+        session.run(None);
+        let message = session.tick().unwrap();
+        assert_eq!(message["type"], "error");
+        assert_eq!(message["error"], "Iteration failed: bad process");
+        assert!(!session.is_running());
+        assert_eq!(session.simulation().iteration(), 0);
+        assert_eq!(session.simulation().state.cells, cells);
+    }
+
+    #[test]
+    fn should_apply_actions_and_parameters() {
+        let mut session = demo_session();
+        let message = session.apply_action(1, "setpop150").unwrap();
+        assert_eq!(message["cells"][0]["population"], 150);
+        assert!(matches!(
+            session.apply_action(5, "setpop150"),
+            Err(SessionError::UnknownCell(5))
+        ));
+        assert!(matches!(
+            session.apply_action(0, "explode"),
+            Err(SessionError::Gui(_))
+        ));
+
+        let parameters = session.set_parameters(br#"{"growth_rate": 20}"#).unwrap();
+        assert_eq!(parameters["growth_rate"], 20.0);
+        assert!(matches!(
+            session.set_parameters(br#"{"growth_rate": 500}"#),
+            Err(SessionError::Parameter(_))
+        ));
+        session.reset();
+        assert_eq!(session.parameters()["growth_rate"], 20.0);
+    }
+}
@@ -0,0 +1,191 @@
+/// Derive Tests
+///
+/// Derives the model state traits on real structs and uses the generated
+/// python wrappers through an embedded interpreter.
+use cellular_automata::process_runner::cells::state::CellIndex;
+use cellular_automata::process_runner::cells::state::CellStateBase;
+use cellular_automata::process_runner::fields::FieldError;
+use cellular_automata::process_runner::fields::FieldKind;
+use cellular_automata::process_runner::fields::FieldValue;
+use cellular_automata::process_runner::fields::Introspect;
+use cellular_automata::py_interface::PyWrapperBase;
+use cellular_automata::CellState;
+use cellular_automata::GlobalState;
+use geo::point;
+use geo::Point;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3::types::PyModule;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, CellState)]
+#[cell_state(randomize = "double_population", module = "derive_model")]
+pub struct LandCell {
+    pub id: CellIndex,
+    pub position: Point<f64>,
+    pub population: u32,
+    pub name: String,
+    #[cell_state(skip)]
+    pub cache: Vec<f64>,
+}
+
+fn double_population(cell: &LandCell) -> LandCell {
+    LandCell {
+        population: cell.population * 2,
+        ..cell.clone()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, GlobalState)]
+#[global_state(hash, module = "derive_model")]
+pub struct Climate {
+    pub rainfall: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, GlobalState)]
+pub struct Weather {
+    pub temperature: f64,
+}
+
+/// Run python code with the wrapper classes in scope
+///
+/// The classes are added to a `derive_model` module so that pickle can find them.
+fn run_python(code: &str) {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    let module = PyModule::new(py, "derive_model").unwrap();
+    module.add_class::<LandCellPy>().unwrap();
+    module.add_class::<ClimatePy>().unwrap();
+    module.add_class::<WeatherPy>().unwrap();
+    py.import("sys")
+        .and_then(|sys| sys.getattr("modules"))
+        .and_then(|modules| modules.set_item("derive_model", module))
+        .unwrap();
+    let locals = PyDict::new(py);
+    locals.set_item("derive_model", module).unwrap();
+    let code = format!("from derive_model import *\n{}", code);
+    if let Err(err) = py.run(&code, None, Some(locals)) {
+        err.print(py);
+        panic!("Python code failed");
+    }
+}
+
+#[test]
+fn should_implement_the_state_traits() {
+    let cell = LandCell {
+        id: CellIndex(3),
+        position: point!(x: 1.0, y: 2.0),
+        population: 10,
+        name: "north".to_owned(),
+        cache: vec![1.0],
+    };
+    assert_eq!(cell.id(), CellIndex(3));
+    assert_eq!(cell.position(), point!(x: 1.0, y: 2.0));
+    assert_eq!(cell.randomize().population, 20);
+
+    let wrapper = LandCellPy::from_inner(&cell);
+    assert_eq!(wrapper.get_inner(), cell);
+    assert_eq!(ClimatePy::default().get_inner(), Climate::default());
+    let created = LandCellPy::new(3, (1.0, 2.0), 10, "north".to_owned()).get_inner();
+    assert!(created.cache.is_empty());
+}
+
+#[test]
+fn should_describe_primitive_fields() {
+    let mut cell = LandCell {
+        id: CellIndex(3),
+        position: point!(x: 1.0, y: 2.0),
+        population: 10,
+        name: "north".to_owned(),
+        cache: vec![1.0],
+    };
+    let fields = cell.describe_fields();
+    let kinds: Vec<(&str, &FieldKind)> =
+        fields.iter().map(|f| (f.name.as_str(), &f.kind)).collect();
+    assert_eq!(
+        kinds,
+        vec![("population", &FieldKind::Int), ("name", &FieldKind::Text)]
+    );
+    cell.set_field_value("population", FieldValue::Float(2.6))
+        .unwrap();
+    cell.set_numeric_field_value("population", -4.0).unwrap();
+    assert_eq!(cell.field_value("population"), Some(FieldValue::Int(0)));
+    assert_eq!(cell.field_value("cache"), None);
+    assert!(matches!(
+        cell.set_field_value("name", FieldValue::Int(1)),
+        Err(FieldError::InvalidType { .. })
+    ));
+    assert_eq!(
+        cell.set_field_value("id", FieldValue::Int(1)),
+        Err(FieldError::Unknown("id".to_owned()))
+    );
+
+    let mut weather = Weather::default();
+    weather
+        .set_field_value("temperature", FieldValue::Int(3))
+        .unwrap();
+    assert_eq!(weather.numeric_field_value("temperature"), Some(3.0));
+}
+
+#[test]
+fn should_expose_fields_to_python() {
+    run_python(
+        r#"
+cell = LandCellPy(3, (1.0, 2.0), 10, "north")
+assert cell.id == 3
+assert cell.position == (1.0, 2.0)
+cell.population = 12
+assert cell.population == 12
+assert repr(cell) == "LandCellPy(id=3, position=(1.0, 2.0), population=12, name=\"north\")"
+try:
+    cell.population = "many"
+    raise AssertionError("Expected a TypeError")
+except TypeError:
+    pass
+"#,
+    );
+}
+
+#[test]
+fn should_compare_and_hash_in_python() {
+    run_python(
+        r#"
+cell = LandCellPy(3, (0.0, 2.0), 10, "north")
+other = LandCellPy(3, (-0.0, 2.0), 10, "north")
+assert cell == other
+assert hash(cell) == hash(other)
+other.population = 11
+assert cell != other
+assert hash(cell) != hash(other)
+try:
+    cell < other
+    raise AssertionError("Expected a TypeError")
+except TypeError:
+    pass
+
+assert ClimatePy(5) == ClimatePy(5)
+assert hash(ClimatePy(5)) == hash(ClimatePy(5))
+assert WeatherPy(0.0) == WeatherPy(-0.0)
+assert hash(WeatherPy(0.0)) == hash(WeatherPy(-0.0))
+"#,
+    );
+}
+
+#[test]
+fn should_copy_and_pickle_in_python() {
+    run_python(
+        r#"
+import copy
+cell = LandCellPy(3, (1.0, 2.0), 10, "north")
+assert copy.copy(cell) == cell
+assert copy.deepcopy(cell) == cell
+
+import pickle
+restored = pickle.loads(pickle.dumps(cell))
+assert restored == cell
+assert repr(restored) == repr(cell)
+assert pickle.loads(pickle.dumps(ClimatePy(5))) == ClimatePy(5)
+"#,
+    );
+}